@@ -1,11 +1,13 @@
+use std::sync::Arc;
+
 use axum::{
     handler::HandlerWithoutStateExt,
     http::StatusCode,
-    middleware,
     routing::{get, post},
     Router,
 };
-use saleor_app_sdk::middleware::verify_webhook_signature::webhook_signature_verifier;
+use saleor_app_sdk::middleware::verify_webhook_signature::WebhookSignatureLayer;
+use saleor_app_sdk::{apl::APL, middleware::webhook_router::WebhookRouter};
 use tower_http::services::ServeDir;
 
 use crate::app::AppState;
@@ -15,9 +17,12 @@ pub mod register;
 pub mod webhooks;
 use manifest::manifest;
 use register::register;
-use webhooks::webhooks;
 
-pub fn create_routes(state: AppState) -> Router {
+pub fn create_routes(
+    state: AppState,
+    apl: Arc<dyn APL>,
+    webhooks: WebhookRouter<AppState>,
+) -> Router {
     async fn handle_404() -> (StatusCode, &'static str) {
         (StatusCode::NOT_FOUND, "Not found")
     }
@@ -25,9 +30,13 @@ pub fn create_routes(state: AppState) -> Router {
     let serve_dir = ServeDir::new("./public").not_found_service(service);
 
     Router::new()
-        .layer(middleware::from_fn(webhook_signature_verifier))
+        .route(
+            "/api/webhooks",
+            webhooks
+                .into_method_router()
+                .layer(WebhookSignatureLayer::new(apl)),
+        )
         //handles just path, eg. localhost:3000/
-        .route("/api/webhooks", post(webhooks))
         .route(
             "/",
             get(|| async { "Your app got installed successfully!" }),
@@ -1,40 +1,21 @@
-use anyhow::Context;
 use axum::{
     extract::Json,
     extract::State,
     http::{HeaderMap, StatusCode},
 };
-use saleor_app_sdk::{AuthData, AuthToken};
-use tracing::{debug, info};
+use saleor_app_sdk::{
+    middleware::register::{register_app, RegisterError},
+    AuthToken,
+};
 
-use crate::app::{AppError, AppState};
+use crate::app::AppState;
 
 pub async fn register(
     headers: HeaderMap,
     State(state): State<AppState>,
     Json(auth_token): Json<AuthToken>,
-) -> Result<StatusCode, AppError> {
-    debug!(
-        "/api/register:\nsaleor_api_url:{:?}\nauth_token:{:?}",
-        headers.get("saleor-api-url"),
-        auth_token
-    );
-
-    if auth_token.auth_token.is_empty() {
-        return Err(anyhow::anyhow!("missing auth_token").into());
-    }
+) -> Result<StatusCode, RegisterError> {
     let app = state.saleor_app.lock().await;
-    let saleor_api_url = headers.get("saleor-api-url").context("missing api field")?;
-    let saleor_api_url = saleor_api_url.to_str()?.to_owned();
-    let auth_data = AuthData {
-        jwks: None,
-        token: auth_token.auth_token,
-        domain: Some(state.config.app_api_base_url),
-        app_id: state.manifest.id,
-        saleor_api_url: saleor_api_url.clone(),
-    };
-    app.apl.set(auth_data).await?;
-
-    info!("registered app for{:?}", &saleor_api_url);
+    register_app(app.apl.as_ref(), &state.config, &headers, auth_token).await?;
     Ok(StatusCode::OK)
 }
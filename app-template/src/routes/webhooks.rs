@@ -1,64 +1,53 @@
-use anyhow::Context;
-use axum::{
-    extract::{Json, State},
-    http::{HeaderMap, StatusCode},
-};
-use cynic::{http::SurfExt, MutationBuilder};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use cynic::{http::SurfExt, MutationBuilder, OperationBuilder};
 use saleor_app_sdk::{
-    headers::SALEOR_API_URL_HEADER,
-    webhooks::{
-        utils::{get_webhook_event_type, EitherWebhookType},
-        AsyncWebhookEventType,
-    },
+    apl::APL,
+    middleware::webhook_router::{Webhook, WebhookRouter},
+    webhooks::AsyncWebhookEventType,
+    AuthData,
 };
 use tracing::{debug, info};
 
 use crate::{
     app::{AppError, AppState},
     queries::{
-        event_products_updated::ProductUpdated,
+        event_products_updated::{ProductUpdated, QueryProductsChanged},
         product_metadata_update::{
             MetadataInput, UpdateProductMetadata, UpdateProductMetadataVariables,
         },
     },
 };
 
-pub async fn webhooks(
-    headers: HeaderMap,
-    State(state): State<AppState>,
-    //Will try to convert req body to ProductUpdated type, else returns 400
-    Json(product): Json<ProductUpdated>,
+/// Handlers for every event the app subscribes to, the app manifest webhooks are derived from it
+pub fn webhooks(apl: Arc<dyn APL>) -> WebhookRouter<AppState> {
+    WebhookRouter::new(apl)
+        .subscription(
+            &OperationBuilder::<QueryProductsChanged>::subscription()
+                .build()
+                .expect("subscription query is valid")
+                .query,
+        )
+        .on_async(AsyncWebhookEventType::ProductUpdated, product_changed)
+        .on_async(AsyncWebhookEventType::ProductCreated, product_changed)
+        .on_async(AsyncWebhookEventType::ProductDeleted, product_changed)
+}
+
+async fn product_changed(
+    _state: AppState,
+    webhook: Webhook<ProductUpdated>,
 ) -> Result<StatusCode, AppError> {
     debug!("/api/webhooks");
-    debug!("req: {:?}", product);
-    debug!("headers: {:?}", headers);
+    debug!("req: {:?}", webhook.payload);
 
-    let url = headers
-        .get(SALEOR_API_URL_HEADER)
-        .context("missing saleor api url header")?;
-    let event_type = get_webhook_event_type(&headers)?;
-    // In this case I disagree with this hint
-    #[allow(clippy::collapsible_match)]
-    if let EitherWebhookType::Async(a) = event_type {
-        match a {
-            AsyncWebhookEventType::ProductUpdated
-            | AsyncWebhookEventType::ProductCreated
-            | AsyncWebhookEventType::ProductDeleted => {
-                update_product(product, url.to_str()?, state).await?
-            }
-            _ => (),
-        }
-    }
+    update_product(webhook.payload, &webhook.auth_data).await?;
 
     info!("got webhooks!");
     Ok(StatusCode::OK)
 }
 
-async fn update_product(
-    product: ProductUpdated,
-    saleor_api_url: &str,
-    state: AppState,
-) -> anyhow::Result<()> {
+async fn update_product(product: ProductUpdated, auth_data: &AuthData) -> anyhow::Result<()> {
     debug!("Product got changed!");
     if let Some(product) = product.product {
         let operation = UpdateProductMetadata::build(UpdateProductMetadataVariables {
@@ -68,9 +57,7 @@ async fn update_product(
                 value: "hiiiihii",
             }]),
         });
-        let saleor_app = state.saleor_app.lock().await;
-        let auth_data = saleor_app.apl.get(saleor_api_url).await?;
-        let result = surf::post(saleor_api_url)
+        let result = surf::post(&auth_data.saleor_api_url)
             .header("Authorization", format!("bearer {}", auth_data.token))
             .run_graphql(operation)
             .await;
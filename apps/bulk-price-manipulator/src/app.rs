@@ -5,7 +5,12 @@ use axum::{
 use std::sync::Arc;
 use tracing_subscriber::EnvFilter;
 
-use saleor_app_sdk::{config::Config, manifest::AppManifest, SaleorApp};
+use saleor_app_sdk::{
+    config::Config,
+    manifest::AppManifest,
+    settings_manager::{channel_config::ChannelConfig, instance_config::InstanceConfigs},
+    SaleorApp,
+};
 use serde::{Deserialize, Serialize};
 use tracing::level_filters::LevelFilter;
 
@@ -56,18 +61,27 @@ pub fn trace_to_std(config: &Config) -> anyhow::Result<()> {
 pub struct AppState {
     pub saleor_app: Arc<tokio::sync::Mutex<SaleorApp>>,
     pub config: Config,
-    pub target_channel: String,
     pub manifest: AppManifest,
-    pub manipulator: ManipulatorConfig,
+    pub manipulator: InstanceConfigs<ManipulatorConfig>,
+    /// What new installations start with, see [`ManipulatorConfig::load`]
+    pub default_manipulator: ManipulatorConfig,
 }
 
+/// Settings of a single saleor instance, stored in its app metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ManipulatorConfig {
     pub price_expression: String,
     pub cost_price_expression: Option<String>,
+    /// Products get queried and their prices updated in this channel
+    pub channel_slug: String,
+}
+
+impl ChannelConfig for ManipulatorConfig {
+    const NAME: &'static str = "manipulator";
 }
 
 impl ManipulatorConfig {
+    /// Loads the defaults new installations start with, later changes go to the app metadata
     pub fn load() -> Result<Self, envy::Error> {
         _ = dotenvy::dotenv();
         envy::from_env::<ManipulatorConfig>()
@@ -16,6 +16,7 @@ use axum::Router;
 use saleor_app_sdk::{
     config::Config,
     manifest::{cargo_info, AppManifestBuilder, AppPermission},
+    settings_manager::instance_config::InstanceConfigs,
     SaleorApp,
 };
 use std::sync::Arc;
@@ -68,15 +69,10 @@ async fn create_app(config: &Config, manipulator_config: ManipulatorConfig) -> R
     debug!("Created AppManifest...");
 
     let app_state = AppState {
-        manipulator: manipulator_config,
+        manipulator: InstanceConfigs::new(),
+        default_manipulator: manipulator_config,
         manifest: app_manifest,
         config: config.clone(),
-        target_channel: match dotenvy::var("CHANNEL_SLUG") {
-            Ok(v) => v,
-            Err(_) => {
-                panic!("Missing channel slug. Slug is needed for price obtainment.");
-            }
-        },
         saleor_app: Arc::new(Mutex::new(saleor_app)),
     };
     debug!("Created AppState...");
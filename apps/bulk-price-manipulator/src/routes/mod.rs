@@ -4,12 +4,6 @@ use axum::{
     routing::{get, post},
     Router,
 };
-
-#[cfg(not(debug_assertions))]
-use axum::middleware;
-#[cfg(not(debug_assertions))]
-use saleor_app_sdk::middleware::verify_webhook_signature::webhook_signature_verifier;
-
 use tower_http::services::ServeDir;
 
 use crate::app::AppState;
@@ -32,12 +26,7 @@ pub fn create_routes(state: AppState) -> Router {
     // dev-only workaround
     #[cfg(debug_assertions)]
     let serve_dir = ServeDir::new("./bulk-price-manipulator/public").not_found_service(service);
-    let r = Router::new();
-
-    #[cfg(not(debug_assertions))]
-    let r = r.layer(middleware::from_fn(webhook_signature_verifier));
-
-    r
+    Router::new()
         //handles just path, eg. localhost:3000/
         .route(
             "/",
@@ -5,7 +5,7 @@ use rust_decimal::{prelude::FromPrimitive, Decimal};
 use tracing::{debug, error, warn};
 
 use crate::{
-    app::AppState,
+    app::{AppState, ManipulatorConfig},
     queries::{
         get_all_products::{
             GetProductsNext, GetProductsNextVariables, Jsonstring, Product, ProductTypeKindEnum,
@@ -16,14 +16,18 @@ use crate::{
     },
 };
 
-pub async fn update_prices(state: AppState, saleor_api_url: String) -> anyhow::Result<()> {
+pub async fn update_prices(
+    state: AppState,
+    saleor_api_url: String,
+    manipulator: ManipulatorConfig,
+) -> anyhow::Result<()> {
     debug!("fetching all products");
     let app = state.saleor_app.lock().await;
     let auth_data = app.apl.get(&saleor_api_url).await?;
     let mut products =
-        get_all_products(&saleor_api_url, &state.target_channel, &auth_data.token).await?;
+        get_all_products(&saleor_api_url, &manipulator.channel_slug, &auth_data.token).await?;
     let channel_id =
-        get_channel_id(&saleor_api_url, &auth_data.token, &state.target_channel).await?;
+        get_channel_id(&saleor_api_url, &auth_data.token, &manipulator.channel_slug).await?;
     debug!("found {} products", products.len(),);
     // dbg!(&products);
     products.reverse();
@@ -35,12 +39,11 @@ pub async fn update_prices(state: AppState, saleor_api_url: String) -> anyhow::R
                 // dbg!(&create_context_map(variant.clone()).unwrap());
                 match (
                     eval_float_with_context(
-                        &state.manipulator.price_expression,
+                        &manipulator.price_expression,
                         &create_context_map(variant.clone(), channel_id.inner()).unwrap(),
                     ),
                     eval_float_with_context(
-                        &state
-                            .manipulator
+                        &manipulator
                             .cost_price_expression
                             .clone()
                             .unwrap_or("".into()),
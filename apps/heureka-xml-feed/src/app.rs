@@ -199,15 +199,20 @@ pub struct AppState {
     #[cfg(feature = "ssr")]
     pub saleor_app: std::sync::Arc<tokio::sync::Mutex<saleor_app_sdk::SaleorApp>>,
     #[cfg(feature = "ssr")]
-    pub settings: AppSettings,
+    pub allowed_host: String,
+    #[cfg(feature = "ssr")]
+    pub settings: saleor_app_sdk::settings_manager::instance_config::InstanceConfigs<AppSettings>,
+    /// What new installations start with, see [`AppSettings::load`]
+    #[cfg(feature = "ssr")]
+    pub default_settings: AppSettings,
     #[cfg(feature = "ssr")]
     pub db_handle: Surreal<Any>,
 }
 
+/// Settings of a single saleor instance, stored in its app metadata
 #[cfg(feature = "ssr")]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
-    pub allowed_host: String,
     pub variant_url_template: String,
     //eg. 23%
     pub tax_rate: String,
@@ -226,8 +231,14 @@ pub enum ShippingPriceCod {
     NotShipping,
 }
 
+#[cfg(feature = "ssr")]
+impl saleor_app_sdk::settings_manager::channel_config::ChannelConfig for AppSettings {
+    const NAME: &'static str = "heureka";
+}
+
 #[cfg(feature = "ssr")]
 impl AppSettings {
+    /// Loads the defaults new installations start with, later changes go to the app metadata
     pub fn load() -> Result<Self, envy::Error> {
         _ = dotenvy::dotenv();
         envy::from_env::<AppSettings>()
@@ -7,6 +7,10 @@ use http::header::ToStrError;
 use http::status::StatusCode;
 use leptos::prelude::*;
 use saleor_app_sdk::apl::AplError;
+#[cfg(feature = "ssr")]
+use saleor_app_sdk::middleware::register::RegisterError;
+#[cfg(feature = "ssr")]
+use saleor_app_sdk::settings_manager::instance_config::InstanceConfigError;
 use thiserror::Error;
 #[cfg(feature = "ssr")]
 use tokio::sync::mpsc::error::SendError;
@@ -28,6 +32,10 @@ pub enum AxumError {
     InternalServerError(String),
     #[error("Internal server error with APL, `{0}`")]
     AplError(#[from] AplError),
+    #[error("Failed registering app, `{0}`")]
+    RegisterError(#[from] RegisterError),
+    #[error("Failed getting app settings, `{0}`")]
+    SettingsError(#[from] InstanceConfigError),
     #[error("Failed sending task to task handler, `{0}`")]
     SendError(#[from] SendError<event_handler::Event>),
 }
@@ -37,6 +45,9 @@ pub enum AxumError {
 impl IntoResponse for AxumError {
     fn into_response(self) -> Response {
         tracing::error!("{:?}", self);
+        if let AxumError::RegisterError(e) = self {
+            return e.into_response();
+        }
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Something went wrong: {:?}", self),
@@ -37,6 +37,8 @@ async fn main() -> Result<(), std::io::Error> {
         SaleorApp, cargo_info,
         config::Config,
         manifest::{AppManifestBuilder, AppPermission},
+        middleware::webhook_router::EMPTY_SUBSCRIPTION,
+        settings_manager::instance_config::InstanceConfigs,
     };
     use server::event_handler::EventHandler;
     use std::sync::Arc;
@@ -88,6 +90,12 @@ async fn main() -> Result<(), std::io::Error> {
                 ])
                 .build(),
         )
+        .add_webhook(
+            WebhookManifestBuilder::new(&config)
+                .set_query(EMPTY_SUBSCRIPTION)
+                .add_async_events(vec![AsyncWebhookEventType::AppUpdated])
+                .build(),
+        )
         .build()
         .expect("Failed building app manifest, contact app support plz");
 
@@ -125,10 +133,12 @@ async fn main() -> Result<(), std::io::Error> {
         config: config.clone(),
         saleor_app: Arc::new(Mutex::new(saleor_app)),
         leptos_options: leptos_options.clone(),
-        settings: AppSettings::load().expect("Failed getting app settings from env"),
+        allowed_host: dotenvy::var("ALLOWED_HOST").expect("Missing ALLOWED_HOST in env"),
+        settings: InstanceConfigs::new(),
+        default_settings: AppSettings::load().expect("Failed getting app settings from env"),
     };
 
-    EventHandler::start(app_state.settings.clone(), receiver, db_handle);
+    EventHandler::start(app_state.clone(), receiver, db_handle);
 
     let state_1 = app_state.clone();
     let app = Router::new()
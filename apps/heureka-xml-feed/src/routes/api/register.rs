@@ -1,11 +1,10 @@
-use anyhow::Context;
 use axum::{
     extract::Json,
     extract::State,
     http::{HeaderMap, StatusCode},
 };
-use saleor_app_sdk::{AuthData, AuthToken};
-use tracing::{debug, error, info};
+use saleor_app_sdk::{AuthToken, middleware::register::register_app};
+use tracing::info;
 
 use crate::{
     app::AppState,
@@ -18,40 +17,24 @@ pub async fn register(
     State(state): State<AppState>,
     Json(auth_token): Json<AuthToken>,
 ) -> Result<StatusCode, AxumError> {
-    debug!(
-        "/api/register:\nsaleor_api_url:{:?}\nauth_token:{:?}",
-        headers.get("saleor-api-url"),
-        auth_token
-    );
-
-    if auth_token.auth_token.is_empty() {
-        return Err(anyhow::anyhow!("missing auth_token").into());
-    }
     let app = state.saleor_app.lock().await;
-    let saleor_api_url = headers.get("saleor-api-url").context("missing api field")?;
-    let saleor_api_url = saleor_api_url.to_str()?.to_owned();
-    let auth_data = AuthData {
-        jwks: None,
-        token: auth_token.auth_token,
-        domain: Some(state.clone().config.app_api_base_url),
-        app_id: state.clone().manifest.id,
-        saleor_api_url: saleor_api_url.clone(),
-    };
-    if let Err(e) = app.apl.set(auth_data).await {
-        error!("{:?}", e);
-        return Err(e.into());
-    };
+    let auth_data = register_app(app.apl.as_ref(), &state.config, &headers, auth_token).await?;
+
+    //Reinstalls keep the settings they had, new installations start with the ones from env
+    state
+        .settings
+        .init(&auth_data, state.default_settings.clone())
+        .await?;
 
     info!("starting regeneration of db");
 
     state
         .task_queue_sender
         .send(Event::Regenerate(RegenerateEvent {
-            saleor_api_url: saleor_api_url.clone(),
+            saleor_api_url: auth_data.saleor_api_url,
             state: state.clone(),
         }))
         .await?;
 
-    info!("registered app for {:?}", &saleor_api_url);
     Ok(StatusCode::OK)
 }
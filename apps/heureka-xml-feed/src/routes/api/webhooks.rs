@@ -38,7 +38,7 @@ pub async fn webhooks(
         .ok_or(AxumError::MissingHeader(SALEOR_API_URL_HEADER.to_owned()))?
         .to_str()?
         .to_owned();
-    if url != state.settings.allowed_host {
+    if url != state.allowed_host {
         debug!("webhook didn't come from allowed host");
         return Ok(StatusCode::METHOD_NOT_ALLOWED);
     }
@@ -132,6 +132,10 @@ pub async fn webhooks(
                     .send(Event::ShippingZoneDeleted(shipping_zone))
                     .await?;
             }
+            // apps metadata changed, settings get refetched on next use
+            AsyncWebhookEventType::AppUpdated => {
+                state.settings.remove(&url);
+            }
             _ => (),
         }
     }
@@ -22,7 +22,7 @@ use crate::{
 };
 use cynic::GraphQlError;
 use heureka_xml_feed::{Shop, ShopItem};
-use saleor_app_sdk::apl::AplError;
+use saleor_app_sdk::{apl::AplError, settings_manager::instance_config::InstanceConfigError};
 use surrealdb::{Surreal, engine::any::Any};
 use tokio::{
     sync::mpsc::{Receiver, Sender},
@@ -34,6 +34,8 @@ use super::TryIntoShopItemError;
 
 pub struct EventHandler {
     receiver: Receiver<Event>,
+    state: AppState,
+    /// Settings of `state.allowed_host`, refreshed before every event
     settings: AppSettings,
     db_handle: Surreal<Any>,
 }
@@ -71,20 +73,42 @@ pub struct RegenerateEvent {
 
 impl EventHandler {
     pub fn start(
-        settings: AppSettings,
+        state: AppState,
         receiver: Receiver<Event>,
         db_handle: Surreal<Any>,
     ) -> JoinHandle<()> {
         let s = Self {
-            settings,
+            settings: state.default_settings.clone(),
+            state,
             receiver,
             db_handle,
         };
         tokio::spawn(s.listen())
     }
 
+    async fn refresh_settings(&mut self) -> Result<(), EventHandlerError> {
+        let auth_data = self
+            .state
+            .saleor_app
+            .lock()
+            .await
+            .apl
+            .get(&self.state.allowed_host)
+            .await?;
+        self.settings = self
+            .state
+            .settings
+            .get_or_init(&auth_data, &self.state.default_settings)
+            .await?;
+        Ok(())
+    }
+
     async fn listen(mut self) {
         while let Some(message) = self.receiver.recv().await {
+            if let Err(e) = self.refresh_settings().await {
+                error!("Failed getting app settings, skipping event. {:?}", e);
+                continue;
+            }
             // debug!("received Event: {:?}", &message);
             match message {
                 Event::ProductCreated(product_created) => {
@@ -192,6 +216,8 @@ pub enum EventHandlerError {
     GraphQl(#[from] GraphQlError),
     #[error("Error fetching APL token, {0}")]
     Apl(#[from] AplError),
+    #[error("Error fetching app settings, {0}")]
+    Settings(#[from] InstanceConfigError),
     #[error("Error during graphql operation, {0}")]
     Surf(surf::Error),
     #[error("Failed surrealdb query: {0}")]
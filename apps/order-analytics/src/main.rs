@@ -14,7 +14,6 @@ use saleor_app_sdk::{
     cargo_info,
     config::Config,
     manifest::{AppManifestBuilder, AppPermission},
-    webhooks::{AsyncWebhookEventType, WebhookManifestBuilder},
     SaleorApp,
 };
 use std::sync::Arc;
@@ -22,7 +21,7 @@ use tokio::sync::Mutex;
 
 use crate::{
     app::{trace_to_std, AppState},
-    routes::create_routes,
+    routes::{create_routes, webhooks::webhooks},
 };
 
 #[tokio::main]
@@ -32,48 +31,10 @@ async fn main() -> anyhow::Result<()> {
 
     let saleor_app = SaleorApp::new(&config)?;
 
+    let apl = saleor_app.apl.clone();
+    let webhooks = webhooks(apl.clone());
     let app_manifest = AppManifestBuilder::new(&config, cargo_info!())
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(
-                    r#"
-                    subscription QueryProductsChanged {
-                      event {
-                        ... on ProductUpdated {
-                          product {
-                            ... BaseProduct
-                          }
-                        }
-                        ... on ProductCreated {
-                          product {
-                            ... BaseProduct
-                          }
-                        }
-                        ... on ProductDeleted {
-                          product {
-                            ... BaseProduct
-                          }
-                        }
-                      }
-                    }
-
-                    fragment BaseProduct on Product {
-                      id
-                      slug
-                      name
-                      category {
-                        slug
-                      }
-                    }
-                    "#,
-                )
-                .add_async_events(vec![
-                    AsyncWebhookEventType::ProductCreated,
-                    AsyncWebhookEventType::ProductUpdated,
-                    AsyncWebhookEventType::ProductDeleted,
-                ])
-                .build(),
-        )
+        .add_webhooks(webhooks.webhook_manifests(&config))
         .add_permission(AppPermission::ManageProducts)
         .build()
         .expect("Manifest has invalid parameters");
@@ -83,7 +44,7 @@ async fn main() -> anyhow::Result<()> {
         config: config.clone(),
         saleor_app: Arc::new(Mutex::new(saleor_app)),
     };
-    let app = create_routes(app_state);
+    let app = create_routes(app_state, apl, webhooks);
 
     let listener = tokio::net::TcpListener::bind(
         "0.0.0.0:".to_owned()
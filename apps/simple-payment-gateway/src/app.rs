@@ -7,6 +7,7 @@ use iso_currency::Currency;
 use saleor_app_sdk::{
     config::Config,
     manifest::{AppManifest, LocaleCode},
+    settings_manager::{channel_config::ChannelConfig, instance_config::InstanceConfigs},
     SaleorApp,
 };
 use std::{str::FromStr, sync::Arc};
@@ -72,56 +73,85 @@ pub struct AppState {
     pub saleor_app: Arc<tokio::sync::Mutex<SaleorApp>>,
     pub config: Config,
     pub manifest: AppManifest,
-    pub active_payment_methods: Vec<ActivePaymentMethod>,
+    pub settings: InstanceConfigs<GatewaySettings>,
+    /// What new installations start with, see [`GatewaySettings::from_env`]
+    pub default_settings: GatewaySettings,
+}
+
+/// Settings of a single saleor instance, stored in its app metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GatewaySettings {
+    pub active_payment_methods: Vec<PaymentMethodType>,
+    pub locale: LocaleCode,
+    pub currencies: Vec<Currency>,
     pub cod_extra_price_as_product_slug: Option<String>,
 }
 
-pub fn get_active_payment_methods_from_env() -> anyhow::Result<Vec<ActivePaymentMethod>> {
-    _ = dotenvy::dotenv();
-    //eg: "accreditation,cod,other,transfer"
-    let env_methods = std::env::var("ACTIVE_PAYMENT_METHODS")?;
-    let locale = std::env::var("LOCALE")?;
-    let currencies = std::env::var("CURRENCIES")?;
-    let locale = LocaleCode::from_str(&locale)?;
-    let currencies = currencies.split(',').collect::<Vec<_>>();
-    let currencies = currencies
-        .iter()
-        .map(|c| Currency::from_str(c))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!(format!("{:?}", e)))?;
+impl ChannelConfig for GatewaySettings {
+    const NAME: &'static str = "gateway";
+}
 
-    let str_types: Vec<_> = env_methods.split(',').collect();
-    let payment_methods = str_types
-        .iter()
-        .flat_map(|s| all::<PaymentMethodType>().map(move |g| (s, g)))
-        .filter_map(|(s, g)| match format!("{:?}", g).to_lowercase() == *s {
-            true => Some(g),
-            false => None,
-        })
-        .map(|g| ActivePaymentMethod {
-            typ: g,
-            name: match (g, &locale) {
-                (PaymentMethodType::COD, LocaleCode::Sk) => "Dobierka".to_owned(),
-                (PaymentMethodType::Cash, LocaleCode::Sk) => "Hotovosť".to_owned(),
-                (PaymentMethodType::Transfer, LocaleCode::Sk) => "Bankový prevod".to_owned(),
-                (PaymentMethodType::Inkaso, LocaleCode::Sk) => "Inkaso".to_owned(),
-                (PaymentMethodType::Accreditation, LocaleCode::Sk) => "Vzajomný zápočet".to_owned(),
-                (PaymentMethodType::Other, LocaleCode::Sk) => "Iné".to_owned(),
-                (PaymentMethodType::COD, LocaleCode::En) => "Cash on delivery".to_owned(),
-                (PaymentMethodType::Cash, LocaleCode::En) => "Cash".to_owned(),
-                (PaymentMethodType::Transfer, LocaleCode::En) => "Bank transfer".to_owned(),
-                (PaymentMethodType::Inkaso, LocaleCode::En) => "Encashment".to_owned(),
-                (PaymentMethodType::Accreditation, LocaleCode::En) => "Mutual credit".to_owned(),
-                (PaymentMethodType::Other, LocaleCode::En) => "Other".to_owned(),
-                (g, l) => unimplemented!("Gateway {:?} in locale {:?} not implemented", g, l),
-            },
+impl GatewaySettings {
+    /// Loads the defaults new installations start with, later changes go to the app metadata
+    pub fn from_env() -> anyhow::Result<Self> {
+        _ = dotenvy::dotenv();
+        //eg: "accreditation,cod,other,transfer"
+        let env_methods = std::env::var("ACTIVE_PAYMENT_METHODS")?;
+        let locale = std::env::var("LOCALE")?;
+        let currencies = std::env::var("CURRENCIES")?;
+        let locale = LocaleCode::from_str(&locale)?;
+        let currencies = currencies
+            .split(',')
+            .map(Currency::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!(format!("{:?}", e)))?;
+
+        let str_types: Vec<_> = env_methods.split(',').collect();
+        let active_payment_methods = str_types
+            .iter()
+            .flat_map(|s| all::<PaymentMethodType>().map(move |g| (s, g)))
+            .filter_map(|(s, g)| match format!("{:?}", g).to_lowercase() == *s {
+                true => Some(g),
+                false => None,
+            })
+            .collect::<Vec<_>>();
+        debug!(
+            "active gateway types:{:?}\ncurrencies:{:?}\nlocale:{:?}",
+            &active_payment_methods, &currencies, &locale
+        );
+        Ok(Self {
+            active_payment_methods,
+            locale,
+            currencies,
+            cod_extra_price_as_product_slug: std::env::var("COD_EXTRA_PRICE_AS_PRODUCT_SLUG").ok(),
         })
-        .collect::<Vec<_>>();
-    debug!(
-        "active gateway types:{:?}\ncurrencies:{:?}\nlocale:{:?}",
-        &payment_methods, &currencies, &locale
-    );
-    Ok(payment_methods)
+    }
+
+    /// Active payment methods, named in the instances locale, english for locales without translations
+    pub fn payment_methods(&self) -> Vec<ActivePaymentMethod> {
+        self.active_payment_methods
+            .iter()
+            .map(|&g| ActivePaymentMethod {
+                typ: g,
+                name: match (g, &self.locale) {
+                    (PaymentMethodType::COD, LocaleCode::Sk) => "Dobierka".to_owned(),
+                    (PaymentMethodType::Cash, LocaleCode::Sk) => "Hotovosť".to_owned(),
+                    (PaymentMethodType::Transfer, LocaleCode::Sk) => "Bankový prevod".to_owned(),
+                    (PaymentMethodType::Inkaso, LocaleCode::Sk) => "Inkaso".to_owned(),
+                    (PaymentMethodType::Accreditation, LocaleCode::Sk) => {
+                        "Vzajomný zápočet".to_owned()
+                    }
+                    (PaymentMethodType::Other, LocaleCode::Sk) => "Iné".to_owned(),
+                    (PaymentMethodType::COD, _) => "Cash on delivery".to_owned(),
+                    (PaymentMethodType::Cash, _) => "Cash".to_owned(),
+                    (PaymentMethodType::Transfer, _) => "Bank transfer".to_owned(),
+                    (PaymentMethodType::Inkaso, _) => "Encashment".to_owned(),
+                    (PaymentMethodType::Accreditation, _) => "Mutual credit".to_owned(),
+                    (PaymentMethodType::Other, _) => "Other".to_owned(),
+                },
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -14,20 +14,15 @@ use saleor_app_sdk::{
     cargo_info,
     config::Config,
     manifest::{AppManifestBuilder, AppPermission},
-    webhooks::{SyncWebhookEventType, WebhookManifestBuilder},
+    settings_manager::instance_config::InstanceConfigs,
     SaleorApp,
 };
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::{
-    app::{get_active_payment_methods_from_env, trace_to_std, AppState},
-    queries::event_transactions::{
-        sub_payment_gateway_initialize_session, sub_transaction_cancelation_requested,
-        sub_transaction_charge_requested, sub_transaction_initialize_session,
-        sub_transaction_process_session, sub_transaction_refund_requested,
-    },
-    routes::create_routes,
+    app::{trace_to_std, AppState, GatewaySettings},
+    routes::{create_routes, webhooks::webhooks},
 };
 
 #[tokio::main]
@@ -37,49 +32,10 @@ async fn main() -> anyhow::Result<()> {
 
     let saleor_app = SaleorApp::new(&config)?;
 
+    let apl = saleor_app.apl.clone();
+    let webhooks = webhooks(apl.clone());
     let app_manifest = AppManifestBuilder::new(&config, cargo_info!())
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(sub_transaction_process_session)
-                .add_sync_event(SyncWebhookEventType::TransactionProcessSession)
-                .build(),
-        )
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(sub_transaction_charge_requested)
-                .add_sync_event(SyncWebhookEventType::TransactionChargeRequested)
-                .build(),
-        )
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(sub_transaction_refund_requested)
-                .add_sync_event(SyncWebhookEventType::TransactionRefundRequested)
-                .build(),
-        )
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(sub_transaction_initialize_session)
-                .add_sync_event(SyncWebhookEventType::TransactionInitializeSession)
-                .build(),
-        )
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(sub_payment_gateway_initialize_session)
-                .add_sync_event(SyncWebhookEventType::PaymentGatewayInitializeSession)
-                .build(),
-        )
-        .add_webhook(
-            WebhookManifestBuilder::new(&config)
-                .set_query(sub_transaction_cancelation_requested)
-                .add_sync_event(SyncWebhookEventType::TransactionCancelationRequested)
-                .build(),
-        )
-        // .add_webhook(
-        //     WebhookManifestBuilder::new(&config)
-        //         .set_query(sub_list_payment_gateways)
-        //         .add_sync_event(SyncWebhookEventType::PaymentListGateways)
-        //         .build(),
-        // )
+        .add_webhooks(webhooks.webhook_manifests(&config))
         .add_permissions(vec![
             AppPermission::HandlePayments,
             AppPermission::ManageOrders,
@@ -90,13 +46,13 @@ async fn main() -> anyhow::Result<()> {
         .expect("Failed building app manifest, contact app support plz");
 
     let app_state = AppState {
-        active_payment_methods: get_active_payment_methods_from_env()?,
+        settings: InstanceConfigs::new(),
+        default_settings: GatewaySettings::from_env()?,
         manifest: app_manifest,
         config: config.clone(),
         saleor_app: Arc::new(Mutex::new(saleor_app)),
-        cod_extra_price_as_product_slug: std::env::var("COD_EXTRA_PRICE_AS_PRODUCT_SLUG").ok(),
     };
-    let app = create_routes(app_state);
+    let app = create_routes(app_state, apl, webhooks);
 
     let listener = tokio::net::TcpListener::bind(
         "0.0.0.0:".to_owned()
@@ -1,40 +1,35 @@
-use anyhow::Context;
 use axum::{
     extract::Json,
     extract::State,
     http::{HeaderMap, StatusCode},
 };
-use saleor_app_sdk::{AuthData, AuthToken};
-use tracing::{debug, info};
+use saleor_app_sdk::{
+    middleware::register::{register_app, RegisterError},
+    AuthToken,
+};
+use tracing::{error, info};
 
-use crate::app::{AppError, AppState};
+use crate::app::AppState;
 
 pub async fn register(
     headers: HeaderMap,
     State(state): State<AppState>,
     Json(auth_token): Json<AuthToken>,
-) -> Result<StatusCode, AppError> {
-    debug!(
-        "/api/register:\nsaleor_api_url:{:?}\nauth_token:{:?}",
-        headers.get("saleor-api-url"),
-        auth_token
-    );
-
-    if auth_token.auth_token.is_empty() {
-        return Err(anyhow::anyhow!("missing auth_token").into());
-    }
+) -> Result<StatusCode, RegisterError> {
     let app = state.saleor_app.lock().await;
-    let saleor_api_url = headers.get("saleor-api-url").context("missing api field")?;
-    let saleor_api_url = saleor_api_url.to_str()?.to_owned();
-    let auth_data = AuthData {
-        jwks: None,
-        token: auth_token.auth_token,
-        domain: Some(state.config.app_api_base_url.clone()),
-        app_id: state.manifest.id.clone(),
-        saleor_api_url: saleor_api_url.clone(),
-    };
-    app.apl.set(auth_data.clone()).await?;
-    info!("registered app for: {:?}", &saleor_api_url);
+    let auth_data = register_app(app.apl.as_ref(), &state.config, &headers, auth_token).await?;
 
+    //Reinstalls keep the settings they had, new installations start with the ones from env
+    match state
+        .settings
+        .init(&auth_data, state.default_settings.clone())
+        .await
+    {
+        Ok(settings) => info!("{} uses {:?}", &auth_data.saleor_api_url, settings),
+        Err(e) => error!(
+            "Failed storing settings of {}, {:?}",
+            &auth_data.saleor_api_url, e
+        ),
+    }
     Ok(StatusCode::OK)
 }
@@ -1,20 +1,24 @@
+use std::sync::Arc;
+
 use anyhow::Context;
-use axum::{extract::State, http::HeaderMap, Json};
+use axum::{http::StatusCode, Json};
 use rust_decimal::{prelude::FromPrimitive, Decimal};
-use saleor_app_sdk::webhooks::{
-    sync_response::{
-        CancelationRequestedResult, ChargeRequestedResult, PaymentGatewayInitializeSessionResponse,
-        RefundRequestedResult, TransactionCancelationRequestedResponse,
-        TransactionChargeRequestedResponse, TransactionInitializeSessionResponse,
-        TransactionProcessSessionResponse, TransactionRefundRequestedResponse,
-        TransactionSessionResult,
+use saleor_app_sdk::{
+    apl::APL,
+    middleware::webhook_router::{Webhook, WebhookRouter},
+    webhooks::{
+        sync_response::{
+            CancelationRequestedResult, ChargeRequestedResult,
+            PaymentGatewayInitializeSessionResponse, RefundRequestedResult,
+            TransactionCancelationRequestedResponse, TransactionChargeRequestedResponse,
+            TransactionInitializeSessionResponse, TransactionProcessSessionResponse,
+            TransactionRefundRequestedResponse, TransactionSessionResult,
+        },
+        AsyncWebhookEventType, SyncWebhookEventType,
     },
-    utils::{get_webhook_event_type, EitherWebhookType},
-    SyncWebhookEventType,
 };
 use serde_json::Value;
-use std::str::FromStr;
-use tracing::{debug, error, info};
+use tracing::{debug, error};
 
 use crate::{
     app::{
@@ -22,229 +26,262 @@ use crate::{
         TransactionInitializeSessionData,
     },
     queries::event_transactions::{
-        DeliveryMethod, OrderOrCheckout, PaymentGatewayInitializeSession2,
-        TransactionCancelationRequested2, TransactionChargeRequested2, TransactionFlowStrategyEnum,
-        TransactionInitializeSession2, TransactionProcessSession2, TransactionRefundRequested2,
+        sub_payment_gateway_initialize_session, sub_transaction_cancelation_requested,
+        sub_transaction_charge_requested, sub_transaction_initialize_session,
+        sub_transaction_process_session, sub_transaction_refund_requested, DeliveryMethod,
+        OrderOrCheckout, PaymentGatewayInitializeSession2, TransactionCancelationRequested2,
+        TransactionChargeRequested2, TransactionFlowStrategyEnum, TransactionInitializeSession2,
+        TransactionProcessSession2, TransactionRefundRequested2,
     },
 };
 
-pub async fn webhooks(
-    headers: HeaderMap,
-    State(state): State<AppState>,
-    body: String,
-) -> Result<Json<Value>, AppError> {
-    debug!("/api/webhooks");
-    debug!("req: {:?}", body);
-
-    // let saleor_api_url = headers
-    //     .get(SALEOR_API_URL_HEADER)
-    //     .context("missing saleor api url header")?
-    //     .to_str()?
-    //     .to_owned();
-    let event_type = get_webhook_event_type(&headers)?;
-
-    debug!("event: {:?}", event_type);
+/**
+ * Handlers for every event the app subscribes to, the app manifest webhooks are derived from it.
+ * `APP_UPDATED` drops the cached settings of the instance.
+ */
+pub fn webhooks(apl: Arc<dyn APL>) -> WebhookRouter<AppState> {
+    WebhookRouter::new(apl)
+        .on_async(AsyncWebhookEventType::AppUpdated, settings_changed)
+        .subscription(sub_transaction_process_session)
+        .on_sync(
+            SyncWebhookEventType::TransactionProcessSession,
+            transaction_process_session,
+        )
+        .subscription(sub_transaction_charge_requested)
+        .on_sync(
+            SyncWebhookEventType::TransactionChargeRequested,
+            transaction_charge_requested,
+        )
+        .subscription(sub_transaction_refund_requested)
+        .on_sync(
+            SyncWebhookEventType::TransactionRefundRequested,
+            transaction_refund_requested,
+        )
+        .subscription(sub_transaction_initialize_session)
+        .on_sync(
+            SyncWebhookEventType::TransactionInitializeSession,
+            transaction_initialize_session,
+        )
+        .subscription(sub_payment_gateway_initialize_session)
+        .on_sync(
+            SyncWebhookEventType::PaymentGatewayInitializeSession,
+            payment_gateway_initialize_session,
+        )
+        .subscription(sub_transaction_cancelation_requested)
+        .on_sync(
+            SyncWebhookEventType::TransactionCancelationRequested,
+            transaction_cancelation_requested,
+        )
+    // .subscription(sub_list_payment_gateways)
+    // .on_sync(SyncWebhookEventType::PaymentListGateways, list_payment_gateways)
+}
 
-    let res = match create_response(event_type, body, state).await {
-        Ok(r) => r,
-        Err(e) => {
-            error!("Response creation failed: {:?}", e);
-            return Err(AppError(anyhow::anyhow!(e)));
-        }
-    };
+// async fn list_payment_gateways(
+//     state: AppState,
+//     _webhook: Webhook<Value>,
+// ) -> Result<Json<Value>, AppError> {
+//     let gateways = state
+//         .active_gateways
+//         .iter()
+//         .cloned()
+//         .map(|g| g.gateway)
+//         .collect::<Vec<_>>();
+//     Ok(Json::from(serde_json::to_value(PaymentListGatewaysResponse(gateways))?))
+// }
 
-    debug!("res: {}", &res.to_string());
-    info!("got webhooks!");
-    Ok(res)
+/// Saleor sends `APP_UPDATED` when the apps metadata changes, so settings get refetched on next use
+async fn settings_changed(state: AppState, webhook: Webhook<Value>) -> StatusCode {
+    debug!("settings of {} changed", &webhook.auth_data.saleor_api_url);
+    state.settings.remove(&webhook.auth_data.saleor_api_url);
+    StatusCode::OK
 }
 
-async fn create_response(
-    event_type: EitherWebhookType,
-    body: String,
+async fn payment_gateway_initialize_session(
     state: AppState,
-) -> anyhow::Result<Json<Value>> {
-    Ok(match event_type {
-        EitherWebhookType::Sync(a) => match a {
-            // SyncWebhookEventType::PaymentListGateways => {
-            //     let gateways = state
-            //         .active_gateways
-            //         .iter()
-            //         .cloned()
-            //         .map(|g| g.gateway)
-            //         .collect::<Vec<_>>();
-            //     Json::from(serde_json::to_value(PaymentListGatewaysResponse(gateways))?)
-            // }
-            SyncWebhookEventType::PaymentGatewayInitializeSession => {
-                let session_data = serde_json::from_str::<PaymentGatewayInitializeSession2>(&body)?;
-                let mut filtered_payment_methods = state.active_payment_methods.clone();
+    webhook: Webhook<PaymentGatewayInitializeSession2>,
+) -> Result<Json<Value>, AppError> {
+    debug!("req: {:?}", webhook.payload);
+    let settings = state
+        .settings
+        .get_or_init(&webhook.auth_data, &state.default_settings)
+        .await?;
+    let mut filtered_payment_methods = settings.payment_methods();
 
-                //If obtainment method is via some sort of shipping, remove PaymentMethodType::Cash
-                //If obtainment method is collection in person at warehouse, remove PaymentMethodType::CODv
-                match session_data.source_object {
-                    OrderOrCheckout::Order(o) => {
-                        if o.shipping_method_name.is_some() {
-                            filtered_payment_methods.retain(|p| p.typ != PaymentMethodType::Cash)
-                        } else if o.collection_point_name.is_some() {
-                            filtered_payment_methods.retain(|p| p.typ != PaymentMethodType::COD)
-                        } else {
-                            error!("Order has neither shipping_method_name or collection_point_name, how is it being payed for?");
-                        }
+    //If obtainment method is via some sort of shipping, remove PaymentMethodType::Cash
+    //If obtainment method is collection in person at warehouse, remove PaymentMethodType::CODv
+    match webhook.payload.source_object {
+        OrderOrCheckout::Order(o) => {
+            if o.shipping_method_name.is_some() {
+                filtered_payment_methods.retain(|p| p.typ != PaymentMethodType::Cash)
+            } else if o.collection_point_name.is_some() {
+                filtered_payment_methods.retain(|p| p.typ != PaymentMethodType::COD)
+            } else {
+                error!("Order has neither shipping_method_name or collection_point_name, how is it being payed for?");
+            }
+        }
+        OrderOrCheckout::Checkout(c) => {
+            if let Some(d) = c.delivery_method {
+                match d {
+                    DeliveryMethod::Warehouse(_) => {
+                        filtered_payment_methods.retain(|p| p.typ != PaymentMethodType::COD);
                     }
-                    OrderOrCheckout::Checkout(c) => {
-                        if let Some(d) = c.delivery_method {
-                            match d {
-                                DeliveryMethod::Warehouse(_) => {
-                                    filtered_payment_methods
-                                        .retain(|p| p.typ != PaymentMethodType::COD);
-                                }
-                                DeliveryMethod::ShippingMethod(_) => {
-                                    filtered_payment_methods
-                                        .retain(|p| p.typ != PaymentMethodType::Cash);
-                                }
-                                DeliveryMethod::Unknown => {
-                                    error!("DeliveryMethod is neither");
-                                }
-                            }
-                        }
+                    DeliveryMethod::ShippingMethod(_) => {
+                        filtered_payment_methods.retain(|p| p.typ != PaymentMethodType::Cash);
                     }
-                    OrderOrCheckout::Unknown => {
-                        error!("OrderOrCheckout is neither");
+                    DeliveryMethod::Unknown => {
+                        error!("DeliveryMethod is neither");
                     }
                 }
-                let data = serde_json::to_value(PaymentGatewayInitializeSessionData {
-                    payment_methods: filtered_payment_methods,
-                })?;
-                Json::from(serde_json::to_value(
-                    PaymentGatewayInitializeSessionResponse::<Value> { data: Some(data) },
-                )?)
             }
-            SyncWebhookEventType::TransactionInitializeSession => {
-                let session_data = serde_json::from_str::<TransactionInitializeSession2>(&body)?;
+        }
+        OrderOrCheckout::Unknown => {
+            error!("OrderOrCheckout is neither");
+        }
+    }
+    let data = serde_json::to_value(PaymentGatewayInitializeSessionData {
+        payment_methods: filtered_payment_methods,
+    })?;
+    Ok(Json::from(serde_json::to_value(
+        PaymentGatewayInitializeSessionResponse::<Value> { data: Some(data) },
+    )?))
+}
 
-                debug!(
-                    "Transaction session initialised with '{:?}' payment method.",
-                    &session_data.data
-                );
-                let payment_method = session_data
-                    .data
-                    .context("Missing Payment Method in request")?
-                    .payment_method;
+async fn transaction_initialize_session(
+    state: AppState,
+    webhook: Webhook<TransactionInitializeSession2>,
+) -> Result<Json<Value>, AppError> {
+    let session_data = webhook.payload;
+    debug!(
+        "Transaction session initialised with '{:?}' payment method.",
+        &session_data.data
+    );
+    let payment_method = session_data
+        .data
+        .context("Missing Payment Method in request")?
+        .payment_method;
 
-                // let apl_token = state
-                //     .saleor_app
-                //     .lock()
-                //     .await
-                //     .apl
-                //     .get(saleor_api_url)
-                //     .await?
-                //     .token;
+    // if payment_method == PaymentMethodType::COD {
+    //     match session_data.source_object {
+    //         OrderOrCheckout::Order(o) => {
+    //             o.collection_point_name;
+    //         }
+    //         OrderOrCheckout::Checkout(c) => {
+    //             c.delivery_method;
+    //         }
+    //         _ => error!("session_data.source_object is neither Order or Checkout")
+    //     }
+    // }
 
-                // if payment_method == PaymentMethodType::COD {
-                //     match session_data.source_object {
-                //         OrderOrCheckout::Order(o) => {
-                //             o.collection_point_name;
-                //         }
-                //         OrderOrCheckout::Checkout(c) => {
-                //             c.delivery_method;
-                //         }
-                //         _ => error!("session_data.source_object is neither Order or Checkout")
-                //     }
-                // }
+    let str_payment_method =
+        serde_json::to_string(&TransactionInitializeSessionData { payment_method })?;
 
-                let str_payment_method =
-                    serde_json::to_string(&TransactionInitializeSessionData { payment_method })?;
+    // update_transaction_message(
+    //     session_data.transaction.id,
+    //     str_payment_method.clone(),
+    //     webhook.auth_data.token,
+    //     webhook.auth_data.saleor_api_url,
+    // );
 
-                // update_transaction_message(
-                //     session_data.transaction.id,
-                //     str_payment_method.clone(),
-                //     apl_token,
-                //     saleor_api_url.to_owned(),
-                // );
+    Ok(Json::from(serde_json::to_value(
+        TransactionInitializeSessionResponse::<u8> {
+            data: None,
+            time: None,
+            psp_reference: Some("New transaction from ".to_owned() + &state.manifest.name),
+            external_url: None,
+            message: Some(str_payment_method),
+            amount: Decimal::from_f32(session_data.action.amount.0)
+                .context("failed to convert f32 to dec")?,
+            result: match session_data.action.action_type {
+                TransactionFlowStrategyEnum::Charge => TransactionSessionResult::ChargeSuccess,
+                TransactionFlowStrategyEnum::Authorization => {
+                    TransactionSessionResult::AuthorizationSuccess
+                }
+            },
+        },
+    )?))
+}
 
-                Json::from(serde_json::to_value(
-                    TransactionInitializeSessionResponse::<u8> {
-                        data: None,
-                        time: None,
-                        psp_reference: Some(
-                            "New transaction from ".to_owned() + &state.manifest.name,
-                        ),
-                        external_url: None,
-                        message: Some(str_payment_method),
-                        amount: Decimal::from_f32(session_data.action.amount.0)
-                            .context("failed to convert f32 to dec")?,
-                        result: match session_data.action.action_type {
-                            TransactionFlowStrategyEnum::Charge => {
-                                TransactionSessionResult::ChargeSuccess
-                            }
-                            TransactionFlowStrategyEnum::Authorization => {
-                                TransactionSessionResult::AuthorizationSuccess
-                            }
-                        },
-                    },
-                )?)
-            }
-            SyncWebhookEventType::TransactionChargeRequested => {
-                let data = serde_json::from_str::<TransactionChargeRequested2>(&body)?;
-                Json::from(serde_json::to_value(TransactionChargeRequestedResponse {
-                    time: None,
-                    psp_reference: "".to_owned(),
-                    external_url: None,
-                    message: None,
-                    amount: data.action.amount.and_then(|a| Decimal::from_f32(a.0)),
-                    result: Some(ChargeRequestedResult::ChargeSuccess),
-                })?)
-            }
-            SyncWebhookEventType::TransactionRefundRequested => {
-                let data = serde_json::from_str::<TransactionRefundRequested2>(&body)?;
-                Json::from(serde_json::to_value(TransactionRefundRequestedResponse {
-                    time: None,
-                    psp_reference: "".to_owned(),
-                    external_url: None,
-                    message: None,
-                    amount: data.action.amount.and_then(|a| Decimal::from_f32(a.0)),
-                    result: Some(RefundRequestedResult::RefundSuccess),
-                })?)
-            }
+async fn transaction_charge_requested(
+    _state: AppState,
+    webhook: Webhook<TransactionChargeRequested2>,
+) -> Result<Json<Value>, AppError> {
+    Ok(Json::from(serde_json::to_value(
+        TransactionChargeRequestedResponse {
+            time: None,
+            psp_reference: "".to_owned(),
+            external_url: None,
+            message: None,
+            amount: webhook
+                .payload
+                .action
+                .amount
+                .and_then(|a| Decimal::from_f32(a.0)),
+            result: Some(ChargeRequestedResult::ChargeSuccess),
+        },
+    )?))
+}
 
-            SyncWebhookEventType::TransactionCancelationRequested => {
-                let data = serde_json::from_str::<TransactionCancelationRequested2>(&body)?;
-                Json::from(serde_json::to_value(
-                    TransactionCancelationRequestedResponse {
-                        time: None,
-                        psp_reference: "".to_owned(),
-                        external_url: None,
-                        message: None,
-                        amount: data.action.amount.and_then(|a| Decimal::from_f32(a.0)),
-                        result: Some(CancelationRequestedResult::CancelSuccess),
-                    },
-                )?)
-            }
-            SyncWebhookEventType::TransactionProcessSession => {
-                let data = serde_json::from_str::<TransactionProcessSession2>(&body)?;
-                Json::from(serde_json::to_value(TransactionProcessSessionResponse::<
-                    u8,
-                > {
-                    data: None,
-                    time: None,
-                    psp_reference: None,
-                    external_url: None,
-                    message: None,
-                    amount: Decimal::from_f32(data.action.amount.0)
-                        .context("failed f32 to Decimal")?,
-                    result: match data.action.action_type {
-                        TransactionFlowStrategyEnum::Charge => {
-                            TransactionSessionResult::ChargeSuccess
-                        }
-                        TransactionFlowStrategyEnum::Authorization => {
-                            TransactionSessionResult::AuthorizationSuccess
-                        }
-                    },
-                })?)
-            }
-            _ => Json::from(Value::from_str("")?),
+async fn transaction_refund_requested(
+    _state: AppState,
+    webhook: Webhook<TransactionRefundRequested2>,
+) -> Result<Json<Value>, AppError> {
+    Ok(Json::from(serde_json::to_value(
+        TransactionRefundRequestedResponse {
+            time: None,
+            psp_reference: "".to_owned(),
+            external_url: None,
+            message: None,
+            amount: webhook
+                .payload
+                .action
+                .amount
+                .and_then(|a| Decimal::from_f32(a.0)),
+            result: Some(RefundRequestedResult::RefundSuccess),
+        },
+    )?))
+}
+
+async fn transaction_cancelation_requested(
+    _state: AppState,
+    webhook: Webhook<TransactionCancelationRequested2>,
+) -> Result<Json<Value>, AppError> {
+    Ok(Json::from(serde_json::to_value(
+        TransactionCancelationRequestedResponse {
+            time: None,
+            psp_reference: "".to_owned(),
+            external_url: None,
+            message: None,
+            amount: webhook
+                .payload
+                .action
+                .amount
+                .and_then(|a| Decimal::from_f32(a.0)),
+            result: Some(CancelationRequestedResult::CancelSuccess),
+        },
+    )?))
+}
+
+async fn transaction_process_session(
+    _state: AppState,
+    webhook: Webhook<TransactionProcessSession2>,
+) -> Result<Json<Value>, AppError> {
+    let action = webhook.payload.action;
+    Ok(Json::from(serde_json::to_value(
+        TransactionProcessSessionResponse::<u8> {
+            data: None,
+            time: None,
+            psp_reference: None,
+            external_url: None,
+            message: None,
+            amount: Decimal::from_f32(action.amount.0).context("failed f32 to Decimal")?,
+            result: match action.action_type {
+                TransactionFlowStrategyEnum::Charge => TransactionSessionResult::ChargeSuccess,
+                TransactionFlowStrategyEnum::Authorization => {
+                    TransactionSessionResult::AuthorizationSuccess
+                }
+            },
         },
-        _ => Json::from(Value::from_str("")?),
-    })
+    )?))
 }
 
 // fn set_order_payment_method(
@@ -2,15 +2,21 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::mpsc::Sender;
 use tracing_subscriber::EnvFilter;
 
-use saleor_app_sdk::{config::Config, manifest::AppManifest, SaleorApp};
-use serde::{Deserialize, Serialize};
+use saleor_app_sdk::{
+    config::Config,
+    manifest::AppManifest,
+    settings_manager::{channel_config::ChannelConfig, instance_config::InstanceConfigs},
+    SaleorApp,
+};
+use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use tracing::level_filters::LevelFilter;
 
-use crate::sitemap::event_handler::Event;
+use crate::sitemap::{event_handler::QueuedEvent, ItemType};
 
 // Make our own error that wraps `anyhow::Error`.
 pub struct AppError(anyhow::Error);
@@ -52,23 +58,254 @@ pub fn trace_to_std(config: &Config) -> anyhow::Result<()> {
 }
 
 /**
- * Sitemaps have a limit of 10mb, so we create an index and split all paths between multiple
- * sitemaps.
+ * Sitemaps have a limit of 50 000 urls and 50MB, so we create an index and split all paths between
+ * multiple sitemaps.
  */
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub saleor_app: Arc<tokio::sync::Mutex<SaleorApp>>,
     pub config: Config,
-    pub target_channel: String,
     pub sitemap_config: SitemapConfig,
+    pub settings: InstanceConfigs<SitemapSettings>,
+    /// What new installations start with, see [`SitemapSettings::load`]
+    pub default_settings: SitemapSettings,
     pub manifest: AppManifest,
-    pub task_queue_sender: Sender<Event>,
+    pub task_queue_sender: Sender<QueuedEvent>,
 }
 
+/// Settings of the deployment
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SitemapConfig {
+    /// Every registered saleor instance gets its own subfolder, see [`Self::instance_folder`]
     #[serde(rename = "sitemap_target_folder")]
     pub target_folder: String,
+    #[serde(rename = "sitemap_product_changefreq", default)]
+    #[serde(deserialize_with = "changefreq")]
+    pub product_changefreq: Option<ChangeFreq>,
+    #[serde(rename = "sitemap_product_priority", default)]
+    #[serde(deserialize_with = "priority")]
+    pub product_priority: Option<f32>,
+    #[serde(rename = "sitemap_category_changefreq", default)]
+    #[serde(deserialize_with = "changefreq")]
+    pub category_changefreq: Option<ChangeFreq>,
+    #[serde(rename = "sitemap_category_priority", default)]
+    #[serde(deserialize_with = "priority")]
+    pub category_priority: Option<f32>,
+    #[serde(rename = "sitemap_collection_changefreq", default)]
+    #[serde(deserialize_with = "changefreq")]
+    pub collection_changefreq: Option<ChangeFreq>,
+    #[serde(rename = "sitemap_collection_priority", default)]
+    #[serde(deserialize_with = "priority")]
+    pub collection_priority: Option<f32>,
+    #[serde(rename = "sitemap_pages_changefreq", default)]
+    #[serde(deserialize_with = "changefreq")]
+    pub pages_changefreq: Option<ChangeFreq>,
+    #[serde(rename = "sitemap_pages_priority", default)]
+    #[serde(deserialize_with = "priority")]
+    pub pages_priority: Option<f32>,
+    /// Adds `<image:image>` entries of product media to product urls
+    #[serde(rename = "sitemap_include_images", default)]
+    #[serde(deserialize_with = "flag")]
+    pub include_images: bool,
+    /// Other language versions of every url, linked to each other with `hreflang`
+    #[serde(rename = "sitemap_locales", default)]
+    #[serde(deserialize_with = "locales")]
+    pub locales: Vec<Locale>,
+    /// `hreflang` of the urls the instance templates make, `x-default` if unset
+    #[serde(rename = "sitemap_default_hreflang", default)]
+    #[serde(deserialize_with = "non_empty")]
+    pub default_hreflang: Option<String>,
+    /// How long urls of an instance have to stay unchanged before its sitemaps get rewritten
+    #[serde(rename = "sitemap_write_delay_ms", default)]
+    #[serde(deserialize_with = "millis")]
+    pub write_delay_ms: Option<u64>,
+}
+
+const DEFAULT_WRITE_DELAY_MS: u64 = 1000;
+
+/// Templates of one language version of the storefront
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Locale {
+    /// eg. `sk` or `en-GB`
+    pub hreflang: String,
+    /// Saleor language code whose translated slugs go into the templates, eg. `SK`. Without it
+    /// the original slugs are used
+    #[serde(default)]
+    pub language_code: Option<String>,
+    pub product_template: String,
+    pub category_template: String,
+    pub collection_template: String,
+    pub pages_template: String,
+}
+
+impl Locale {
+    pub fn template(&self, typ: &ItemType) -> &str {
+        match typ {
+            ItemType::Product => &self.product_template,
+            ItemType::Category => &self.category_template,
+            ItemType::Collection => &self.collection_template,
+            ItemType::Page => &self.pages_template,
+        }
+    }
+}
+
+/// `<changefreq>` values the sitemap protocol allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl ChangeFreq {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Hourly => "hourly",
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+            Self::Yearly => "yearly",
+            Self::Never => "never",
+        }
+    }
+}
+
+/// Empty env vars, like the ones in `.env.example`, count as unset
+fn non_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|s| !s.trim().is_empty()))
+}
+
+fn changefreq<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<ChangeFreq>, D::Error> {
+    non_empty(deserializer)?
+        .map(|c| ChangeFreq::deserialize(c.trim().into_deserializer()))
+        .transpose()
+}
+
+/// Json list of [`Locale`]s, with templates checked up front instead of on every sitemap write
+fn locales<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Locale>, D::Error> {
+    let Some(locales) = non_empty(deserializer)? else {
+        return Ok(vec![]);
+    };
+    let locales: Vec<Locale> = serde_json::from_str(&locales).map_err(serde::de::Error::custom)?;
+    for locale in locales.iter() {
+        let mut tt = tinytemplate::TinyTemplate::new();
+        for typ in [
+            ItemType::Product,
+            ItemType::Category,
+            ItemType::Collection,
+            ItemType::Page,
+        ] {
+            tt.add_template("t", locale.template(&typ)).map_err(|e| {
+                serde::de::Error::custom(format!("bad {} template, {e}", locale.hreflang))
+            })?;
+        }
+    }
+    Ok(locales)
+}
+
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let Some(flag) = non_empty(deserializer)? else {
+        return Ok(false);
+    };
+    flag.trim()
+        .parse()
+        .map_err(|_| serde::de::Error::custom(format!("expected true or false, got {flag}")))
+}
+
+fn priority<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    let Some(priority) = non_empty(deserializer)? else {
+        return Ok(None);
+    };
+    match priority.trim().parse::<f32>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(Some(p)),
+        _ => Err(serde::de::Error::custom(format!(
+            "sitemap priority has to be between 0.0 and 1.0, got {priority}"
+        ))),
+    }
+}
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let Some(millis) = non_empty(deserializer)? else {
+        return Ok(None);
+    };
+    millis
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("expected milliseconds, got {millis}")))
+}
+
+impl SitemapConfig {
+    pub fn load() -> Result<Self, envy::Error> {
+        _ = dotenvy::dotenv();
+        envy::from_env::<SitemapConfig>()
+    }
+
+    /// Saleor language codes of the locales using translated slugs, without duplicates
+    pub fn language_codes(&self) -> Vec<&str> {
+        let mut codes = vec![];
+        for code in self
+            .locales
+            .iter()
+            .filter_map(|l| l.language_code.as_deref())
+        {
+            if !codes.contains(&code) {
+                codes.push(code);
+            }
+        }
+        codes
+    }
+
+    pub fn write_delay(&self) -> Duration {
+        Duration::from_millis(self.write_delay_ms.unwrap_or(DEFAULT_WRITE_DELAY_MS))
+    }
+
+    /// `<changefreq>` and `<priority>` urls of given type get, if configured
+    pub fn hints(&self, typ: &ItemType) -> (Option<ChangeFreq>, Option<f32>) {
+        match typ {
+            ItemType::Product => (self.product_changefreq, self.product_priority),
+            ItemType::Category => (self.category_changefreq, self.category_priority),
+            ItemType::Collection => (self.collection_changefreq, self.collection_priority),
+            ItemType::Page => (self.pages_changefreq, self.pages_priority),
+        }
+    }
+
+    /**
+     * Folder the sitemap of a saleor instance is written to, named after its api url without the
+     * scheme plus the start of the full urls sha256, so urls differing only in scheme or in
+     * characters replaced by `_` don't share a folder,
+     * eg. `https://api.example.com/graphql/` -> `{target_folder}/api.example.com_graphql-56081414`
+     */
+    pub fn instance_folder(&self, saleor_api_url: &str) -> String {
+        let name = saleor_api_url
+            .split_once("://")
+            .map_or(saleor_api_url, |(_, rest)| rest)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let hash = Sha256::digest(saleor_api_url.as_bytes())[..4]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        format!("{}/{}-{hash}", self.target_folder, name.trim_matches('_'))
+    }
+}
+
+/// Settings of a single saleor instance, stored in its app metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SitemapSettings {
     #[serde(rename = "sitemap_product_template")]
     pub product_template: String,
     #[serde(rename = "sitemap_category_template")]
@@ -77,15 +314,21 @@ pub struct SitemapConfig {
     pub pages_template: String,
     #[serde(rename = "sitemap_collection_template")]
     pub collection_template: String,
+    /// Where the sitemaps are hosted, `sitemap_index.xml` points to `{index_hostname}/sitemap-N.xml`
     #[serde(rename = "sitemap_index_hostname")]
     pub index_hostname: String,
-    #[serde(rename = "sitemap_allowed_host")]
-    pub allowed_host: String,
+    /// Products get queried in this channel
+    pub channel_slug: String,
 }
 
-impl SitemapConfig {
+impl ChannelConfig for SitemapSettings {
+    const NAME: &'static str = "sitemap";
+}
+
+impl SitemapSettings {
+    /// Loads the defaults new installations start with, later changes go to the app metadata
     pub fn load() -> Result<Self, envy::Error> {
         _ = dotenvy::dotenv();
-        envy::from_env::<SitemapConfig>()
+        envy::from_env::<SitemapSettings>()
     }
 }
@@ -18,18 +18,17 @@ use axum::Router;
 use saleor_app_sdk::{
     config::Config,
     manifest::{cargo_info, AppManifestBuilder, AppPermission},
-    webhooks::{AsyncWebhookEventType, WebhookManifestBuilder},
+    settings_manager::instance_config::InstanceConfigs,
     SaleorApp,
 };
-use sitemap::event_handler::EventHandler;
+use sitemap::event_handler::{prepare_instance_folders, EventHandler};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info};
 
 use crate::{
-    app::{trace_to_std, AppState, SitemapConfig},
-    queries::event_subjects_updated::EVENTS_QUERY,
-    routes::create_routes,
+    app::{trace_to_std, AppState, SitemapConfig, SitemapSettings},
+    routes::{create_routes, webhooks::webhooks},
 };
 
 #[tokio::main]
@@ -38,8 +37,17 @@ async fn main() {
     let config = Config::load().unwrap();
     trace_to_std(&config).unwrap();
     let sitemap_config = SitemapConfig::load().unwrap();
+    let default_settings = SitemapSettings::load().unwrap();
 
-    let app = create_app(&config, sitemap_config).await;
+    let saleor_app = SaleorApp::new(&config).unwrap();
+    let app = create_app(
+        &config,
+        saleor_app,
+        sitemap_config,
+        default_settings,
+        InstanceConfigs::new(),
+    )
+    .await;
 
     let listener = tokio::net::TcpListener::bind(
         "0.0.0.0:".to_owned()
@@ -56,39 +64,37 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn create_app(config: &Config, sitemap_config: SitemapConfig) -> Router {
-    let saleor_app = SaleorApp::new(config).unwrap();
+async fn create_app(
+    config: &Config,
+    saleor_app: SaleorApp,
+    sitemap_config: SitemapConfig,
+    default_settings: SitemapSettings,
+    settings: InstanceConfigs<SitemapSettings>,
+) -> Router {
+    let apl = saleor_app.apl.clone();
+    let webhooks = webhooks(apl.clone(), &sitemap_config);
+    let mut permissions = vec![AppPermission::ManageProducts, AppPermission::ManagePages];
+    if !sitemap_config.language_codes().is_empty() {
+        // translation webhooks need it
+        permissions.push(AppPermission::ManageTranslations);
+    }
 
     debug!("Creating saleor App...");
     let app_manifest = AppManifestBuilder::new(config, cargo_info!())
-        .add_permissions(vec![
-            AppPermission::ManageProducts,
-            AppPermission::ManagePages,
-        ])
-        .add_webhook(
-            WebhookManifestBuilder::new(config)
-                .set_query(EVENTS_QUERY)
-                .add_async_events(vec![
-                    AsyncWebhookEventType::ProductCreated,
-                    AsyncWebhookEventType::ProductUpdated,
-                    AsyncWebhookEventType::ProductDeleted,
-                    AsyncWebhookEventType::CategoryCreated,
-                    AsyncWebhookEventType::CategoryUpdated,
-                    AsyncWebhookEventType::CategoryDeleted,
-                    AsyncWebhookEventType::PageCreated,
-                    AsyncWebhookEventType::PageUpdated,
-                    AsyncWebhookEventType::PageDeleted,
-                    AsyncWebhookEventType::CollectionCreated,
-                    AsyncWebhookEventType::CollectionUpdated,
-                    AsyncWebhookEventType::CollectionDeleted,
-                ])
-                .build(),
-        )
+        .add_permissions(permissions)
+        .add_webhooks(webhooks.webhook_manifests(config))
         .build()
         .expect("Manifest has invalid parameters");
 
     debug!("Created AppManifest...");
 
+    if let Err(e) = prepare_instance_folders(&sitemap_config, apl.as_ref()).await {
+        error!(
+            "Failed preparing sitemap folders of registered instances, {:?}",
+            e
+        );
+    }
+
     let (sender, receiver) = tokio::sync::mpsc::channel(100);
 
     EventHandler::start(sitemap_config.clone(), receiver);
@@ -96,17 +102,12 @@ async fn create_app(config: &Config, sitemap_config: SitemapConfig) -> Router {
     let app_state = AppState {
         task_queue_sender: sender,
         sitemap_config,
+        settings,
+        default_settings,
         manifest: app_manifest,
         config: config.clone(),
-        target_channel: match dotenvy::var("CHANNEL_SLUG") {
-            Ok(v) => v,
-            Err(_) => {
-                error!("Missing channel slug, Saleor will soon deprecate product queries without channel specified.");
-                "".to_string()
-            }
-        },
         saleor_app: Arc::new(Mutex::new(saleor_app)),
     };
     debug!("Created AppState...");
-    create_routes(app_state)
+    create_routes(app_state, apl, webhooks)
 }
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[cynic::schema("saleor")]
 mod schema {}
@@ -72,20 +72,106 @@ subscription QueryProductsChanged {
         slug
       }
     }
+    ... on ProductMediaCreated {
+      productMedia {
+        ...BaseProductMedia
+      }
+    }
+    ... on ProductMediaUpdated {
+      productMedia {
+        ...BaseProductMedia
+      }
+    }
+    ... on ProductMediaDeleted {
+      productMedia {
+        ...BaseProductMedia
+      }
+    }
   }
 }
 
 fragment BaseCategory on Category {
   id
   slug
+  updatedAt
 }
 
 fragment BaseProduct on Product {
   id
   slug
+  updatedAt
   category {
     slug
     id
+    updatedAt
+  }
+  media {
+    ...BaseProductMedia
+  }
+}
+
+fragment BaseProductMedia on ProductMedia {
+  id
+  productId
+  url
+  type
+}
+"#;
+
+/// Separate from [`EVENTS_QUERY`], as translated slugs need Saleor 3.21
+pub const TRANSLATIONS_QUERY: &str = r#"
+subscription QueryTranslationsChanged {
+  event {
+    ... on TranslationCreated {
+      translation {
+        ...BaseTranslation
+      }
+    }
+    ... on TranslationUpdated {
+      translation {
+        ...BaseTranslation
+      }
+    }
+  }
+}
+
+fragment BaseTranslation on TranslationTypes {
+  __typename
+  ... on ProductTranslation {
+    slug
+    language {
+      code
+    }
+    translatableContent {
+      productId
+    }
+  }
+  ... on CategoryTranslation {
+    slug
+    language {
+      code
+    }
+    translatableContent {
+      categoryId
+    }
+  }
+  ... on CollectionTranslation {
+    slug
+    language {
+      code
+    }
+    translatableContent {
+      collectionId
+    }
+  }
+  ... on PageTranslation {
+    slug
+    language {
+      code
+    }
+    translatableContent {
+      pageId
+    }
   }
 }
 "#;
@@ -115,7 +201,46 @@ pub struct ProductCreated {
 pub struct Product {
     pub id: cynic::Id,
     pub slug: String,
+    /// Optional, as subscriptions registered by older versions don't query it
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<DateTime>,
     pub category: Option<Category>,
+    pub media: Option<Vec<ProductMedia>>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct ProductMediaCreated {
+    #[serde(rename = "productMedia")]
+    pub product_media: Option<ProductMedia>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct ProductMediaUpdated {
+    #[serde(rename = "productMedia")]
+    pub product_media: Option<ProductMedia>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct ProductMediaDeleted {
+    #[serde(rename = "productMedia")]
+    pub product_media: Option<ProductMedia>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct ProductMedia {
+    pub id: cynic::Id,
+    #[serde(rename = "productId")]
+    pub product_id: Option<cynic::Id>,
+    pub url: String,
+    #[cynic(rename = "type")]
+    #[serde(rename = "type")]
+    pub typ: ProductMediaType,
+}
+
+#[derive(cynic::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductMediaType {
+    Image,
+    Video,
 }
 
 #[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
@@ -179,6 +304,8 @@ pub struct CategoryCreated {
 pub struct Category {
     pub slug: String,
     pub id: cynic::Id,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<DateTime>,
 }
 
 #[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
@@ -186,14 +313,115 @@ pub struct Category {
 pub struct Category2 {
     pub id: cynic::Id,
     pub slug: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<DateTime>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct TranslationCreated {
+    pub translation: Option<TranslationTypes>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct TranslationUpdated {
+    pub translation: Option<TranslationTypes>,
+}
+
+#[derive(cynic::InlineFragments, Debug, Serialize, Clone)]
+#[serde(tag = "__typename")]
+pub enum TranslationTypes {
+    ProductTranslation(ProductTranslation),
+    CategoryTranslation(CategoryTranslation),
+    CollectionTranslation(CollectionTranslation),
+    PageTranslation(PageTranslation),
+    #[cynic(fallback)]
+    Unknown,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct ProductTranslation {
+    pub slug: Option<String>,
+    pub language: LanguageDisplay,
+    #[serde(rename = "translatableContent")]
+    pub translatable_content: Option<ProductTranslatableContent>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct ProductTranslatableContent {
+    #[serde(rename = "productId")]
+    pub product_id: cynic::Id,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct CategoryTranslation {
+    pub slug: Option<String>,
+    pub language: LanguageDisplay,
+    #[serde(rename = "translatableContent")]
+    pub translatable_content: Option<CategoryTranslatableContent>,
 }
 
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct CategoryTranslatableContent {
+    #[serde(rename = "categoryId")]
+    pub category_id: cynic::Id,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct CollectionTranslation {
+    pub slug: Option<String>,
+    pub language: LanguageDisplay,
+    #[serde(rename = "translatableContent")]
+    pub translatable_content: Option<CollectionTranslatableContent>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct CollectionTranslatableContent {
+    #[serde(rename = "collectionId")]
+    pub collection_id: cynic::Id,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct PageTranslation {
+    pub slug: Option<String>,
+    pub language: LanguageDisplay,
+    #[serde(rename = "translatableContent")]
+    pub translatable_content: Option<PageTranslatableContent>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct PageTranslatableContent {
+    #[serde(rename = "pageId")]
+    pub page_id: cynic::Id,
+}
+
+#[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
+pub struct LanguageDisplay {
+    pub code: LanguageCodeEnum,
+}
+
+/**
+ * Saleor has hundreds of language codes and they're only ever compared to the configured ones,
+ * so they're kept as strings. Implemented by hand, as deriving `cynic::Enum` needs every variant.
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct LanguageCodeEnum(pub String);
+
+impl cynic::Enum for LanguageCodeEnum {
+    type SchemaType = schema::LanguageCodeEnum;
+}
+
+cynic::impl_coercions!(LanguageCodeEnum, schema::LanguageCodeEnum);
+
 #[derive(cynic::QueryFragment, Debug, Serialize, Clone)]
 pub struct PageInfo {
     pub end_cursor: Option<String>,
     pub has_next_page: bool,
 }
 
+#[derive(cynic::Scalar, Debug, Clone)]
+pub struct DateTime(pub String);
+
 #[derive(cynic::InlineFragments, Debug, Clone)]
 pub enum Event {
     ProductUpdated(ProductUpdated),
@@ -208,6 +436,11 @@ pub enum Event {
     CollectionCreated(CollectionCreated),
     CollectionUpdated(CollectionUpdated),
     CollectionDeleted(CollectionDeleted),
+    ProductMediaCreated(ProductMediaCreated),
+    ProductMediaUpdated(ProductMediaUpdated),
+    ProductMediaDeleted(ProductMediaDeleted),
+    TranslationCreated(TranslationCreated),
+    TranslationUpdated(TranslationUpdated),
     #[cynic(fallback)]
     Unknown,
 }
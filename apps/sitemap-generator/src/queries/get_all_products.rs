@@ -19,6 +19,12 @@ query getProductsInitial($id: ID!, $channel: String!) {
           category {
             id
             slug
+            updatedAt
+          }
+          media {
+            id
+            url
+            type
           }
         }
       }
@@ -41,6 +47,12 @@ query getProductsNext($after: String!, $channel: String!) {
         category {
           id
           slug
+          updatedAt
+        }
+        media {
+          id
+          url
+          type
         }
       }
     }
@@ -90,6 +102,21 @@ pub struct Product {
     pub slug: String,
     pub updated_at: DateTime,
     pub category: Option<Category>,
+    pub media: Option<Vec<ProductMedia>>,
+}
+
+#[derive(cynic::QueryFragment, Debug, Clone)]
+pub struct ProductMedia {
+    pub id: cynic::Id,
+    pub url: String,
+    #[cynic(rename = "type")]
+    pub typ: ProductMediaType,
+}
+
+#[derive(cynic::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductMediaType {
+    Image,
+    Video,
 }
 
 #[derive(cynic::QueryFragment, Debug)]
@@ -102,6 +129,7 @@ pub struct PageInfo {
 pub struct Category {
     pub id: cynic::Id,
     pub slug: String,
+    pub updated_at: DateTime,
 }
 
 #[derive(cynic::Scalar, Debug, Clone)]
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+#[cynic::schema("saleor")]
+mod schema {}
+/*
+query getTranslations($kind: TranslatableKinds!, $languageCode: LanguageCodeEnum!, $after: String) {
+  translations(kind: $kind, first: 100, after: $after) {
+    pageInfo {
+      hasNextPage
+      endCursor
+    }
+    edges {
+      node {
+        __typename
+        ... on ProductTranslatableContent {
+          productId
+          translation(languageCode: $languageCode) {
+            slug
+          }
+        }
+        ... on CategoryTranslatableContent {
+          categoryId
+          translation(languageCode: $languageCode) {
+            slug
+          }
+        }
+        ... on CollectionTranslatableContent {
+          collectionId
+          translation(languageCode: $languageCode) {
+            slug
+          }
+        }
+        ... on PageTranslatableContent {
+          pageId
+          translation(languageCode: $languageCode) {
+            slug
+          }
+        }
+      }
+    }
+  }
+}
+*/
+
+#[derive(cynic::QueryVariables, Debug)]
+pub struct GetTranslationsVariables<'a> {
+    pub kind: TranslatableKinds,
+    pub language_code: LanguageCodeEnum,
+    pub after: Option<&'a str>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Query", variables = "GetTranslationsVariables")]
+pub struct GetTranslations {
+    #[arguments(kind: $kind, first: 100, after: $after)]
+    pub translations: Option<TranslatableItemConnection>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub struct TranslatableItemConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<TranslatableItemEdge>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub struct TranslatableItemEdge {
+    pub node: TranslatableItem,
+}
+
+#[derive(cynic::InlineFragments, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub enum TranslatableItem {
+    ProductTranslatableContent(ProductTranslatableContent),
+    CategoryTranslatableContent(CategoryTranslatableContent),
+    CollectionTranslatableContent(CollectionTranslatableContent),
+    PageTranslatableContent(PageTranslatableContent),
+    #[cynic(fallback)]
+    Unknown,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub struct ProductTranslatableContent {
+    pub product_id: cynic::Id,
+    #[arguments(languageCode: $language_code)]
+    pub translation: Option<ProductTranslation>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct ProductTranslation {
+    pub slug: Option<String>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub struct CategoryTranslatableContent {
+    pub category_id: cynic::Id,
+    #[arguments(languageCode: $language_code)]
+    pub translation: Option<CategoryTranslation>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct CategoryTranslation {
+    pub slug: Option<String>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub struct CollectionTranslatableContent {
+    pub collection_id: cynic::Id,
+    #[arguments(languageCode: $language_code)]
+    pub translation: Option<CollectionTranslation>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct CollectionTranslation {
+    pub slug: Option<String>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(variables = "GetTranslationsVariables")]
+pub struct PageTranslatableContent {
+    pub page_id: cynic::Id,
+    #[arguments(languageCode: $language_code)]
+    pub translation: Option<PageTranslation>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct PageTranslation {
+    pub slug: Option<String>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub end_cursor: Option<String>,
+}
+
+#[derive(cynic::Enum, Clone, Copy, Debug)]
+pub enum TranslatableKinds {
+    Attribute,
+    AttributeValue,
+    Category,
+    Collection,
+    MenuItem,
+    Page,
+    Product,
+    Promotion,
+    PromotionRule,
+    Sale,
+    ShippingMethod,
+    Variant,
+    Voucher,
+}
+
+/// See [`crate::queries::event_subjects_updated::LanguageCodeEnum`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct LanguageCodeEnum(pub String);
+
+impl cynic::Enum for LanguageCodeEnum {
+    type SchemaType = schema::LanguageCodeEnum;
+}
+
+cynic::impl_coercions!(LanguageCodeEnum, schema::LanguageCodeEnum);
+
+impl schema::variable::Variable for LanguageCodeEnum {
+    const TYPE: cynic::variables::VariableType =
+        cynic::variables::VariableType::Named("LanguageCodeEnum");
+}
@@ -3,3 +3,4 @@ pub mod get_all_categories;
 pub mod get_all_collections;
 pub mod get_all_pages;
 pub mod get_all_products;
+pub mod get_all_translations;
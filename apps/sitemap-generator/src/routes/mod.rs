@@ -1,7 +1,9 @@
+use std::sync::Arc;
+
 use axum::{
     handler::HandlerWithoutStateExt,
     http::StatusCode,
-    routing::{any, get, post},
+    routing::{get, post},
     Router,
 };
 
@@ -10,6 +12,7 @@ use axum::middleware;
 #[cfg(not(debug_assertions))]
 use saleor_app_sdk::middleware::verify_webhook_signature::webhook_signature_verifier;
 
+use saleor_app_sdk::{apl::APL, middleware::webhook_router::WebhookRouter};
 use tower_http::services::ServeDir;
 
 use crate::app::AppState;
@@ -19,9 +22,13 @@ pub mod register;
 pub mod webhooks;
 use manifest::manifest;
 use register::register;
-use webhooks::webhooks;
 
-pub fn create_routes(state: AppState) -> Router {
+#[cfg_attr(debug_assertions, allow(unused_variables))]
+pub fn create_routes(
+    state: AppState,
+    apl: Arc<dyn APL>,
+    webhooks: WebhookRouter<AppState>,
+) -> Router {
     async fn handle_404() -> (StatusCode, &'static str) {
         (StatusCode::NOT_FOUND, "Not found")
     }
@@ -36,10 +43,13 @@ pub fn create_routes(state: AppState) -> Router {
     let serve_dir = ServeDir::new("./sitemap-generator/public").not_found_service(service);
     //TODO: Query for everything using the app auth token
     //TODO: "Failed fetching initial products: More than one channel exists, please spocify which one"
-    let r = Router::new().route("/api/webhooks", any(webhooks));
+    let r = Router::new().route("/api/webhooks", webhooks.into_method_router());
 
     #[cfg(not(debug_assertions))]
-    let r = r.layer(middleware::from_fn(webhook_signature_verifier));
+    let r = r.layer(middleware::from_fn_with_state(
+        apl,
+        webhook_signature_verifier,
+    ));
 
     r
         //handles just path, eg. localhost:3000/
@@ -1,65 +1,53 @@
-use anyhow::Context;
 use axum::{
     extract::Json,
     extract::State,
     http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
-use saleor_app_sdk::{headers::SALEOR_API_URL_HEADER, AuthData, AuthToken};
-use tracing::{debug, info};
+use saleor_app_sdk::{middleware::register::register_app, AuthData, AuthToken};
+use tracing::info;
 
 use crate::{
     app::{AppError, AppState},
-    sitemap::event_handler::{Event, RegenerateEvent},
+    sitemap::event_handler::{Event, QueuedEvent, RegenerateEvent},
 };
 
 pub async fn register(
     headers: HeaderMap,
     State(state): State<AppState>,
     Json(auth_token): Json<AuthToken>,
-) -> Result<StatusCode, AppError> {
-    debug!(
-        "/api/register:\nsaleor_api_url: {:?}\nauth_token: {:?}",
-        &headers.get(SALEOR_API_URL_HEADER),
-        &auth_token
-    );
-
-    if auth_token.auth_token.is_empty() {
-        return Err(anyhow::anyhow!("missing auth_token").into());
-    }
-    if let Some(url) = headers.get(SALEOR_API_URL_HEADER) {
-        if url.to_str()? != state.sitemap_config.allowed_host {
-            debug!("register didn't come from allowed host");
-            return Err(anyhow::anyhow!("Url not in allowed hosts").into());
-        }
-    } else {
-        debug!("no url in header");
-        return Err(anyhow::anyhow!("Url in header").into());
-    }
-
+) -> Response {
     let app = state.saleor_app.lock().await;
-    let saleor_api_url = headers.get("saleor-api-url").context("missing api field")?;
-    let saleor_api_url = saleor_api_url.to_str()?.to_owned();
-    let auth_data = AuthData {
-        jwks: None,
-        token: auth_token.auth_token,
-        domain: Some(state.config.app_api_base_url.clone()),
-        app_id: state.manifest.id.clone(),
-        saleor_api_url: saleor_api_url.clone(),
+    let registered = register_app(app.apl.as_ref(), &state.config, &headers, auth_token).await;
+    let auth_data = match registered {
+        Ok(auth_data) => auth_data,
+        Err(e) => return e.into_response(),
     };
-    app.apl.set(auth_data).await?;
+    match start_generating(&state, auth_data).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
 
-    info!("registered app for{:?}", &saleor_api_url);
+/// Loads the instances settings and queues generating its whole sitemap
+async fn start_generating(state: &AppState, auth_data: AuthData) -> Result<(), AppError> {
+    //Reinstalls keep the settings they had, new installations start with the ones from env
+    let settings = state
+        .settings
+        .init(&auth_data, state.default_settings.clone())
+        .await?;
 
     //When app registers, start collecting everything of substance
     info!("Starting caching and generation process");
-    let cloned_state = state.clone();
-
     state
         .task_queue_sender
-        .send(Event::Regenerate(RegenerateEvent {
-            state: cloned_state,
-            saleor_api_url,
-        }))
+        .send(QueuedEvent {
+            saleor_api_url: auth_data.saleor_api_url,
+            event: Event::Regenerate(RegenerateEvent {
+                state: state.clone(),
+            }),
+            settings,
+        })
         .await?;
-    Ok(StatusCode::OK)
+    Ok(())
 }
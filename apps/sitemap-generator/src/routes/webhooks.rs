@@ -1,142 +1,113 @@
-use anyhow::Context;
-use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
-};
+use std::sync::Arc;
+
+use axum::http::StatusCode;
 use saleor_app_sdk::{
-    headers::SALEOR_API_URL_HEADER,
-    webhooks::{
-        utils::{get_webhook_event_type, EitherWebhookType},
-        AsyncWebhookEventType,
-    },
+    apl::APL,
+    middleware::webhook_router::{Webhook, WebhookRouter},
+    webhooks::AsyncWebhookEventType,
 };
+use serde_json::Value;
 use tracing::{debug, info};
 
 use crate::{
-    app::{AppError, AppState},
-    queries::event_subjects_updated::{
-        CategoryCreated, CategoryDeleted, CategoryUpdated, CollectionCreated, CollectionDeleted,
-        CollectionUpdated, PageCreated, PageDeleted, PageUpdated, ProductCreated, ProductDeleted,
-        ProductUpdated,
-    },
-    sitemap::event_handler::Event,
+    app::{AppError, AppState, SitemapConfig},
+    queries::event_subjects_updated::{EVENTS_QUERY, TRANSLATIONS_QUERY},
+    sitemap::event_handler::{Event, QueuedEvent},
 };
 
-pub async fn webhooks(
-    headers: HeaderMap,
-    State(state): State<AppState>,
-    data: String,
-) -> Result<StatusCode, AppError> {
-    debug!("/api/webhooks");
-    debug!("req: {:?}", data);
-    debug!("headers: {:?}", headers);
-
-    let url = headers
-        .get(SALEOR_API_URL_HEADER)
-        .context("missing saleor api url header")?
-        .to_str()?
-        .to_owned();
-    if url != state.sitemap_config.allowed_host {
-        debug!("webhook didn't come from allowed host");
-        return Ok(StatusCode::METHOD_NOT_ALLOWED);
-    }
-    let event_type = get_webhook_event_type(&headers)?;
-    debug!("event type: {:?}", &event_type);
-    if let EitherWebhookType::Async(a) = event_type {
-        // TODO: Extract this into a function so You can check what the error was if something fails
-        match a {
-            AsyncWebhookEventType::ProductUpdated => {
-                let product: ProductUpdated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::ProductUpdated(product))
-                    .await?;
-            }
-            AsyncWebhookEventType::ProductCreated => {
-                let product: ProductCreated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::ProductCreated(product))
-                    .await?;
-            }
-            AsyncWebhookEventType::ProductDeleted => {
-                let product: ProductDeleted = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::ProductDeleted(product))
-                    .await?;
-            }
-            AsyncWebhookEventType::CategoryCreated => {
-                let category: CategoryCreated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::CategoryCreated(category))
-                    .await?;
-            }
-            AsyncWebhookEventType::CategoryUpdated => {
-                let category: CategoryUpdated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::CategoryUpdated(category))
-                    .await?;
-            }
-            AsyncWebhookEventType::CategoryDeleted => {
-                let category: CategoryDeleted = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::CategoryDeleted(category))
-                    .await?;
-            }
-            AsyncWebhookEventType::PageCreated => {
-                let page: PageCreated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::PageCreated(page))
-                    .await?;
-            }
-            AsyncWebhookEventType::PageUpdated => {
-                let page: PageUpdated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::PageUpdated(page))
-                    .await?;
-            }
-            AsyncWebhookEventType::PageDeleted => {
-                let page: PageDeleted = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::PageDeleted(page))
-                    .await?;
-            }
-            AsyncWebhookEventType::CollectionCreated => {
-                let collection: CollectionCreated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::CollectionCreated(collection))
-                    .await?;
-            }
-            AsyncWebhookEventType::CollectionUpdated => {
-                let collection: CollectionUpdated = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::CollectionUpdated(collection))
-                    .await?;
-            }
-            AsyncWebhookEventType::CollectionDeleted => {
-                let collection: CollectionDeleted = serde_json::from_str(&data)?;
-                state
-                    .task_queue_sender
-                    .send(Event::CollectionDeleted(collection))
-                    .await?;
-            }
-            _ => (),
-        }
+/**
+ * Handlers for every event the app subscribes to, the app manifest webhooks are derived from it.
+ * Translations are only listened to if some locale uses translated slugs, `APP_UPDATED` drops the
+ * cached settings of the instance.
+ */
+pub fn webhooks(apl: Arc<dyn APL>, sitemap_config: &SitemapConfig) -> WebhookRouter<AppState> {
+    use AsyncWebhookEventType as E;
+    let router = WebhookRouter::new(apl)
+        .on_async(E::AppUpdated, settings_changed)
+        .subscription(EVENTS_QUERY)
+        .on_async(E::ProductCreated, |s, w| {
+            forward(s, w, Event::ProductCreated)
+        })
+        .on_async(E::ProductUpdated, |s, w| {
+            forward(s, w, Event::ProductUpdated)
+        })
+        .on_async(E::ProductDeleted, |s, w| {
+            forward(s, w, Event::ProductDeleted)
+        })
+        .on_async(E::CategoryCreated, |s, w| {
+            forward(s, w, Event::CategoryCreated)
+        })
+        .on_async(E::CategoryUpdated, |s, w| {
+            forward(s, w, Event::CategoryUpdated)
+        })
+        .on_async(E::CategoryDeleted, |s, w| {
+            forward(s, w, Event::CategoryDeleted)
+        })
+        .on_async(E::PageCreated, |s, w| forward(s, w, Event::PageCreated))
+        .on_async(E::PageUpdated, |s, w| forward(s, w, Event::PageUpdated))
+        .on_async(E::PageDeleted, |s, w| forward(s, w, Event::PageDeleted))
+        .on_async(E::CollectionCreated, |s, w| {
+            forward(s, w, Event::CollectionCreated)
+        })
+        .on_async(E::CollectionUpdated, |s, w| {
+            forward(s, w, Event::CollectionUpdated)
+        })
+        .on_async(E::CollectionDeleted, |s, w| {
+            forward(s, w, Event::CollectionDeleted)
+        })
+        .on_async(E::ProductMediaCreated, |s, w| {
+            forward(s, w, Event::ProductMediaCreated)
+        })
+        .on_async(E::ProductMediaUpdated, |s, w| {
+            forward(s, w, Event::ProductMediaUpdated)
+        })
+        .on_async(E::ProductMediaDeleted, |s, w| {
+            forward(s, w, Event::ProductMediaDeleted)
+        });
+    if sitemap_config.language_codes().is_empty() {
+        return router;
     }
+    router
+        .subscription(TRANSLATIONS_QUERY)
+        .on_async(E::TranslationCreated, |s, w| {
+            forward(s, w, Event::TranslationCreated)
+        })
+        .on_async(E::TranslationUpdated, |s, w| {
+            forward(s, w, Event::TranslationUpdated)
+        })
+}
+
+/// Passes the webhook payload on to the event handler
+async fn forward<T: Send>(
+    state: AppState,
+    webhook: Webhook<T>,
+    into_event: fn(T) -> Event,
+) -> Result<StatusCode, AppError> {
+    debug!("/api/webhooks {:?}", &webhook.event);
+    let settings = state
+        .settings
+        .get_or_init(&webhook.auth_data, &state.default_settings)
+        .await?;
+    state
+        .task_queue_sender
+        .send(QueuedEvent {
+            saleor_api_url: webhook.auth_data.saleor_api_url,
+            event: into_event(webhook.payload),
+            settings,
+        })
+        .await?;
 
     info!("webhook proccessed");
     Ok(StatusCode::OK)
 }
 
+/// Saleor sends `APP_UPDATED` when the apps metadata changes, so settings get refetched on next use
+async fn settings_changed(state: AppState, webhook: Webhook<Value>) -> StatusCode {
+    debug!("settings of {} changed", &webhook.auth_data.saleor_api_url);
+    state.settings.remove(&webhook.auth_data.saleor_api_url);
+    StatusCode::OK
+}
+
 // pub async fn write_xml(
 //     urls: Vec<Url>,
 //     state: &AppState,
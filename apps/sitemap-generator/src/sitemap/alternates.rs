@@ -0,0 +1,91 @@
+use tinytemplate::TinyTemplate;
+use tracing::error;
+
+use super::{ItemType, Url, UrlSet};
+use crate::{
+    app::{Locale, SitemapConfig},
+    queries::event_subjects_updated::{
+        CategoryCreated, CollectionCreated, PageCreated, ProductCreated,
+    },
+};
+
+/// `hreflang` of the urls made by the instance templates, unless configured otherwise
+pub const DEFAULT_HREFLANG: &str = "x-default";
+
+/**
+ * Renders the other language versions of urls with the templates of every configured locale,
+ * for `<xhtml:link rel="alternate">`. Templates get compiled once, as this runs for every url
+ * on every sitemap write.
+ */
+pub struct Alternates<'a> {
+    default_hreflang: &'a str,
+    locales: Vec<(&'a Locale, TinyTemplate<'a>)>,
+}
+
+impl<'a> Alternates<'a> {
+    pub fn new(sitemap_config: &'a SitemapConfig) -> Result<Self, tinytemplate::error::Error> {
+        let mut locales = vec![];
+        for locale in sitemap_config.locales.iter() {
+            let mut tt = TinyTemplate::new();
+            for typ in [
+                ItemType::Product,
+                ItemType::Category,
+                ItemType::Collection,
+                ItemType::Page,
+            ] {
+                tt.add_template(template_name(&typ), locale.template(&typ))?;
+            }
+            locales.push((locale, tt));
+        }
+        Ok(Self {
+            default_hreflang: sitemap_config
+                .default_hreflang
+                .as_deref()
+                .unwrap_or(DEFAULT_HREFLANG),
+            locales,
+        })
+    }
+
+    /// `(hreflang, url)` of every language version of `url` starting with itself, empty if there
+    /// are no locales
+    pub fn of(&self, url: &Url, url_set: &UrlSet) -> Vec<(&'a str, String)> {
+        if self.locales.is_empty() {
+            return vec![];
+        }
+        let mut alternates = vec![(self.default_hreflang, url.url.clone())];
+        for (locale, tt) in self.locales.iter() {
+            let localized = match locale.language_code.as_deref() {
+                Some(language_code) => url_set.localized(url, language_code),
+                None => url.clone(),
+            };
+            match render(tt, localized) {
+                Ok(href) => alternates.push((locale.hreflang.as_str(), href)),
+                Err(e) => error!(
+                    "Failed rendering {} version of {}, {:?}",
+                    &locale.hreflang, &url.url, e
+                ),
+            }
+        }
+        alternates
+    }
+}
+
+fn template_name(typ: &ItemType) -> &'static str {
+    match typ {
+        ItemType::Product => "product",
+        ItemType::Category => "category",
+        ItemType::Collection => "collection",
+        ItemType::Page => "page",
+    }
+}
+
+/// Same template data as [`Url::new`] gets from the webhooks
+fn render(tt: &TinyTemplate, url: Url) -> Result<String, tinytemplate::error::Error> {
+    let name = template_name(&url.data.typ);
+    match url.data.typ {
+        ItemType::Product => tt.render(name, &ProductCreated::from(url)),
+        ItemType::Category => tt.render(name, &CategoryCreated::from(url)),
+        ItemType::Collection => tt.render(name, &CollectionCreated::from(url)),
+        ItemType::Page => tt.render(name, &PageCreated::from(url)),
+    }
+}
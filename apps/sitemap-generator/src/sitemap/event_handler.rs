@@ -1,33 +1,67 @@
 use super::regenerate::regenerate;
+use saleor_app_sdk::apl::APL;
 use serde::Serialize;
 use std::{
+    collections::{hash_map::Entry, HashMap},
     fs::{self},
-    io::ErrorKind,
+    time::Duration,
 };
 
 use crate::{
-    app::{AppState, SitemapConfig},
+    app::{AppState, SitemapConfig, SitemapSettings},
     queries::event_subjects_updated::{
         Category2, CategoryCreated, CategoryDeleted, CategoryUpdated, Collection,
-        CollectionCreated, CollectionDeleted, CollectionUpdated, Page, PageCreated, PageDeleted,
-        PageUpdated, Product, ProductCreated, ProductDeleted, ProductUpdated,
+        CollectionCreated, CollectionDeleted, CollectionUpdated, LanguageCodeEnum, Page,
+        PageCreated, PageDeleted, PageUpdated, Product, ProductCreated, ProductDeleted,
+        ProductMedia, ProductMediaCreated, ProductMediaDeleted, ProductMediaUpdated,
+        ProductUpdated, TranslationCreated, TranslationTypes, TranslationUpdated,
     },
     sitemap::{AffectedResult, AffectedType, Url},
 };
-use tokio::{sync::mpsc::Receiver, task::JoinHandle};
+use tokio::{sync::mpsc::Receiver, task::JoinHandle, time::Instant};
 use tracing::{debug, error, info, warn};
 
-use super::{ItemData, ItemType, UrlSet};
+use super::{
+    parse_datetime,
+    store::{UrlStore, DB_FILE_NAME},
+    xml::{
+        render_index, render_sitemaps, sitemap_file_name, MAX_SITEMAP_BYTES, MAX_URLS_PER_SITEMAP,
+    },
+    Image, ItemData, ItemType, UrlSet,
+};
 
-// 10k links google says, but there's also a size limit and my custom params might be messing with
-// that? Rather split prematurely to be sure.
-const MAX_URL_IN_SET: usize = 50_000;
-const DB_FILE_NAME: &str = "db.cbor";
-const SITEMAP_FILE_NAME: &str = "sitemap.txt";
+const SITEMAP_INDEX_FILE_NAME: &str = "sitemap_index.xml";
+/// Older versions wrote a plain list of urls here
+const LEGACY_SITEMAP_FILE_NAME: &str = "sitemap.txt";
+/// Longest a sitemap waits for its urls to stop changing before it gets written anyway
+const MAX_WRITE_DELAY: Duration = Duration::from_secs(30);
 
 pub struct EventHandler {
-    receiver: Receiver<Event>,
+    receiver: Receiver<QueuedEvent>,
     sitemap_config: SitemapConfig,
+    /// Urls of instances that got events, by their folder, so they're only read from disk once
+    stores: HashMap<String, UrlStore>,
+    /// Sitemaps of instances waiting for their urls to stop changing, by their folder
+    pending_writes: HashMap<String, PendingWrite>,
+}
+
+/**
+ * Sitemap files get rewritten once an instance's urls stop changing for
+ * [`SitemapConfig::write_delay`], so bursts of events like imports don't rewrite them for every
+ * event. They don't wait more than [`MAX_WRITE_DELAY`] during long bursts.
+ */
+struct PendingWrite {
+    settings: SitemapSettings,
+    first_change: Instant,
+    last_change: Instant,
+}
+
+/// Event along with the saleor instance it came from and its settings
+#[derive(Debug, Clone)]
+pub struct QueuedEvent {
+    pub saleor_api_url: String,
+    pub event: Event,
+    pub settings: SitemapSettings,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +78,11 @@ pub enum Event {
     CollectionCreated(CollectionCreated),
     CollectionUpdated(CollectionUpdated),
     CollectionDeleted(CollectionDeleted),
+    ProductMediaCreated(ProductMediaCreated),
+    ProductMediaUpdated(ProductMediaUpdated),
+    ProductMediaDeleted(ProductMediaDeleted),
+    TranslationCreated(TranslationCreated),
+    TranslationUpdated(TranslationUpdated),
     Regenerate(RegenerateEvent),
     Unknown,
 }
@@ -51,41 +90,96 @@ pub enum Event {
 #[derive(Debug, Clone)]
 pub struct RegenerateEvent {
     pub state: AppState,
-    pub saleor_api_url: String,
 }
 
 impl EventHandler {
-    pub fn start(sitemap_config: SitemapConfig, receiver: Receiver<Event>) -> JoinHandle<()> {
+    pub fn start(sitemap_config: SitemapConfig, receiver: Receiver<QueuedEvent>) -> JoinHandle<()> {
         let s = Self {
             sitemap_config,
             receiver,
+            stores: HashMap::new(),
+            pending_writes: HashMap::new(),
         };
         tokio::spawn(s.listen())
     }
 
     async fn listen(mut self) {
-        while let Some(message) = self.receiver.recv().await {
-            debug!("received Event: {:?}", &message);
-            match message {
+        loop {
+            let queued = match self.next_write() {
+                Some(due) => match tokio::time::timeout_at(due, self.receiver.recv()).await {
+                    Ok(queued) => queued,
+                    Err(_) => {
+                        self.write_pending_sitemaps(false).await;
+                        continue;
+                    }
+                },
+                None => self.receiver.recv().await,
+            };
+            let Some(QueuedEvent {
+                saleor_api_url,
+                event,
+                settings,
+            }) = queued
+            else {
+                break;
+            };
+            debug!("received Event from {saleor_api_url}: {:?}", &event);
+            let target_folder = self.sitemap_config.instance_folder(&saleor_api_url);
+            if let Err(e) = fs::create_dir_all(&target_folder) {
+                error!("failed creating sitemap folder {target_folder}, {:?}", e);
+                continue;
+            }
+            if let Event::Regenerate(r) = event {
+                match regenerate(r.state, saleor_api_url, settings).await {
+                    Ok(_) => info!("regenerate: Fully created sitemap!"),
+                    Err(e) => error!("regenerate: ERR! {:?}", e),
+                }
+                // it wrote both the db and sitemaps
+                self.stores.remove(&target_folder);
+                self.pending_writes.remove(&target_folder);
+                continue;
+            }
+            let store = match self.stores.entry(target_folder.clone()) {
+                Entry::Occupied(store) => store.into_mut(),
+                Entry::Vacant(entry) => match UrlStore::open(&target_folder) {
+                    Ok(store) => entry.insert(store),
+                    Err(e) => {
+                        error!(
+                            "failed loading urls of {target_folder}, won't crash, but probably broken. {:?}",
+                            e
+                        );
+                        continue;
+                    }
+                },
+            };
+            match event {
                 Event::ProductCreated(product_created) => {
                     if let Some(product) = product_created.clone().product {
-                        product_updated_or_created(product_created, product, &self.sitemap_config)
-                            .await;
+                        product_updated_or_created(
+                            product_created,
+                            product,
+                            &mut store.url_set,
+                            &settings,
+                        );
                     } else {
                         warn!("Event::ProductCreated/Updated missing data");
                     }
                 }
                 Event::ProductUpdated(product_updated) => {
                     if let Some(product) = product_updated.clone().product {
-                        product_updated_or_created(product_updated, product, &self.sitemap_config)
-                            .await;
+                        product_updated_or_created(
+                            product_updated,
+                            product,
+                            &mut store.url_set,
+                            &settings,
+                        );
                     } else {
                         warn!("Event::ProductCreated/Updated missing data");
                     }
                 }
                 Event::ProductDeleted(product) => {
                     if let Some(product) = product.product {
-                        delete(product.id.inner(), &self.sitemap_config).await;
+                        delete(product.id.inner(), &mut store.url_set);
                     } else {
                         warn!("Event::ProductDeleted missing data");
                     }
@@ -96,9 +190,9 @@ impl EventHandler {
                         category_updated_or_created(
                             category_created,
                             category,
-                            &self.sitemap_config,
-                        )
-                        .await;
+                            &mut store.url_set,
+                            &settings,
+                        );
                     } else {
                         warn!("Event::CategoryCreated/Updated missing data");
                     }
@@ -108,16 +202,16 @@ impl EventHandler {
                         category_updated_or_created(
                             category_updated,
                             category,
-                            &self.sitemap_config,
-                        )
-                        .await;
+                            &mut store.url_set,
+                            &settings,
+                        );
                     } else {
                         warn!("Event::CategoryCreated/Updated missing data");
                     }
                 }
                 Event::CategoryDeleted(category) => {
                     if let Some(category) = category.category {
-                        delete(category.id.inner(), &self.sitemap_config).await;
+                        delete(category.id.inner(), &mut store.url_set);
                     } else {
                         warn!("Event::CategoryDeleted missing data");
                     }
@@ -128,9 +222,9 @@ impl EventHandler {
                         collection_updated_or_created(
                             collection_created,
                             collection,
-                            &self.sitemap_config,
-                        )
-                        .await;
+                            &mut store.url_set,
+                            &settings,
+                        );
                     } else {
                         warn!("Event::ProductCreated/Updated missing Data");
                     }
@@ -140,16 +234,16 @@ impl EventHandler {
                         collection_updated_or_created(
                             collection_updated,
                             collection,
-                            &self.sitemap_config,
-                        )
-                        .await;
+                            &mut store.url_set,
+                            &settings,
+                        );
                     } else {
                         warn!("Event::ProductCreated/Updated missing Data");
                     }
                 }
                 Event::CollectionDeleted(collection) => {
                     if let Some(collection) = collection.collection {
-                        delete(collection.id.inner(), &self.sitemap_config).await;
+                        delete(collection.id.inner(), &mut store.url_set);
                     } else {
                         warn!("Event::ProductDeleted missing data");
                     }
@@ -157,148 +251,233 @@ impl EventHandler {
 
                 Event::PageCreated(page_created) => {
                     if let Some(page) = page_created.clone().page {
-                        page_updated_or_created(page_created, page, &self.sitemap_config).await;
+                        page_updated_or_created(page_created, page, &mut store.url_set, &settings);
                     }
                     warn!("Event::PageCreated/Updated missing data");
                 }
                 Event::PageUpdated(page_updated) => {
                     if let Some(page) = page_updated.clone().page {
-                        page_updated_or_created(page_updated, page, &self.sitemap_config).await;
+                        page_updated_or_created(page_updated, page, &mut store.url_set, &settings);
                     } else {
                         warn!("Event::PageCreated/Updated missing data");
                     }
                 }
                 Event::PageDeleted(page) => {
                     if let Some(page) = page.page {
-                        delete(page.id.inner(), &self.sitemap_config).await;
+                        delete(page.id.inner(), &mut store.url_set);
                     } else {
                         warn!("Event::PageDeleted missing data");
                     }
                 }
-                Event::Regenerate(r) => match regenerate(r.state, r.saleor_api_url).await {
-                    Ok(_) => info!("regenerate: Fully created sitemap!"),
-                    Err(e) => error!("regenerate: ERR! {:?}", e),
-                },
-                Event::Unknown => (),
+                Event::ProductMediaCreated(ProductMediaCreated { product_media })
+                | Event::ProductMediaUpdated(ProductMediaUpdated { product_media }) => {
+                    if let Some(media) = product_media {
+                        product_media_changed(media, false, &mut store.url_set);
+                    } else {
+                        warn!("Event::ProductMediaCreated/Updated missing data");
+                    }
+                }
+                Event::ProductMediaDeleted(media) => {
+                    if let Some(media) = media.product_media {
+                        product_media_changed(media, true, &mut store.url_set);
+                    } else {
+                        warn!("Event::ProductMediaDeleted missing data");
+                    }
+                }
+                Event::TranslationCreated(TranslationCreated { translation })
+                | Event::TranslationUpdated(TranslationUpdated { translation }) => {
+                    if let Some(translation) = translation {
+                        translation_changed(translation, &mut store.url_set, &self.sitemap_config);
+                    } else {
+                        warn!("Event::TranslationCreated/Updated missing data");
+                    }
+                }
+                Event::Regenerate(_) | Event::Unknown => (),
+            }
+            match store.commit() {
+                Ok(true) => {
+                    let now = Instant::now();
+                    let pending =
+                        self.pending_writes
+                            .entry(target_folder)
+                            .or_insert(PendingWrite {
+                                settings: settings.clone(),
+                                first_change: now,
+                                last_change: now,
+                            });
+                    pending.settings = settings;
+                    pending.last_change = now;
+                }
+                Ok(false) => debug!("Changes haven't affected any urls, ignoring..."),
+                Err(e) => {
+                    error!("failed writing DB to file, {:?}", e);
+                    // what's in memory isn't what's stored anymore
+                    self.stores.remove(&target_folder);
+                }
             }
             info!("Event succesfully handled");
         }
+        // don't leave anything unwritten when shutting down
+        self.write_pending_sitemaps(true).await;
+    }
+
+    /// When the next pending sitemap write is due
+    fn next_write(&self) -> Option<Instant> {
+        self.pending_writes
+            .values()
+            .map(|p| self.write_due(p))
+            .min()
+    }
+
+    fn write_due(&self, pending: &PendingWrite) -> Instant {
+        (pending.last_change + self.sitemap_config.write_delay())
+            .min(pending.first_change + MAX_WRITE_DELAY)
+    }
+
+    /// Writes sitemaps of instances whose write is due, or all pending ones with `all`
+    async fn write_pending_sitemaps(&mut self, all: bool) {
+        let now = Instant::now();
+        let due = self
+            .pending_writes
+            .iter()
+            .filter(|(_, p)| all || self.write_due(p) <= now)
+            .map(|(folder, _)| folder.clone())
+            .collect::<Vec<_>>();
+        for target_folder in due {
+            let (Some(pending), Some(store)) = (
+                self.pending_writes.remove(&target_folder),
+                self.stores.get(&target_folder),
+            ) else {
+                continue;
+            };
+            if let Err(e) = write_url_set_to_file(
+                &store.url_set,
+                &target_folder,
+                &pending.settings,
+                &self.sitemap_config,
+            )
+            .await
+            {
+                error!("failed writing url to file, {:?}", e);
+            }
+        }
     }
 }
 
 /* =============== Event handlers =============== */
 
-async fn product_updated_or_created<T: Serialize + Clone>(
+fn product_updated_or_created<T: Serialize + Clone>(
     request: T,
     product: Product,
-    sitemap_config: &SitemapConfig,
+    url_set: &mut UrlSet,
+    settings: &SitemapSettings,
 ) {
     update_or_create(
         request,
-        sitemap_config,
+        url_set,
+        settings,
         ItemData {
             id: product.id.inner().to_owned(),
             slug: product.slug,
             typ: ItemType::Product,
+            updated_at: product.updated_at.and_then(|d| parse_datetime(&d.0)),
         },
         product.category.map(|c| ItemData {
             slug: c.slug,
             typ: ItemType::Category,
             id: c.id.inner().to_owned(),
+            updated_at: c.updated_at.and_then(|d| parse_datetime(&d.0)),
         }),
-    )
-    .await;
+        product.media.as_deref().map(Image::from_media),
+    );
 }
 
-async fn category_updated_or_created<T: Serialize + Clone>(
+fn category_updated_or_created<T: Serialize + Clone>(
     request: T,
     category: Category2,
-    sitemap_config: &SitemapConfig,
+    url_set: &mut UrlSet,
+    settings: &SitemapSettings,
 ) {
     update_or_create(
         request,
-        sitemap_config,
+        url_set,
+        settings,
         ItemData {
             id: category.id.inner().to_owned(),
             slug: category.slug,
             typ: ItemType::Category,
+            updated_at: category.updated_at.and_then(|d| parse_datetime(&d.0)),
         },
         None,
-    )
-    .await;
+        None,
+    );
 }
 
-async fn page_updated_or_created<T: Serialize + Clone>(
+fn page_updated_or_created<T: Serialize + Clone>(
     request: T,
     page: Page,
-    sitemap_config: &SitemapConfig,
+    url_set: &mut UrlSet,
+    settings: &SitemapSettings,
 ) {
     update_or_create(
         request,
-        sitemap_config,
+        url_set,
+        settings,
         ItemData {
             id: page.id.inner().to_owned(),
             slug: page.slug,
             typ: ItemType::Page,
+            // saleor doesn't track when pages change
+            updated_at: None,
         },
         None,
-    )
-    .await;
+        None,
+    );
 }
 
-async fn collection_updated_or_created<T: Serialize + Clone>(
+fn collection_updated_or_created<T: Serialize + Clone>(
     request: T,
     collection: Collection,
-    sitemap_config: &SitemapConfig,
+    url_set: &mut UrlSet,
+    settings: &SitemapSettings,
 ) {
     update_or_create(
         request,
-        sitemap_config,
+        url_set,
+        settings,
         ItemData {
             id: collection.id.inner().to_owned(),
             slug: collection.slug,
             typ: ItemType::Collection,
+            // saleor doesn't track when collections change
+            updated_at: None,
         },
         None,
-    )
-    .await;
+        None,
+    );
 }
 
 /* ============= URL Manipulations ================ */
 
-async fn update_or_create<T: Serialize + Clone>(
+fn update_or_create<T: Serialize + Clone>(
     data: T,
-    sitemap_config: &SitemapConfig,
+    url_set: &mut UrlSet,
+    settings: &SitemapSettings,
     item: ItemData,
     rel_item: Option<ItemData>,
+    images: Option<Vec<Image>>,
 ) {
-    let mut url_set = match get_db_from_file(&sitemap_config.target_folder).await {
-        Ok(u) => u,
-        Err(e) => match e {
-            UrlSetFileOperationsErr::IoResult(e) => match e.kind() {
-                ErrorKind::NotFound => UrlSet::new(),
-                _ => {
-                    error!("File errror: {:?}\n won't crash, but probably broken.", e);
-                    return;
-                }
-            },
-            UrlSetFileOperationsErr::DeError(e) => {
-                error!(
-                    "DE error: {:?}\n Won't crash, but something went badly wrong",
-                    e
-                );
-                return;
-            }
-        },
-    };
-
+    let refreshed = url_set.refresh(&item, images.as_deref());
     let affected_urls = url_set.find_affected(&item.id, &item.slug);
     match affected_urls {
         AffectedResult::NoneRelated => {
             debug!("{:?} doesn't exist in url_set yet", &item.slug);
             std::mem::drop(affected_urls);
-            let new_url = match Url::new(data, sitemap_config, item, rel_item) {
-                Ok(v) => v,
+            let new_url = match Url::new(data, settings, item, rel_item) {
+                Ok(v) => Url {
+                    images: images.unwrap_or_default(),
+                    ..v
+                },
                 Err(e) => {
                     error!("Failed creating new url, {:?}", e);
                     return;
@@ -306,17 +485,16 @@ async fn update_or_create<T: Serialize + Clone>(
             };
             url_set.push(new_url);
         }
-        AffectedResult::NoneAffected => {
-            debug!("Changes haven't affected any urls, ignoring...");
-            return;
+        AffectedResult::NoneAffected if refreshed => {
+            debug!("Changes haven't affected any urls, only their timestamps or images");
         }
+        AffectedResult::NoneAffected => (),
         AffectedResult::Some(mut affected_urls) => {
             debug!("affected urls: {:?}", &affected_urls);
             for affected in affected_urls.iter_mut() {
                 match affected {
                     AffectedType::Data(url) => {
-                        match Url::new(data.clone(), sitemap_config, item.clone(), rel_item.clone())
-                        {
+                        match Url::new(data.clone(), settings, item.clone(), rel_item.clone()) {
                             Ok(new_url) => {
                                 url.url = new_url.url;
                                 url.data = new_url.data;
@@ -333,7 +511,7 @@ async fn update_or_create<T: Serialize + Clone>(
                                 let new_data: ProductCreated = url.clone().into();
                                 match Url::new(
                                     new_data,
-                                    sitemap_config,
+                                    settings,
                                     url.clone().data,
                                     Some(item.clone()),
                                 ) {
@@ -349,7 +527,7 @@ async fn update_or_create<T: Serialize + Clone>(
                                 let new_data: CollectionCreated = url.clone().into();
                                 match Url::new(
                                     new_data,
-                                    sitemap_config,
+                                    settings,
                                     url.clone().data,
                                     Some(item.clone()),
                                 ) {
@@ -365,7 +543,7 @@ async fn update_or_create<T: Serialize + Clone>(
                                 let new_data: PageCreated = url.clone().into();
                                 match Url::new(
                                     new_data,
-                                    sitemap_config,
+                                    settings,
                                     url.clone().data,
                                     Some(item.clone()),
                                 ) {
@@ -381,7 +559,7 @@ async fn update_or_create<T: Serialize + Clone>(
                                 let new_data: CollectionCreated = url.clone().into();
                                 match Url::new(
                                     new_data,
-                                    sitemap_config,
+                                    settings,
                                     url.clone().data,
                                     Some(item.clone()),
                                 ) {
@@ -399,88 +577,182 @@ async fn update_or_create<T: Serialize + Clone>(
             }
         }
     }
+}
 
-    if let Err(e) = write_db_to_file(&url_set, &sitemap_config.target_folder).await {
-        error!("failed writing DB to file, {:?}", e);
-    }
-    if let Err(e) = write_url_set_to_file(&url_set, &sitemap_config.target_folder).await {
-        error!("failed writing url to file, {:?}", e);
+fn delete(id: &str, url_set: &mut UrlSet) {
+    if !url_set.flush_related(id) {
+        debug!("{id} had no urls, ignoring...");
     }
 }
 
-async fn delete(id: &str, sitemap_config: &SitemapConfig) {
-    let mut url_set = match get_db_from_file(&sitemap_config.target_folder).await {
-        Ok(u) => u,
-        Err(e) => match e {
-            UrlSetFileOperationsErr::IoResult(e) => match e.kind() {
-                ErrorKind::NotFound => UrlSet::new(),
-                _ => {
-                    error!("File errror: {:?}\n won't crash, but probably broken.", e);
-                    return;
-                }
-            },
-            UrlSetFileOperationsErr::DeError(e) => {
-                error!(
-                    "DE error: {:?}\n Won't crash, but something went badly wrong",
-                    e
-                );
-                return;
-            }
-        },
+fn product_media_changed(media: ProductMedia, deleted: bool, url_set: &mut UrlSet) {
+    let Some(product_id) = media.product_id.as_ref() else {
+        warn!(
+            "product media {} is missing its product id",
+            media.id.inner()
+        );
+        return;
+    };
+    let image = match deleted {
+        true => None,
+        // videos come back empty, which removes them in case they used to be an image
+        false => Image::from_media(std::slice::from_ref(&media)).pop(),
     };
-    url_set.flush_related(id);
+    if !url_set.set_image(product_id.inner(), media.id.inner(), image) {
+        debug!("media change didn't affect any product urls, ignoring...");
+    }
+}
 
-    if let Err(e) = write_db_to_file(&url_set, &sitemap_config.target_folder).await {
-        error!("failed writing DB to file, {:?}", e);
+fn translation_changed(
+    translation: TranslationTypes,
+    url_set: &mut UrlSet,
+    sitemap_config: &SitemapConfig,
+) {
+    let (id, language, slug) = match translation {
+        TranslationTypes::ProductTranslation(t) => (
+            t.translatable_content.map(|c| c.product_id),
+            t.language,
+            t.slug,
+        ),
+        TranslationTypes::CategoryTranslation(t) => (
+            t.translatable_content.map(|c| c.category_id),
+            t.language,
+            t.slug,
+        ),
+        TranslationTypes::CollectionTranslation(t) => (
+            t.translatable_content.map(|c| c.collection_id),
+            t.language,
+            t.slug,
+        ),
+        TranslationTypes::PageTranslation(t) => (
+            t.translatable_content.map(|c| c.page_id),
+            t.language,
+            t.slug,
+        ),
+        TranslationTypes::Unknown => {
+            debug!("translation of a type that has no urls, ignoring...");
+            return;
+        }
+    };
+    let Some(id) = id else {
+        warn!("translation is missing the id of what it translates");
+        return;
+    };
+    let LanguageCodeEnum(language_code) = language.code;
+    if !sitemap_config
+        .language_codes()
+        .contains(&language_code.as_str())
+    {
+        debug!("no locale uses {language_code}, ignoring...");
+        return;
     }
-    if let Err(e) = write_url_set_to_file(&url_set, &sitemap_config.target_folder).await {
-        error!("failed writing url to file, {:?}", e);
+    if !url_set.set_translated_slug(id.inner(), &language_code, slug) {
+        debug!("translation didn't change any slugs, ignoring...");
     }
 }
 
 /* =================== File and SerDe operations  ========================= */
 
-pub async fn get_db_from_file(target_folder: &str) -> Result<UrlSet, UrlSetFileOperationsErr> {
-    let urls: UrlSet =
-        serde_cbor::de::from_slice(&std::fs::read(format!("{target_folder}/{DB_FILE_NAME}"))?)?;
-    Ok(urls)
+/**
+ * Creates the folder of every saleor instance in the APL. Sitemaps used to be written straight
+ * into `target_folder`, if there's only one instance those files get moved into its folder.
+ */
+pub async fn prepare_instance_folders(
+    sitemap_config: &SitemapConfig,
+    apl: &dyn APL,
+) -> anyhow::Result<()> {
+    let instances = apl.get_all().await?;
+    for auth_data in instances.iter() {
+        let folder = sitemap_config.instance_folder(&auth_data.saleor_api_url);
+        debug!("sitemap of {} goes to {folder}", &auth_data.saleor_api_url);
+        fs::create_dir_all(&folder)?;
+    }
+
+    if let [auth_data] = instances.as_slice() {
+        let folder = sitemap_config.instance_folder(&auth_data.saleor_api_url);
+        let legacy = format!("{}/{DB_FILE_NAME}", sitemap_config.target_folder);
+        let moved = format!("{folder}/{DB_FILE_NAME}");
+        if fs::exists(&legacy)? && !fs::exists(&moved)? {
+            info!("moving {legacy} to {moved}");
+            fs::rename(legacy, moved)?;
+        }
+    }
+    Ok(())
 }
 
-pub async fn write_db_to_file(
+/**
+ * Writes the urls as `sitemap-N.xml` files split by [`MAX_URLS_PER_SITEMAP`] and
+ * [`MAX_SITEMAP_BYTES`], plus `sitemap_index.xml` pointing to them. Every file is written next to
+ * its final path and renamed over it, so crawlers never get a half written sitemap. Sitemaps left
+ * over from a bigger url set get removed once the index stops pointing to them.
+ */
+pub async fn write_url_set_to_file(
     url_set: &UrlSet,
     target_folder: &str,
+    settings: &SitemapSettings,
+    sitemap_config: &SitemapConfig,
 ) -> Result<(), UrlSetFileOperationsErr> {
-    if url_set.len() > MAX_URL_IN_SET {
-        // return Err(UrlSetFileOperationsErr::UrlSetTooLong(url_set.len()));
-        warn!("Urlset exeeded {MAX_URL_IN_SET} links, search engines might start to complain!");
-    }
-    fs::write(
-        format!("{target_folder}/{DB_FILE_NAME}"),
-        serde_cbor::to_vec(url_set)?,
-    )?;
-    Ok(())
+    write_sitemaps(
+        url_set,
+        target_folder,
+        settings,
+        sitemap_config,
+        MAX_URLS_PER_SITEMAP,
+        MAX_SITEMAP_BYTES,
+    )
 }
 
-pub async fn write_url_set_to_file(
+pub fn write_sitemaps(
     url_set: &UrlSet,
     target_folder: &str,
+    settings: &SitemapSettings,
+    sitemap_config: &SitemapConfig,
+    max_urls: usize,
+    max_bytes: usize,
 ) -> Result<(), UrlSetFileOperationsErr> {
-    if url_set.len() > MAX_URL_IN_SET {
-        // return Err(UrlSetFileOperationsErr::UrlSetTooLong(url_set.len()));
-        warn!("Urlset exeeded {MAX_URL_IN_SET} links, search engines might start to complain!");
+    let sitemaps = render_sitemaps(url_set, sitemap_config, max_urls, max_bytes);
+    debug!(
+        "writing {} urls into {} sitemaps",
+        url_set.len(),
+        sitemaps.len()
+    );
+    for (i, sitemap) in sitemaps.iter().enumerate() {
+        write_atomically(target_folder, &sitemap_file_name(i + 1), sitemap)?;
     }
-
-    fs::write(
-        format!("{target_folder}/{SITEMAP_FILE_NAME}"),
-        url_set
-            .iter()
-            .map(|u| u.url.clone())
-            .collect::<Vec<_>>()
-            .join("\n"),
+    let lastmod = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    write_atomically(
+        target_folder,
+        SITEMAP_INDEX_FILE_NAME,
+        render_index(&settings.index_hostname, sitemaps.len(), &lastmod),
     )?;
+
+    for entry in fs::read_dir(target_folder)? {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let stale = file_name == LEGACY_SITEMAP_FILE_NAME
+            || file_name
+                .strip_prefix("sitemap-")
+                .and_then(|n| n.strip_suffix(".xml"))
+                .and_then(|n| n.parse::<usize>().ok())
+                .is_some_and(|n| n == 0 || n > sitemaps.len());
+        if stale {
+            debug!("removing stale {file_name}");
+            fs::remove_file(format!("{target_folder}/{file_name}"))?;
+        }
+    }
     Ok(())
 }
 
+pub(super) fn write_atomically(
+    target_folder: &str,
+    file_name: &str,
+    contents: impl AsRef<[u8]>,
+) -> Result<(), std::io::Error> {
+    let tmp = format!("{target_folder}/.{file_name}.tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, format!("{target_folder}/{file_name}"))
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum UrlSetFileOperationsErr {
     #[error("writing error")]
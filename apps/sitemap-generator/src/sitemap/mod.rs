@@ -1,28 +1,77 @@
+pub mod alternates;
 pub mod event_handler;
 pub mod regenerate;
+pub mod store;
+pub mod xml;
 
-use std::ops::{Deref, DerefMut};
+use std::collections::{HashMap, HashSet};
 
+use chrono::{DateTime, Utc};
 use saleor_app_sdk::webhooks::{utils::EitherWebhookType, AsyncWebhookEventType};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use tinytemplate::TinyTemplate;
 use tracing::debug;
 
 use crate::{
-    app::SitemapConfig,
+    app::SitemapSettings,
     queries::event_subjects_updated::{
-        Category, Category2, CategoryCreated, CategoryDeleted, Collection, CollectionCreated,
+        self, Category, Category2, CategoryCreated, CategoryDeleted, Collection, CollectionCreated,
         CollectionDeleted, Page, PageCreated, PageDeleted, Product, ProductCreated, ProductDeleted,
+        ProductMedia, ProductMediaType,
     },
 };
 
-const SITEMAP_XMLNS: &str = "http://sitemaps.org/schemas/sitemap/0.9";
+const SITEMAP_XMLNS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+const IMAGE_SITEMAP_XMLNS: &str = "http://www.google.com/schemas/sitemap-image/1.1";
+const XHTML_XMLNS: &str = "http://www.w3.org/1999/xhtml";
 const SALEOR_REF_XMLNS: &str = "http://app-sitemap-generator.kremik.sk/xml-schemas/saleor-ref.xsd";
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
-#[serde(rename = "urlset")]
+/**
+ * Urls in the order they got added, indexed by the items they're made of. Only iterates the urls
+ * immutably, changes go through methods that keep the index up to date and remember what changed
+ * for [`store::UrlStore`]. Removed urls leave holes, so removing doesn't shift the urls after them,
+ * and the holes get dropped once there are more of them than urls.
+ */
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename = "urlset", from = "StoredUrlSet")]
 pub struct UrlSet {
-    pub urls: Vec<Url>,
+    #[serde(serialize_with = "serialize_urls")]
+    urls: Vec<Option<Url>>,
+    /// Holes in `urls`
+    #[serde(skip)]
+    removed: usize,
+    /// Translated slugs of items by their id, then Saleor language code
+    translated_slugs: HashMap<String, HashMap<String, String>>,
+    #[serde(skip)]
+    index: UrlIndex,
+    /// Ids of items whose urls changed since [`Self::take_changes`]
+    #[serde(skip)]
+    changed: HashSet<String>,
+    /// Ids of items whose translated slugs changed since [`Self::take_changes`]
+    #[serde(skip)]
+    changed_slugs: HashSet<String>,
+}
+
+fn serialize_urls<S: Serializer>(urls: &[Option<Url>], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(urls.iter().flatten())
+}
+
+/// [`UrlSet`] as it's stored, the index gets rebuilt on load
+#[derive(Deserialize)]
+struct StoredUrlSet {
+    urls: Vec<Url>,
+    #[serde(default)]
+    translated_slugs: HashMap<String, HashMap<String, String>>,
+}
+
+/**
+ * Positions of urls by the id of their item and of their related item. Related entries can go
+ * stale when urls move to another related item, so lookups check what they find.
+ */
+#[derive(Debug, Clone, Default)]
+struct UrlIndex {
+    by_id: HashMap<String, usize>,
+    by_related: HashMap<String, HashSet<usize>>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -30,6 +79,30 @@ pub struct Url {
     pub url: String,
     pub data: ItemData,
     pub related: Option<ItemData>,
+    /// Product images, for the image sitemap extension
+    #[serde(default)]
+    pub images: Vec<Image>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Image {
+    /// Id of the product media, so media events can find it
+    pub id: String,
+    pub url: String,
+}
+
+impl Image {
+    /// Image entries of product media, videos can't go into the image sitemap
+    pub fn from_media(media: &[ProductMedia]) -> Vec<Self> {
+        media
+            .iter()
+            .filter(|m| m.typ == ProductMediaType::Image)
+            .map(|m| Self {
+                id: m.id.inner().to_owned(),
+                url: m.url.clone(),
+            })
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -37,6 +110,32 @@ pub struct ItemData {
     pub id: String,
     pub slug: String,
     pub typ: ItemType,
+    /// Missing in databases written by older versions
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Parses the `DateTime` scalar saleor sends, ignoring ones that aren't RFC 3339
+pub fn parse_datetime(datetime: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(datetime)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn to_saleor_datetime(datetime: Option<DateTime<Utc>>) -> Option<event_subjects_updated::DateTime> {
+    datetime.map(|d| event_subjects_updated::DateTime(d.to_rfc3339()))
+}
+
+fn to_saleor_media(product_id: &str, images: Vec<Image>) -> Vec<ProductMedia> {
+    images
+        .into_iter()
+        .map(|i| ProductMedia {
+            id: cynic::Id::new(i.id),
+            product_id: Some(cynic::Id::new(product_id)),
+            url: i.url,
+            typ: ProductMediaType::Image,
+        })
+        .collect()
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
@@ -49,34 +148,187 @@ pub enum ItemType {
 
 impl UrlSet {
     pub fn new() -> Self {
-        Self { urls: vec![] }
+        Self::default()
+    }
+
+    pub fn push(&mut self, url: Url) {
+        self.changed.insert(url.data.id.clone());
+        self.urls.push(Some(url));
+        self.index_url(self.urls.len() - 1);
     }
 
-    pub fn flush_related(&mut self, id: &str) {
-        self.retain(|u| u.data.id != id && u.related.as_ref().is_some_and(|ud| ud.id != id));
+    pub fn iter(&self) -> impl Iterator<Item = &Url> {
+        self.urls.iter().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len() - self.removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes urls of item `id` and ones related to it. Returns whether there were any
+    pub fn flush_related(&mut self, id: &str) -> bool {
+        if self.translated_slugs.remove(id).is_some() {
+            self.changed_slugs.insert(id.to_owned());
+        }
+        let positions = self.positions(id);
+        if positions.is_empty() {
+            return false;
+        }
+        for i in positions {
+            if let Some(url) = &self.urls[i] {
+                self.changed.insert(url.data.id.clone());
+            }
+            self.remove_at(i);
+        }
+        if self.removed > self.len() {
+            self.compact();
+        }
+        true
+    }
+
+    /**
+     * Updates what changed about an item without changing its url, like its timestamp or images.
+     * `images` of `None` keeps the stored ones. Returns whether anything changed.
+     */
+    pub fn refresh(&mut self, item: &ItemData, images: Option<&[Image]>) -> bool {
+        let mut changed = false;
+        let positions = self.positions(&item.id);
+        for url in Self::urls_at_mut(&mut self.urls, &positions) {
+            let mut url_changed = false;
+            if url.data.id == item.id {
+                if item.updated_at.is_some() && url.data.updated_at != item.updated_at {
+                    url.data.updated_at = item.updated_at;
+                    url_changed = true;
+                }
+                if let Some(images) = images.filter(|i| url.images != *i) {
+                    url.images = images.to_vec();
+                    url_changed = true;
+                }
+            }
+            if let Some(related) = url.related.as_mut().filter(|r| {
+                r.id == item.id && item.updated_at.is_some() && r.updated_at != item.updated_at
+            }) {
+                related.updated_at = item.updated_at;
+                url_changed = true;
+            }
+            if url_changed {
+                self.changed.insert(url.data.id.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /**
+     * Replaces the image of the product media `media_id`, `None` removes it. Returns whether the
+     * product was found and anything changed.
+     */
+    pub fn set_image(&mut self, product_id: &str, media_id: &str, image: Option<Image>) -> bool {
+        let Some(url) = self
+            .index
+            .by_id
+            .get(product_id)
+            .and_then(|i| self.urls.get_mut(*i))
+            .and_then(Option::as_mut)
+            .filter(|u| u.data.typ == ItemType::Product)
+        else {
+            return false;
+        };
+        match (url.images.iter().position(|i| i.id == media_id), image) {
+            (Some(i), Some(image)) if url.images[i] != image => url.images[i] = image,
+            (Some(i), None) => {
+                url.images.remove(i);
+            }
+            (None, Some(image)) => url.images.push(image),
+            _ => return false,
+        }
+        self.changed.insert(product_id.to_owned());
+        true
+    }
+
+    /// Sets the slug item `id` has in `language_code`, `None` removes it. Returns whether it changed
+    pub fn set_translated_slug(
+        &mut self,
+        id: &str,
+        language_code: &str,
+        slug: Option<String>,
+    ) -> bool {
+        let changed = match slug.filter(|s| !s.is_empty()) {
+            Some(slug) => {
+                self.translated_slugs
+                    .entry(id.to_owned())
+                    .or_default()
+                    .insert(language_code.to_owned(), slug.clone())
+                    != Some(slug)
+            }
+            None => self
+                .translated_slugs
+                .get_mut(id)
+                .and_then(|slugs| slugs.remove(language_code))
+                .is_some(),
+        };
+        if changed {
+            self.changed_slugs.insert(id.to_owned());
+        }
+        changed
     }
 
-    pub fn find_related(&mut self, id: &str) -> Vec<&mut Url> {
-        self.iter_mut()
-            .filter(|u| u.data.id == id || u.related.as_ref().is_some_and(|ud| ud.id == id))
+    /// Copy of `url` with the slugs of it and its related item translated to `language_code`,
+    /// where there are translations
+    pub fn localized(&self, url: &Url, language_code: &str) -> Url {
+        let translate = |item: &mut ItemData| {
+            if let Some(slug) = self
+                .translated_slugs
+                .get(&item.id)
+                .and_then(|slugs| slugs.get(language_code))
+            {
+                item.slug.clone_from(slug);
+            }
+        };
+        let mut url = url.clone();
+        translate(&mut url.data);
+        if let Some(related) = url.related.as_mut() {
+            translate(related);
+        }
+        url
+    }
+
+    pub fn find_related(&self, id: &str) -> Vec<&Url> {
+        self.positions(id)
+            .into_iter()
+            .filter_map(|i| self.urls[i].as_ref())
             .collect()
     }
 
+    /// Urls whose slug of item `id` isn't `slug` anymore. They count as changed, as callers are
+    /// expected to update them
     pub fn find_affected(&mut self, id: &str, slug: &str) -> AffectedResult<'_> {
-        let related: Vec<&mut Url> = self.find_related(id);
-        debug!("related urls: {:?}", &related);
+        let related = self.positions(id);
+        debug!("related urls: {:?}", self.find_related(id));
         if related.is_empty() {
             return AffectedResult::NoneRelated;
         }
 
         let affected = related
             .into_iter()
-            .filter(|u| {
-                (u.data.id == id && u.data.slug != slug)
-                    || u.related
-                        .as_ref()
-                        .is_some_and(|r| (r.id == id && r.slug != slug))
+            .filter(|i| {
+                self.urls[*i].as_ref().is_some_and(|u| {
+                    (u.data.id == id && u.data.slug != slug)
+                        || u.related
+                            .as_ref()
+                            .is_some_and(|r| r.id == id && r.slug != slug)
+                })
             })
+            .collect::<Vec<_>>();
+        for url in affected.iter().filter_map(|i| self.urls[*i].as_ref()) {
+            self.changed.insert(url.data.id.clone());
+        }
+        let affected = Self::urls_at_mut(&mut self.urls, &affected)
+            .into_iter()
             .map(|u| match u.data.id == id {
                 true => AffectedType::Data(u),
                 false => AffectedType::RelatedData(u),
@@ -88,8 +340,124 @@ impl UrlSet {
 
         AffectedResult::Some(affected)
     }
+
+    /// Sorted positions of urls of item `id` and ones related to it
+    fn positions(&self, id: &str) -> Vec<usize> {
+        let mut positions = self
+            .index
+            .by_id
+            .get(id)
+            .into_iter()
+            .chain(self.index.by_related.get(id).into_iter().flatten())
+            .copied()
+            .filter(|i| {
+                self.urls.get(*i).and_then(Option::as_ref).is_some_and(|u| {
+                    u.data.id == id || u.related.as_ref().is_some_and(|r| r.id == id)
+                })
+            })
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+
+    /// Mutable references to urls at sorted, deduplicated `positions`, skipping holes
+    fn urls_at_mut<'a>(urls: &'a mut [Option<Url>], positions: &[usize]) -> Vec<&'a mut Url> {
+        let mut found = Vec::with_capacity(positions.len());
+        let mut rest = urls;
+        let mut offset = 0;
+        for i in positions {
+            let (_, tail) = std::mem::take(&mut rest).split_at_mut(i - offset);
+            let Some((url, tail)) = tail.split_first_mut() else {
+                break;
+            };
+            found.extend(url.as_mut());
+            rest = tail;
+            offset = i + 1;
+        }
+        found
+    }
+
+    fn index_url(&mut self, i: usize) {
+        let Some(url) = &self.urls[i] else {
+            return;
+        };
+        self.index.by_id.insert(url.data.id.clone(), i);
+        if let Some(related) = url.related.as_ref() {
+            self.index
+                .by_related
+                .entry(related.id.clone())
+                .or_default()
+                .insert(i);
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.index = UrlIndex::default();
+        for i in 0..self.urls.len() {
+            self.index_url(i);
+        }
+    }
+
+    /// Leaves a hole at `i`. Only its id leaves the index, related entries are checked on lookup
+    fn remove_at(&mut self, i: usize) {
+        let Some(url) = self.urls[i].take() else {
+            return;
+        };
+        if self.index.by_id.get(&url.data.id) == Some(&i) {
+            self.index.by_id.remove(&url.data.id);
+        }
+        self.removed += 1;
+    }
+
+    /// Drops the holes left by removed urls, moving the rest, so it rebuilds the index
+    fn compact(&mut self) {
+        if self.removed == 0 {
+            return;
+        }
+        self.urls.retain(Option::is_some);
+        self.removed = 0;
+        self.reindex();
+    }
+}
+
+impl From<StoredUrlSet> for UrlSet {
+    fn from(stored: StoredUrlSet) -> Self {
+        let mut url_set = Self {
+            urls: stored.urls.into_iter().map(Some).collect(),
+            translated_slugs: stored.translated_slugs,
+            ..Default::default()
+        };
+        url_set.reindex();
+        url_set
+    }
+}
+
+impl FromIterator<Url> for UrlSet {
+    fn from_iter<T: IntoIterator<Item = Url>>(iter: T) -> Self {
+        let mut url_set = Self::new();
+        url_set.extend(iter);
+        url_set
+    }
+}
+
+impl Extend<Url> for UrlSet {
+    fn extend<T: IntoIterator<Item = Url>>(&mut self, iter: T) {
+        for url in iter {
+            self.push(url);
+        }
+    }
 }
 
+/// Pending changes and the index don't count, only what gets stored
+impl PartialEq for UrlSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter()) && self.translated_slugs == other.translated_slugs
+    }
+}
+
+impl Eq for UrlSet {}
+
 #[derive(Debug)]
 pub enum AffectedResult<'a> {
     Some(Vec<AffectedType<&'a mut Url>>),
@@ -103,23 +471,10 @@ pub enum AffectedType<T> {
     RelatedData(T),
 }
 
-impl Deref for UrlSet {
-    type Target = Vec<Url>;
-    fn deref(&self) -> &Self::Target {
-        &self.urls
-    }
-}
-
-impl DerefMut for UrlSet {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.urls
-    }
-}
-
 impl Url {
     pub fn new<T: Serialize>(
         data: T,
-        sitemap_config: &SitemapConfig,
+        settings: &SitemapSettings,
         item: ItemData,
         rel_item: Option<ItemData>,
     ) -> Result<Self, NewUrlError> {
@@ -128,10 +483,10 @@ impl Url {
         tt.add_template(
             "t",
             match item.typ {
-                ItemType::Category => &sitemap_config.category_template,
-                ItemType::Page => &sitemap_config.pages_template,
-                ItemType::Collection => &sitemap_config.collection_template,
-                ItemType::Product => &sitemap_config.product_template,
+                ItemType::Category => &settings.category_template,
+                ItemType::Page => &settings.pages_template,
+                ItemType::Collection => &settings.collection_template,
+                ItemType::Product => &settings.product_template,
             },
         )?;
         let url = tt.render("t", &data)?;
@@ -139,8 +494,16 @@ impl Url {
             url,
             data: item,
             related: rel_item,
+            images: vec![],
         })
     }
+
+    /// Newest change of the item or the one it's related to, as both change the url
+    pub fn lastmod(&self) -> Option<DateTime<Utc>> {
+        self.data
+            .updated_at
+            .max(self.related.as_ref().and_then(|r| r.updated_at))
+    }
     pub fn into_event_updated_body(self, slug_postfix: &str) -> (String, EitherWebhookType) {
         match self.data.typ.clone() {
             ItemType::Product => {
@@ -195,14 +558,18 @@ impl Url {
 
 impl From<Url> for ProductCreated {
     fn from(value: Url) -> Self {
+        let media = to_saleor_media(&value.data.id, value.images);
         Self {
             product: Some(Product {
                 slug: value.data.slug,
                 id: cynic::Id::new(value.data.id),
+                updated_at: to_saleor_datetime(value.data.updated_at),
                 category: value.related.map(|c| Category {
                     slug: c.slug,
                     id: cynic::Id::new(c.id),
+                    updated_at: to_saleor_datetime(c.updated_at),
                 }),
+                media: Some(media),
             }),
         }
     }
@@ -214,6 +581,7 @@ impl From<Url> for CategoryCreated {
             category: Some(Category2 {
                 slug: value.data.slug,
                 id: cynic::Id::new(value.data.id),
+                updated_at: to_saleor_datetime(value.data.updated_at),
             }),
         }
     }
@@ -243,14 +611,18 @@ impl From<Url> for PageCreated {
 
 impl From<Url> for ProductDeleted {
     fn from(value: Url) -> Self {
+        let media = to_saleor_media(&value.data.id, value.images);
         Self {
             product: Some(Product {
                 slug: value.data.slug,
                 id: cynic::Id::new(value.data.id),
+                updated_at: to_saleor_datetime(value.data.updated_at),
                 category: value.related.map(|c| Category {
                     slug: c.slug,
                     id: cynic::Id::new(c.id),
+                    updated_at: to_saleor_datetime(c.updated_at),
                 }),
+                media: Some(media),
             }),
         }
     }
@@ -262,6 +634,7 @@ impl From<Url> for CategoryDeleted {
             category: Some(Category2 {
                 slug: value.data.slug,
                 id: cynic::Id::new(value.data.id),
+                updated_at: to_saleor_datetime(value.data.updated_at),
             }),
         }
     }
@@ -2,10 +2,10 @@ use cynic::{http::SurfExt, QueryBuilder};
 use tracing::{debug, error, info};
 
 use crate::{
-    app::AppState,
+    app::{AppState, SitemapSettings},
     queries::{
         event_subjects_updated::{
-            CategoryCreated, CollectionCreated, Page, PageCreated, ProductCreated,
+            CategoryCreated, CollectionCreated, DateTime, Page, PageCreated, ProductCreated,
         },
         get_all_categories::{
             Category3, GetCategoriesInitial, GetCategoriesNext, GetCategoriesNextVariables,
@@ -16,25 +16,34 @@ use crate::{
         get_all_pages::{self, GetPagesInitial, GetPagesNext, GetPagesNextVariables},
         get_all_products::{
             GetProductsInitial, GetProductsInitialVariables, GetProductsNext,
-            GetProductsNextVariables, Product,
+            GetProductsNextVariables, Product, ProductMediaType,
+        },
+        get_all_translations::{
+            GetTranslations, GetTranslationsVariables, LanguageCodeEnum, TranslatableItem,
+            TranslatableKinds,
         },
     },
     sitemap::{
-        event_handler::{write_db_to_file, write_url_set_to_file},
+        event_handler::write_url_set_to_file, parse_datetime, store::write_db_to_file, Image,
         ItemData, ItemType, Url, UrlSet,
     },
 };
 
-pub async fn regenerate(state: AppState, saleor_api_url: String) -> anyhow::Result<()> {
+pub async fn regenerate(
+    state: AppState,
+    saleor_api_url: String,
+    settings: SitemapSettings,
+) -> anyhow::Result<()> {
     info!("regeneration: fetching all categories, products, collections, pages");
-    let app = state.saleor_app.lock().await;
-    let auth_data = app.apl.get(&saleor_api_url).await?;
+    // Other requests need the app while all items get paginated through
+    let apl = state.saleor_app.lock().await.apl.clone();
+    let auth_data = apl.get(&saleor_api_url).await?;
 
     let pages = get_all_pages(&saleor_api_url, &auth_data.token).await?;
     let collections = get_all_collections(&saleor_api_url, &auth_data.token).await?;
     let categories = get_all_categories(&saleor_api_url, &auth_data.token).await?;
     let products =
-        get_all_products(&saleor_api_url, &state.target_channel, &auth_data.token).await?;
+        get_all_products(&saleor_api_url, &settings.channel_slug, &auth_data.token).await?;
     info!(
         "regeneration: found {} products, {} categories, {} pages, {} collections",
         products.len(),
@@ -45,139 +54,153 @@ pub async fn regenerate(state: AppState, saleor_api_url: String) -> anyhow::Resu
     info!("regeneration: creating sitemap data");
     let mut url_set = UrlSet::new();
 
-    url_set.urls.append(
-        &mut pages
-            .into_iter()
-            .filter_map(|p| {
-                match Url::new(
-                    PageCreated {
-                        page: Some(Page {
-                            id: p.id.clone(),
-                            slug: p.slug.clone(),
-                        }),
-                    },
-                    &state.sitemap_config,
-                    ItemData {
-                        id: p.id.inner().to_owned(),
-                        slug: p.slug.clone(),
-                        typ: ItemType::Page,
-                    },
-                    None,
-                ) {
-                    Ok(u) => Some(u),
-                    Err(e) => {
-                        error!("Error creating Url from page {:?}, {:?}", &p, e);
-                        None
-                    }
-                }
-            })
-            .collect::<Vec<_>>(),
-    );
+    url_set.extend(pages.into_iter().filter_map(|p| {
+        match Url::new(
+            PageCreated {
+                page: Some(Page {
+                    id: p.id.clone(),
+                    slug: p.slug.clone(),
+                }),
+            },
+            &settings,
+            ItemData {
+                id: p.id.inner().to_owned(),
+                slug: p.slug.clone(),
+                typ: ItemType::Page,
+                updated_at: None,
+            },
+            None,
+        ) {
+            Ok(u) => Some(u),
+            Err(e) => {
+                error!("Error creating Url from page {:?}, {:?}", &p, e);
+                None
+            }
+        }
+    }));
 
-    url_set.urls.append(
-        &mut collections
-            .into_iter()
-            .filter_map(|p| {
-                match Url::new(
-                    CollectionCreated {
-                        collection: Some(crate::queries::event_subjects_updated::Collection {
-                            id: p.id.clone(),
-                            slug: p.slug.clone(),
-                        }),
-                    },
-                    &state.sitemap_config,
-                    ItemData {
-                        id: p.id.inner().to_owned(),
-                        slug: p.slug.clone(),
-                        typ: ItemType::Collection,
-                    },
-                    None,
-                ) {
-                    Ok(u) => Some(u),
-                    Err(e) => {
-                        error!("Error creating Url from collection {:?}, {:?}", &p, e);
-                        None
-                    }
-                }
-            })
-            .collect::<Vec<_>>(),
-    );
+    url_set.extend(collections.into_iter().filter_map(|p| {
+        match Url::new(
+            CollectionCreated {
+                collection: Some(crate::queries::event_subjects_updated::Collection {
+                    id: p.id.clone(),
+                    slug: p.slug.clone(),
+                }),
+            },
+            &settings,
+            ItemData {
+                id: p.id.inner().to_owned(),
+                slug: p.slug.clone(),
+                typ: ItemType::Collection,
+                updated_at: None,
+            },
+            None,
+        ) {
+            Ok(u) => Some(u),
+            Err(e) => {
+                error!("Error creating Url from collection {:?}, {:?}", &p, e);
+                None
+            }
+        }
+    }));
 
-    url_set.urls.append(
-        &mut categories
-            .into_iter()
-            .filter_map(|p| {
-                match Url::new(
-                    CategoryCreated {
-                        category: Some(crate::queries::event_subjects_updated::Category2 {
-                            id: p.id.clone(),
-                            slug: p.slug.clone(),
-                        }),
-                    },
-                    &state.sitemap_config,
-                    ItemData {
-                        id: p.id.inner().to_owned(),
-                        slug: p.slug.clone(),
-                        typ: ItemType::Category,
-                    },
-                    None,
-                ) {
-                    Ok(u) => Some(u),
-                    Err(e) => {
-                        error!("Error creating Url from category {:?}, {:?}", &p, e);
-                        None
-                    }
-                }
-            })
-            .collect::<Vec<_>>(),
-    );
+    url_set.extend(categories.into_iter().filter_map(|p| {
+        match Url::new(
+            CategoryCreated {
+                category: Some(crate::queries::event_subjects_updated::Category2 {
+                    id: p.id.clone(),
+                    slug: p.slug.clone(),
+                    updated_at: Some(DateTime(p.updated_at.0.clone())),
+                }),
+            },
+            &settings,
+            ItemData {
+                id: p.id.inner().to_owned(),
+                slug: p.slug.clone(),
+                typ: ItemType::Category,
+                updated_at: parse_datetime(&p.updated_at.0),
+            },
+            None,
+        ) {
+            Ok(u) => Some(u),
+            Err(e) => {
+                error!("Error creating Url from category {:?}, {:?}", &p, e);
+                None
+            }
+        }
+    }));
 
-    url_set.urls.append(
-        &mut products
-            .into_iter()
-            .filter_map(|p| {
-                match Url::new(
-                    ProductCreated {
-                        product: Some(crate::queries::event_subjects_updated::Product {
-                            id: p.id.clone(),
-                            slug: p.slug.clone(),
-                            category: p.category.clone().map(|c| {
-                                crate::queries::event_subjects_updated::Category {
-                                    slug: c.slug,
-                                    id: c.id,
-                                }
-                            }),
-                        }),
-                    },
-                    &state.sitemap_config,
-                    ItemData {
-                        id: p.id.inner().to_owned(),
-                        slug: p.slug.clone(),
-                        typ: ItemType::Product,
-                    },
-                    p.category.clone().map(|c| ItemData {
-                        id: c.id.inner().to_owned(),
-                        slug: c.slug,
-                        typ: ItemType::Category,
+    url_set.extend(products.into_iter().filter_map(|p| {
+        match Url::new(
+            ProductCreated {
+                product: Some(crate::queries::event_subjects_updated::Product {
+                    id: p.id.clone(),
+                    slug: p.slug.clone(),
+                    updated_at: Some(DateTime(p.updated_at.0.clone())),
+                    category: p.category.clone().map(|c| {
+                        crate::queries::event_subjects_updated::Category {
+                            slug: c.slug,
+                            id: c.id,
+                            updated_at: Some(DateTime(c.updated_at.0)),
+                        }
                     }),
-                ) {
-                    Ok(u) => Some(u),
-                    Err(e) => {
-                        error!("Error creating Url from product{:?}, {:?}", &p, e);
-                        None
-                    }
-                }
-            })
-            .collect::<Vec<_>>(),
-    );
+                    media: None,
+                }),
+            },
+            &settings,
+            ItemData {
+                id: p.id.inner().to_owned(),
+                slug: p.slug.clone(),
+                typ: ItemType::Product,
+                updated_at: parse_datetime(&p.updated_at.0),
+            },
+            p.category.clone().map(|c| ItemData {
+                id: c.id.inner().to_owned(),
+                slug: c.slug,
+                typ: ItemType::Category,
+                updated_at: parse_datetime(&c.updated_at.0),
+            }),
+        ) {
+            Ok(u) => Some(Url {
+                images: product_images(&p),
+                ..u
+            }),
+            Err(e) => {
+                error!("Error creating Url from product{:?}, {:?}", &p, e);
+                None
+            }
+        }
+    }));
+
+    for language_code in state.sitemap_config.language_codes() {
+        for (id, slug) in
+            get_all_translated_slugs(&saleor_api_url, language_code, &auth_data.token).await?
+        {
+            url_set.set_translated_slug(&id, language_code, Some(slug));
+        }
+    }
 
     info!("regeneration: creating sitemap file");
-    write_db_to_file(&url_set, &state.sitemap_config.target_folder).await?;
-    write_url_set_to_file(&url_set, &state.sitemap_config.target_folder).await?;
+    let target_folder = state.sitemap_config.instance_folder(&saleor_api_url);
+    write_db_to_file(&url_set, &target_folder)?;
+    write_url_set_to_file(&url_set, &target_folder, &settings, &state.sitemap_config).await?;
     debug!("Wrote all files to disk");
     Ok(())
 }
 
+fn product_images(product: &Product) -> Vec<Image> {
+    product
+        .media
+        .iter()
+        .flatten()
+        .filter(|m| m.typ == ProductMediaType::Image)
+        .map(|m| Image {
+            id: m.id.inner().to_owned(),
+            url: m.url.clone(),
+        })
+        .collect()
+}
+
 async fn get_all_pages(
     saleor_api_url: &str,
     token: &str,
@@ -417,3 +440,73 @@ async fn get_all_products(
     info!("All products collected...");
     Ok(all_categorised_products)
 }
+
+/**
+ * Gets the `(id, slug)` of every product, category, collection and page translated to
+ * `language_code` that has a translated slug
+ */
+async fn get_all_translated_slugs(
+    saleor_api_url: &str,
+    language_code: &str,
+    token: &str,
+) -> anyhow::Result<Vec<(String, String)>> {
+    debug!("Collecting all {language_code} slugs...");
+    let mut all_slugs = vec![];
+    for kind in [
+        TranslatableKinds::Product,
+        TranslatableKinds::Category,
+        TranslatableKinds::Collection,
+        TranslatableKinds::Page,
+    ] {
+        let mut next_cursor: Option<String> = None;
+        loop {
+            let res = surf::post(saleor_api_url)
+                .header("authorization-bearer", token)
+                .run_graphql(GetTranslations::build(GetTranslationsVariables {
+                    kind,
+                    language_code: LanguageCodeEnum(language_code.to_owned()),
+                    after: next_cursor.as_deref(),
+                }))
+                .await;
+            if let Ok(query) = &res
+                && let Some(data) = &query.data
+                && let Some(translations) = &data.translations
+            {
+                all_slugs.extend(translations.edges.iter().filter_map(|e| {
+                    let (id, slug) = match &e.node {
+                        TranslatableItem::ProductTranslatableContent(c) => {
+                            (&c.product_id, c.translation.as_ref()?.slug.clone())
+                        }
+                        TranslatableItem::CategoryTranslatableContent(c) => {
+                            (&c.category_id, c.translation.as_ref()?.slug.clone())
+                        }
+                        TranslatableItem::CollectionTranslatableContent(c) => {
+                            (&c.collection_id, c.translation.as_ref()?.slug.clone())
+                        }
+                        TranslatableItem::PageTranslatableContent(c) => {
+                            (&c.page_id, c.translation.as_ref()?.slug.clone())
+                        }
+                        TranslatableItem::Unknown => return None,
+                    };
+                    Some((id.inner().to_owned(), slug.filter(|s| !s.is_empty())?))
+                }));
+                debug!(
+                    "fetched {kind:?} translations, eg.:{:?}",
+                    &translations.edges.first()
+                );
+                match translations.page_info.has_next_page {
+                    true => next_cursor.clone_from(&translations.page_info.end_cursor),
+                    false => break,
+                }
+                if next_cursor.is_none() {
+                    break;
+                }
+            } else {
+                error!("Failed fetching {kind:?} translations! {:?}", &res);
+                anyhow::bail!("Failed fetching {kind:?} translations! {:?}", res);
+            }
+        }
+    }
+    info!("All {language_code} slugs collected...");
+    Ok(all_slugs)
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::{
+    event_handler::{write_atomically, UrlSetFileOperationsErr},
+    Url, UrlSet,
+};
+
+pub const DB_FILE_NAME: &str = "db.cbor";
+const JOURNAL_FILE_NAME: &str = "db.journal";
+/// Journals shorter than this don't get compacted, even into a small snapshot
+const MIN_COMPACTED_CHANGES: usize = 1000;
+
+/// Change to a [`UrlSet`], as it's appended to the journal
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Change {
+    /// Url of an item got added or changed
+    Put(Url),
+    /// Url of an item got removed
+    Remove(String),
+    /// Translated slugs of an item, `None` once it has none
+    Slugs(String, Option<HashMap<String, String>>),
+}
+
+/**
+ * Urls of a saleor instance, kept in memory between events. `db.cbor` is a snapshot that changes
+ * get appended to in `db.journal`, so an event only writes what it changed. Once the journal has
+ * more changes than there are urls, it gets compacted into a new snapshot.
+ */
+#[derive(Debug)]
+pub struct UrlStore {
+    target_folder: String,
+    pub url_set: UrlSet,
+    journaled: usize,
+}
+
+impl UrlStore {
+    /// Loads the urls stored in `target_folder`, there being none yet is fine
+    pub fn open(target_folder: &str) -> Result<Self, UrlSetFileOperationsErr> {
+        let (url_set, mut journaled, cut_short) = read_db(target_folder)?;
+        debug!(
+            "loaded {} urls from {target_folder}, {journaled} of the changes from the journal",
+            url_set.len()
+        );
+        // changes appended after a broken one would never get replayed
+        if cut_short {
+            write_db_to_file(&url_set, target_folder)?;
+            journaled = 0;
+        }
+        Ok(Self {
+            target_folder: target_folder.to_owned(),
+            url_set,
+            journaled,
+        })
+    }
+
+    /// Persists what changed in [`Self::url_set`] since the last commit. Returns whether anything did
+    pub fn commit(&mut self) -> Result<bool, UrlSetFileOperationsErr> {
+        let changes = self.url_set.take_changes();
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        self.journaled += changes.len();
+        if self.journaled > self.url_set.len().max(MIN_COMPACTED_CHANGES) {
+            debug!("compacting journal of {}", &self.target_folder);
+            write_db_to_file(&self.url_set, &self.target_folder)?;
+            self.journaled = 0;
+            return Ok(true);
+        }
+        let mut bytes = vec![];
+        for change in changes.iter() {
+            bytes.append(&mut serde_cbor::to_vec(change)?);
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("{}/{JOURNAL_FILE_NAME}", self.target_folder))?
+            .write_all(&bytes)?;
+        Ok(true)
+    }
+}
+
+impl UrlSet {
+    /// Changes since the last call, for [`UrlStore::commit`]
+    fn take_changes(&mut self) -> Vec<Change> {
+        let mut changes = vec![];
+        let mut changed = vec![];
+        for id in std::mem::take(&mut self.changed) {
+            match self.index.by_id.get(&id).copied() {
+                Some(i) => changed.push(i),
+                None => changes.push(Change::Remove(id)),
+            }
+        }
+        // new urls get replayed in the order they were added
+        changed.sort_unstable();
+        for i in changed {
+            // its related item might have changed
+            self.index_url(i);
+            changes.extend(self.urls[i].clone().map(Change::Put));
+        }
+        for id in std::mem::take(&mut self.changed_slugs) {
+            let slugs = self.translated_slugs.get(&id).cloned();
+            changes.push(Change::Slugs(id, slugs));
+        }
+        changes
+    }
+
+    /// Replays a journaled change, without counting it as a new one. Removals leave holes until
+    /// the replay is done and [`UrlSet::compact`] drops them all at once
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Put(url) => match self.index.by_id.get(&url.data.id).copied() {
+                Some(i) => {
+                    self.urls[i] = Some(url);
+                    self.index_url(i);
+                }
+                None => {
+                    self.urls.push(Some(url));
+                    self.index_url(self.urls.len() - 1);
+                }
+            },
+            Change::Remove(id) => {
+                if let Some(i) = self.index.by_id.get(&id).copied() {
+                    self.remove_at(i);
+                }
+            }
+            Change::Slugs(id, Some(slugs)) => {
+                self.translated_slugs.insert(id, slugs);
+            }
+            Change::Slugs(id, None) => {
+                self.translated_slugs.remove(&id);
+            }
+        }
+    }
+}
+
+/// Snapshot with the journal replayed on top, how many changes the journal had and whether it ended
+/// with a broken one
+fn read_db(target_folder: &str) -> Result<(UrlSet, usize, bool), UrlSetFileOperationsErr> {
+    let mut url_set = match fs::read(format!("{target_folder}/{DB_FILE_NAME}")) {
+        Ok(snapshot) => serde_cbor::de::from_slice(&snapshot)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => UrlSet::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let journal = match fs::read(format!("{target_folder}/{JOURNAL_FILE_NAME}")) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    let mut journaled = 0;
+    let mut cut_short = false;
+    for change in serde_cbor::Deserializer::from_slice(&journal).into_iter::<Change>() {
+        match change {
+            Ok(change) => {
+                url_set.apply(change);
+                journaled += 1;
+            }
+            // an append cut short, everything before it is still fine
+            Err(e) => {
+                warn!(
+                    "ignoring the end of {target_folder}/{JOURNAL_FILE_NAME}, {:?}",
+                    e
+                );
+                cut_short = true;
+                break;
+            }
+        }
+    }
+    url_set.compact();
+    Ok((url_set, journaled, cut_short))
+}
+
+pub fn get_db_from_file(target_folder: &str) -> Result<UrlSet, UrlSetFileOperationsErr> {
+    Ok(read_db(target_folder)?.0)
+}
+
+/// Replaces the snapshot with `url_set` and drops the journal, as it's already part of it
+pub fn write_db_to_file(
+    url_set: &UrlSet,
+    target_folder: &str,
+) -> Result<(), UrlSetFileOperationsErr> {
+    write_atomically(target_folder, DB_FILE_NAME, serde_cbor::to_vec(url_set)?)?;
+    match fs::remove_file(format!("{target_folder}/{JOURNAL_FILE_NAME}")) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::SecondsFormat;
+
+use tracing::error;
+
+use super::{alternates::Alternates, Url, UrlSet, IMAGE_SITEMAP_XMLNS, SITEMAP_XMLNS, XHTML_XMLNS};
+use crate::app::SitemapConfig;
+
+/// Search engines ignore sitemaps with more urls than this
+pub const MAX_URLS_PER_SITEMAP: usize = 50_000;
+/// Same goes for sitemaps bigger than 50MB uncompressed
+pub const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+
+/// Google ignores images past this in a single `<url>`
+pub const MAX_IMAGES_PER_URL: usize = 1000;
+
+const XML_HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+
+/// Escapes the characters XML doesn't allow in text
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `<url>` entry, with `<lastmod>`, the hints configured for its type and images if enabled
+pub fn render_url(url: &Url, sitemap_config: &SitemapConfig) -> String {
+    render_entry(url, &url.url, &[], sitemap_config)
+}
+
+/// [`render_url`] for `loc`, one of the language versions of `url` listed in `alternates`
+fn render_entry(
+    url: &Url,
+    loc: &str,
+    alternates: &[(&str, String)],
+    sitemap_config: &SitemapConfig,
+) -> String {
+    let mut entry = format!("  <url>\n    <loc>{}</loc>\n", escape(loc));
+    if let Some(lastmod) = url.lastmod() {
+        entry.push_str(&format!(
+            "    <lastmod>{}</lastmod>\n",
+            lastmod.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+    }
+    let (changefreq, priority) = sitemap_config.hints(&url.data.typ);
+    if let Some(changefreq) = changefreq {
+        entry.push_str(&format!(
+            "    <changefreq>{}</changefreq>\n",
+            changefreq.as_str()
+        ));
+    }
+    if let Some(priority) = priority {
+        entry.push_str(&format!("    <priority>{priority}</priority>\n"));
+    }
+    for (hreflang, href) in alternates {
+        entry.push_str(&format!(
+            "    <xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}\"/>\n",
+            escape(hreflang),
+            escape(href)
+        ));
+    }
+    if sitemap_config.include_images {
+        for image in url.images.iter().take(MAX_IMAGES_PER_URL) {
+            entry.push_str(&format!(
+                "    <image:image>\n      <image:loc>{}</image:loc>\n    </image:image>\n",
+                escape(&image.url)
+            ));
+        }
+    }
+    entry.push_str("  </url>\n");
+    entry
+}
+
+/**
+ * Renders urls into as many `<urlset>` documents as needed to keep each under `max_urls` and
+ * `max_bytes`. Always returns at least one document, so the index never points nowhere.
+ * With locales configured every language version of an url gets its own entry, all of them
+ * linking to each other.
+ */
+pub fn render_sitemaps(
+    url_set: &UrlSet,
+    sitemap_config: &SitemapConfig,
+    max_urls: usize,
+    max_bytes: usize,
+) -> Vec<String> {
+    let alternates = match Alternates::new(sitemap_config) {
+        Ok(a) => Some(a),
+        Err(e) => {
+            error!(
+                "Bad locale templates, leaving out language versions, {:?}",
+                e
+            );
+            None
+        }
+    };
+    let mut open = format!("{XML_HEADER}<urlset xmlns=\"{SITEMAP_XMLNS}\"");
+    if sitemap_config.include_images {
+        open.push_str(&format!(" xmlns:image=\"{IMAGE_SITEMAP_XMLNS}\""));
+    }
+    if !sitemap_config.locales.is_empty() {
+        open.push_str(&format!(" xmlns:xhtml=\"{XHTML_XMLNS}\""));
+    }
+    open.push_str(">\n");
+    let close = "</urlset>\n";
+
+    let mut sitemaps = vec![];
+    let mut current = open.clone();
+    let mut count = 0;
+    let entries = url_set.iter().flat_map(|url| {
+        let links = alternates
+            .as_ref()
+            .map(|a| a.of(url, url_set))
+            .unwrap_or_default();
+        match links.is_empty() {
+            true => vec![render_url(url, sitemap_config)],
+            false => links
+                .iter()
+                .map(|(_, loc)| render_entry(url, loc, &links, sitemap_config))
+                .collect(),
+        }
+    });
+    for entry in entries {
+        if count > 0 && (count >= max_urls || current.len() + entry.len() + close.len() > max_bytes)
+        {
+            current.push_str(close);
+            sitemaps.push(std::mem::replace(&mut current, open.clone()));
+            count = 0;
+        }
+        current.push_str(&entry);
+        count += 1;
+    }
+    current.push_str(close);
+    sitemaps.push(current);
+    sitemaps
+}
+
+/// Name of the `n`th sitemap, counting from 1
+pub fn sitemap_file_name(n: usize) -> String {
+    format!("sitemap-{n}.xml")
+}
+
+/// `<sitemapindex>` pointing to `count` sitemaps hosted under `index_hostname`
+pub fn render_index(index_hostname: &str, count: usize, lastmod: &str) -> String {
+    let hostname = index_hostname.trim_end_matches('/');
+    let mut index = format!("{XML_HEADER}<sitemapindex xmlns=\"{SITEMAP_XMLNS}\">\n");
+    for n in 1..=count {
+        index.push_str(&format!(
+            "  <sitemap>\n    <loc>{}</loc>\n    <lastmod>{lastmod}</lastmod>\n  </sitemap>\n",
+            escape(&format!("{hostname}/{}", sitemap_file_name(n)))
+        ));
+    }
+    index.push_str("</sitemapindex>\n");
+    index
+}
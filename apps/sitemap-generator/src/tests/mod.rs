@@ -1,10 +1,24 @@
 mod utils;
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
+    app::{ChangeFreq, Locale, SitemapConfig, SitemapSettings},
     create_app,
-    sitemap::{ItemType, UrlSet},
+    queries::event_subjects_updated::{
+        Category, CategoryTranslatableContent, CategoryTranslation, LanguageCodeEnum,
+        LanguageDisplay, Product, ProductCreated, ProductMedia, ProductMediaCreated,
+        ProductMediaDeleted, ProductMediaType, TranslationTypes, TranslationUpdated, EVENTS_QUERY,
+        TRANSLATIONS_QUERY,
+    },
+    routes::webhooks::webhooks,
+    sitemap::{
+        event_handler::write_sitemaps,
+        parse_datetime,
+        store::{get_db_from_file, UrlStore},
+        xml::{render_sitemaps, render_url},
+        Image, ItemData, ItemType, Url, UrlSet,
+    },
 };
 use async_std::task::sleep;
 use axum::{
@@ -14,16 +28,31 @@ use axum::{
 };
 use rstest::*;
 use saleor_app_sdk::{
+    apl::memory_apl::MemoryApl,
     headers::{SALEOR_API_URL_HEADER, SALEOR_EVENT_HEADER},
+    middleware::webhook_router::EMPTY_SUBSCRIPTION,
+    mock_saleor::MockSaleor,
+    settings_manager::instance_config::InstanceConfigs,
     webhooks::{utils::EitherWebhookType, AsyncWebhookEventType},
+    SaleorApp,
 };
+use serde_json::json;
 use serial_test::{parallel, serial};
 use tower::{Service, ServiceExt};
 use tracing::debug;
 use tracing_test::traced_test;
-use utils::{create_query, gen_random_url_set, testing_configs};
+use utils::{
+    create_query, gen_random_url_set, read_sitemap_urls, register_test_instance, testing_configs,
+};
 
 async fn init_test_app() -> RouterIntoService<Body> {
+    init_test_app_with(InstanceConfigs::new(), MemoryApl::new()).await
+}
+
+async fn init_test_app_with(
+    instance_configs: InstanceConfigs<SitemapSettings>,
+    apl: MemoryApl,
+) -> RouterIntoService<Body> {
     if let Err(e) = std::fs::remove_dir_all("./temp/sitemaps") {
         match e.kind() {
             std::io::ErrorKind::NotFound => (),
@@ -32,11 +61,29 @@ async fn init_test_app() -> RouterIntoService<Body> {
     };
     std::fs::create_dir_all("./temp/sitemaps").unwrap();
     std::env::set_var("APP_API_BASE_URL", "http://localhost:3000");
-    let (config, sitemap_config) = testing_configs();
+    let (config, sitemap_config, settings) = testing_configs();
+    register_test_instance(&apl, &config, "https://api.example.com").await;
+    register_test_instance(&apl, &config, "https://api.other.com").await;
 
-    create_app(&config, sitemap_config)
-        .await
-        .into_service::<Body>()
+    // the test instance has no app metadata to fetch settings from
+    instance_configs.insert("https://api.example.com", settings.clone());
+    instance_configs.insert("https://api.other.com", settings.clone());
+
+    let saleor_app = SaleorApp { apl: Arc::new(apl) };
+    create_app(
+        &config,
+        saleor_app,
+        sitemap_config,
+        settings,
+        instance_configs,
+    )
+    .await
+    .into_service::<Body>()
+}
+
+/// Folder the test app writes the sitemap of the instance to
+fn instance_folder(saleor_api_url: &str) -> String {
+    testing_configs().1.instance_folder(saleor_api_url)
 }
 
 #[rstest]
@@ -63,9 +110,9 @@ pub async fn app_runs_and_responses() {
 //TODO: This test is busted or smt
 async fn update_event_updates_correctly() {
     let mut app = init_test_app().await;
-    let (_, sitemap_config) = testing_configs();
+    let (_, _, settings) = testing_configs();
 
-    let mut evn = gen_random_url_set(500, &sitemap_config);
+    let mut evn = gen_random_url_set(500, &settings);
     for (body, _, webhook_type) in evn.clone() {
         app = create_query(app, body, webhook_type).await;
     }
@@ -73,7 +120,7 @@ async fn update_event_updates_correctly() {
     //wait for the file to get written
     sleep(Duration::from_secs(1)).await;
 
-    let file_url = std::fs::read_to_string("./temp/sitemaps/sitemap.txt").unwrap();
+    let file_url = read_sitemap_urls(&instance_folder("https://api.example.com"));
 
     assert_eq!(
         file_url,
@@ -110,7 +157,7 @@ async fn update_event_updates_correctly() {
         .await;
 
         sleep(Duration::from_secs(1)).await;
-        let file_url = std::fs::read_to_string("./temp/sitemaps/sitemap.txt").unwrap();
+        let file_url = read_sitemap_urls(&instance_folder("https://api.example.com"));
         assert_eq!(
             file_url,
             evn.clone()
@@ -157,7 +204,7 @@ async fn update_event_updates_correctly() {
     });
 
     sleep(Duration::from_secs(1)).await;
-    let file_url = std::fs::read_to_string("./temp/sitemaps/sitemap.txt").unwrap();
+    let file_url = read_sitemap_urls(&instance_folder("https://api.example.com"));
     assert_eq!(
         file_url,
         evn.iter()
@@ -173,9 +220,9 @@ async fn update_event_updates_correctly() {
 #[serial]
 async fn updates_sitemap_from_request() {
     let mut app = init_test_app().await;
-    let (_, sitemap_config) = testing_configs();
+    let (_, _, settings) = testing_configs();
 
-    let evn = gen_random_url_set(1, &sitemap_config);
+    let evn = gen_random_url_set(1, &settings);
     let (body, url, webhook_type) = evn.first().cloned().unwrap();
 
     let response = app
@@ -184,6 +231,7 @@ async fn updates_sitemap_from_request() {
         .unwrap()
         .call(
             Request::builder()
+                .method("POST")
                 .uri("/api/webhooks")
                 .header(SALEOR_API_URL_HEADER, "https://api.example.com")
                 .header(
@@ -204,20 +252,210 @@ async fn updates_sitemap_from_request() {
     //wait for the file to get written
     sleep(Duration::from_secs(1)).await;
 
-    let file_url = std::fs::read_to_string("./temp/sitemaps/sitemap.txt").unwrap();
+    let file_url = read_sitemap_urls(&instance_folder("https://api.example.com"));
 
     assert_eq!(file_url, url.url);
 }
 
+#[rstest]
+#[tokio::test]
+#[traced_test]
+#[serial]
+async fn instances_get_separate_sitemaps() {
+    let mut app = init_test_app().await;
+    let (_, sitemap_config, settings) = testing_configs();
+    assert!(sitemap_config
+        .instance_folder("https://api.other.com")
+        .starts_with("./temp/sitemaps/api.other.com-"));
+
+    let evn = gen_random_url_set(2, &settings);
+    for ((body, _, webhook_type), saleor_api_url) in evn
+        .clone()
+        .into_iter()
+        .zip(["https://api.example.com", "https://api.other.com"])
+    {
+        let response = app
+            .ready()
+            .await
+            .unwrap()
+            .call(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/webhooks")
+                    .header(SALEOR_API_URL_HEADER, saleor_api_url)
+                    .header(
+                        SALEOR_EVENT_HEADER,
+                        match webhook_type {
+                            EitherWebhookType::Sync(s) => s.as_ref().to_string(),
+                            EitherWebhookType::Async(a) => a.as_ref().to_string(),
+                        },
+                    )
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    //wait for the files to get written
+    sleep(Duration::from_secs(1)).await;
+
+    let first = read_sitemap_urls(&instance_folder("https://api.example.com"));
+    assert_eq!(first, evn[0].1.url);
+    let second = read_sitemap_urls(&instance_folder("https://api.other.com"));
+    assert_eq!(second, evn[1].1.url);
+}
+
+#[rstest]
+#[tokio::test]
+#[traced_test]
+#[serial]
+async fn manifest_lists_registered_webhooks() {
+    let mut app = init_test_app().await;
+
+    let response = app
+        .ready()
+        .await
+        .unwrap()
+        .call(
+            Request::builder()
+                .uri("/api/manifest")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    // events serialize as SCREAMING_SNAKE_CASE but deserialize from snake_case, so no AppManifest
+    let manifest: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let webhooks = manifest["webhooks"].as_array().unwrap();
+    assert_eq!(webhooks.len(), 2);
+    assert_eq!(webhooks[0]["query"], EMPTY_SUBSCRIPTION);
+    assert_eq!(
+        webhooks[0]["asyncEvents"],
+        json!(["APP_UPDATED"])
+    );
+    assert_eq!(webhooks[1]["query"], EVENTS_QUERY);
+    let events = webhooks[1]["asyncEvents"].as_array().unwrap();
+    assert_eq!(events.len(), 15);
+    assert!(events.contains(&"PRODUCT_UPDATED".into()));
+    assert!(events.contains(&"PRODUCT_MEDIA_DELETED".into()));
+    assert!(webhooks[1].get("syncEvents").is_none());
+}
+
+#[rstest]
+#[tokio::test]
+#[traced_test]
+#[serial]
+async fn mismatched_webhook_payload_is_rejected() {
+    let mut app = init_test_app().await;
+
+    let response = app
+        .ready()
+        .await
+        .unwrap()
+        .call(
+            Request::builder()
+                .method("POST")
+                .uri("/api/webhooks")
+                .header(SALEOR_API_URL_HEADER, "https://api.example.com")
+                .header(
+                    SALEOR_EVENT_HEADER,
+                    AsyncWebhookEventType::ProductUpdated.as_ref(),
+                )
+                .body(Body::from(r#"{"product": 1}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[rstest]
+#[tokio::test]
+#[traced_test]
+#[serial]
+async fn app_updated_drops_cached_settings() {
+    let instance_configs = InstanceConfigs::new();
+    let mut app = init_test_app_with(instance_configs.clone(), MemoryApl::new()).await;
+
+    let response = app
+        .ready()
+        .await
+        .unwrap()
+        .call(
+            Request::builder()
+                .method("POST")
+                .uri("/api/webhooks")
+                .header(SALEOR_API_URL_HEADER, "https://api.other.com")
+                .header(
+                    SALEOR_EVENT_HEADER,
+                    AsyncWebhookEventType::AppUpdated.as_ref(),
+                )
+                .body(Body::from(r#"{"__typename": "AppUpdated"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(instance_configs.cached("https://api.other.com").is_none());
+    assert!(instance_configs.cached("https://api.example.com").is_some());
+}
+
+#[rstest]
+#[tokio::test]
+#[traced_test]
+#[serial]
+async fn webhooks_seed_settings_of_instances_without_them() {
+    let saleor = MockSaleor::start().await.unwrap();
+    saleor.set_query("app", json!({ "metadata": [] }));
+    saleor.on_mutation("updateMetadata", |_| Ok(json!({ "item": null })));
+    saleor.on_mutation("deleteMetadata", |_| Ok(json!({ "item": null })));
+    let apl = MemoryApl::new();
+    saleor.register(&apl, "http://localhost:3000").await.unwrap();
+    let instance_configs = InstanceConfigs::new();
+    let mut app = init_test_app_with(instance_configs.clone(), apl).await;
+    let (_, _, settings) = testing_configs();
+
+    let (body, _, webhook_type) = gen_random_url_set(1, &settings).remove(0);
+    let event = match webhook_type {
+        EitherWebhookType::Sync(s) => s.as_ref().to_string(),
+        EitherWebhookType::Async(a) => a.as_ref().to_string(),
+    };
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/api/webhooks")
+        .body(Body::from(body.clone()))
+        .unwrap();
+    *request.headers_mut() = saleor.webhook_headers(event, body.as_bytes());
+    let response = app.ready().await.unwrap().call(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(saleor.request_count("updateMetadata"), 1);
+    assert_eq!(
+        instance_configs
+            .cached(&saleor.api_url())
+            .map(|s| s.product_template),
+        Some(settings.product_template)
+    );
+}
+
 #[rstest]
 #[tokio::test]
 #[traced_test]
 #[serial]
 async fn sequence_of_actions_is_preserved() {
     let mut app = init_test_app().await;
-    let (_, sitemap_config) = testing_configs();
+    let (_, _, settings) = testing_configs();
 
-    let evn = gen_random_url_set(10, &sitemap_config);
+    let evn = gen_random_url_set(10, &settings);
     for (body, _, webhook_type) in evn.clone() {
         let response = app
             .ready()
@@ -225,6 +463,7 @@ async fn sequence_of_actions_is_preserved() {
             .unwrap()
             .call(
                 Request::builder()
+                    .method("POST")
                     .uri("/api/webhooks")
                     .header(SALEOR_API_URL_HEADER, "https://api.example.com")
                     .header(
@@ -246,7 +485,7 @@ async fn sequence_of_actions_is_preserved() {
     //wait for the file to get written
     sleep(Duration::from_secs(1)).await;
 
-    let file_url = std::fs::read_to_string("./temp/sitemaps/sitemap.txt").unwrap();
+    let file_url = read_sitemap_urls(&instance_folder("https://api.example.com"));
 
     assert_eq!(
         file_url,
@@ -257,22 +496,614 @@ async fn sequence_of_actions_is_preserved() {
     );
 }
 
+#[rstest]
+#[traced_test]
+#[serial]
+fn sitemaps_get_split_and_indexed() {
+    std::env::set_var("APP_API_BASE_URL", "http://localhost:3000");
+    let (_, sitemap_config, settings) = testing_configs();
+    let folder = "./temp/split_sitemaps";
+    _ = std::fs::remove_dir_all(folder);
+    std::fs::create_dir_all(folder).unwrap();
+    std::fs::write(format!("{folder}/sitemap.txt"), "legacy").unwrap();
+
+    let mut urls = gen_random_url_set(25, &settings)
+        .into_iter()
+        .map(|u| u.1)
+        .collect::<Vec<_>>();
+    urls[0].url = "https://example.com/?a=1&b=<2>".to_owned();
+    let url_set = urls.into_iter().collect::<UrlSet>();
+    write_sitemaps(&url_set, folder, &settings, &sitemap_config, 10, usize::MAX).unwrap();
+
+    let index = std::fs::read_to_string(format!("{folder}/sitemap_index.xml")).unwrap();
+    assert!(index.contains("<loc>https://example.com/sitemap-3.xml</loc>"));
+    assert!(!index.contains("sitemap-4.xml"));
+    let first = std::fs::read_to_string(format!("{folder}/sitemap-1.xml")).unwrap();
+    assert!(first.starts_with("<?xml"));
+    assert!(first.contains("<loc>https://example.com/?a=1&amp;b=&lt;2&gt;</loc>"));
+    assert_eq!(first.matches("<url>").count(), 10);
+    assert_eq!(
+        read_sitemap_urls(folder),
+        url_set
+            .iter()
+            .map(|u| u.url.clone())
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    assert!(!std::fs::exists(format!("{folder}/sitemap.txt")).unwrap());
+
+    // shrinking the url set drops sitemaps the index doesn't point to anymore
+    let url_set = url_set.iter().take(5).cloned().collect::<UrlSet>();
+    write_sitemaps(&url_set, folder, &settings, &sitemap_config, 10, usize::MAX).unwrap();
+    assert!(!std::fs::exists(format!("{folder}/sitemap-2.xml")).unwrap());
+    assert_eq!(read_sitemap_urls(folder).lines().count(), 5);
+
+    // big sitemaps get split too, this is small enough for a single url per sitemap
+    write_sitemaps(&url_set, folder, &settings, &sitemap_config, 10, 200).unwrap();
+    assert!(std::fs::exists(format!("{folder}/sitemap-5.xml")).unwrap());
+    assert_eq!(read_sitemap_urls(folder).lines().count(), 5);
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn sitemaps_have_lastmod_and_hints() {
+    let (_, mut sitemap_config, _) = testing_configs();
+    sitemap_config.product_changefreq = Some(ChangeFreq::Daily);
+    sitemap_config.product_priority = Some(0.8);
+
+    let mut url = Url {
+        url: "https://example.com/shoes/sneaker".to_owned(),
+        data: ItemData {
+            id: "UHJvZHVjdDox".to_owned(),
+            slug: "sneaker".to_owned(),
+            typ: ItemType::Product,
+            updated_at: parse_datetime("2024-03-01T10:00:00.123456+00:00"),
+        },
+        related: Some(ItemData {
+            id: "Q2F0ZWdvcnk6MQ==".to_owned(),
+            slug: "shoes".to_owned(),
+            typ: ItemType::Category,
+            updated_at: parse_datetime("2024-05-01T12:30:00+02:00"),
+        }),
+        images: vec![],
+    };
+
+    let entry = render_url(&url, &sitemap_config);
+    // newer of the product and its category wins
+    assert!(entry.contains("<lastmod>2024-05-01T10:30:00Z</lastmod>"));
+    assert!(entry.contains("<changefreq>daily</changefreq>"));
+    assert!(entry.contains("<priority>0.8</priority>"));
+
+    url.data.updated_at = None;
+    url.related = None;
+    let entry = render_url(&url, &testing_configs().1);
+    assert!(!entry.contains("<lastmod>"));
+    assert!(!entry.contains("<changefreq>"));
+    assert!(!entry.contains("<priority>"));
+}
+
+#[rstest]
+#[tokio::test]
+#[traced_test]
+#[serial]
+async fn product_media_events_update_images() {
+    let mut app = init_test_app().await;
+    let folder = &instance_folder("https://api.example.com");
+    let media = |id: &str, typ: ProductMediaType| ProductMedia {
+        id: cynic::Id::new(id),
+        product_id: Some(cynic::Id::new("UHJvZHVjdDox")),
+        url: format!("https://media.example.com/{id}.jpg"),
+        typ,
+    };
+    let product = ProductCreated {
+        product: Some(Product {
+            id: cynic::Id::new("UHJvZHVjdDox"),
+            slug: "sneaker".to_owned(),
+            updated_at: None,
+            category: Some(Category {
+                id: cynic::Id::new("Q2F0ZWdvcnk6MQ=="),
+                slug: "shoes".to_owned(),
+                updated_at: None,
+            }),
+            media: Some(vec![
+                media("1", ProductMediaType::Image),
+                media("2", ProductMediaType::Video),
+            ]),
+        }),
+    };
+    app = create_query(
+        app,
+        serde_json::to_string(&product).unwrap(),
+        EitherWebhookType::Async(AsyncWebhookEventType::ProductCreated),
+    )
+    .await;
+    let media_created = ProductMediaCreated {
+        product_media: Some(media("3", ProductMediaType::Image)),
+    };
+    app = create_query(
+        app,
+        serde_json::to_string(&media_created).unwrap(),
+        EitherWebhookType::Async(AsyncWebhookEventType::ProductMediaCreated),
+    )
+    .await;
+    let media_deleted = ProductMediaDeleted {
+        product_media: Some(media("1", ProductMediaType::Image)),
+    };
+    _ = create_query(
+        app,
+        serde_json::to_string(&media_deleted).unwrap(),
+        EitherWebhookType::Async(AsyncWebhookEventType::ProductMediaDeleted),
+    )
+    .await;
+
+    //wait for the file to get written
+    sleep(Duration::from_secs(1)).await;
+
+    let url_set = get_db_from_file(folder).unwrap();
+    assert_eq!(
+        url_set.iter().next().unwrap().images,
+        vec![Image {
+            id: "3".to_owned(),
+            url: "https://media.example.com/3.jpg".to_owned(),
+        }]
+    );
+    // disabled by default
+    let sitemap = std::fs::read_to_string(format!("{folder}/sitemap-1.xml")).unwrap();
+    assert!(!sitemap.contains("image:"));
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn image_sitemaps_list_product_images() {
+    let (_, mut sitemap_config, _) = testing_configs();
+    sitemap_config.include_images = true;
+    let image = |id: &str| Image {
+        id: id.to_owned(),
+        url: format!("https://media.example.com/{id}.jpg?a=1&b=2"),
+    };
+    let mut url_set = UrlSet::new();
+    url_set.push(Url {
+        url: "https://example.com/shoes/sneaker".to_owned(),
+        data: ItemData {
+            id: "UHJvZHVjdDox".to_owned(),
+            slug: "sneaker".to_owned(),
+            typ: ItemType::Product,
+            updated_at: None,
+        },
+        related: None,
+        images: vec![image("1")],
+    });
+
+    assert!(url_set.set_image("UHJvZHVjdDox", "2", Some(image("2"))));
+    assert!(!url_set.set_image("UHJvZHVjdDox", "2", Some(image("2"))));
+    assert!(!url_set.set_image("UHJvZHVjdDoy", "3", Some(image("3"))));
+    assert!(url_set.set_image("UHJvZHVjdDox", "1", None));
+    assert_eq!(url_set.iter().next().unwrap().images, vec![image("2")]);
+
+    let sitemaps = render_sitemaps(&url_set, &sitemap_config, 10, usize::MAX);
+    assert!(sitemaps[0].contains("xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\""));
+    assert!(sitemaps[0].contains(
+        "<image:image>\n      <image:loc>https://media.example.com/2.jpg?a=1&amp;b=2</image:loc>"
+    ));
+
+    sitemap_config.include_images = false;
+    let sitemaps = render_sitemaps(&url_set, &sitemap_config, 10, usize::MAX);
+    assert!(!sitemaps[0].contains("image"));
+}
+
+fn locale(hreflang: &str, language_code: Option<&str>) -> Locale {
+    Locale {
+        hreflang: hreflang.to_owned(),
+        language_code: language_code.map(str::to_owned),
+        product_template: format!(
+            "https://example.com/{hreflang}/{{product.category.slug}}/{{product.slug}}"
+        ),
+        category_template: format!("https://example.com/{hreflang}/{{category.slug}}"),
+        collection_template: format!(
+            "https://example.com/{hreflang}/collection/{{collection.slug}}"
+        ),
+        pages_template: format!("https://example.com/{hreflang}/{{page.slug}}"),
+    }
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn sitemaps_link_language_versions() {
+    let (_, mut sitemap_config, _) = testing_configs();
+    sitemap_config.locales = vec![locale("sk", Some("SK")), locale("en-GB", None)];
+    let sneaker = Url {
+        url: "https://example.com/shoes/sneaker".to_owned(),
+        data: ItemData {
+            id: "UHJvZHVjdDox".to_owned(),
+            slug: "sneaker".to_owned(),
+            typ: ItemType::Product,
+            updated_at: None,
+        },
+        related: Some(ItemData {
+            id: "Q2F0ZWdvcnk6MQ==".to_owned(),
+            slug: "shoes".to_owned(),
+            typ: ItemType::Category,
+            updated_at: None,
+        }),
+        images: vec![],
+    };
+    let mut url_set = UrlSet::new();
+    url_set.push(sneaker.clone());
+
+    assert!(url_set.set_translated_slug("UHJvZHVjdDox", "SK", Some("tenisky".to_owned())));
+    assert!(!url_set.set_translated_slug("UHJvZHVjdDox", "SK", Some("tenisky".to_owned())));
+    assert!(url_set.set_translated_slug("Q2F0ZWdvcnk6MQ==", "SK", Some("obuv".to_owned())));
+    assert!(url_set.set_translated_slug("Q2F0ZWdvcnk6MQ==", "DE", Some("schuhe".to_owned())));
+    assert!(url_set.set_translated_slug("Q2F0ZWdvcnk6MQ==", "DE", None));
+    assert!(!url_set.set_translated_slug("Q2F0ZWdvcnk6MQ==", "DE", Some("".to_owned())));
+
+    let sitemaps = render_sitemaps(&url_set, &sitemap_config, 10, usize::MAX);
+    assert_eq!(sitemaps.len(), 1);
+    let sitemap = &sitemaps[0];
+    assert!(sitemap.contains("xmlns:xhtml=\"http://www.w3.org/1999/xhtml\""));
+    for loc in [
+        "https://example.com/shoes/sneaker",
+        "https://example.com/sk/obuv/tenisky",
+        "https://example.com/en-GB/shoes/sneaker",
+    ] {
+        assert!(
+            sitemap.contains(&format!("<loc>{loc}</loc>")),
+            "{loc} missing"
+        );
+    }
+    assert_eq!(sitemap.matches("<url>").count(), 3);
+    assert_eq!(
+        sitemap
+            .matches(
+                "<xhtml:link rel=\"alternate\" hreflang=\"sk\" href=\"https://example.com/sk/obuv/tenisky\"/>"
+            )
+            .count(),
+        3
+    );
+    assert_eq!(sitemap.matches("hreflang=\"x-default\"").count(), 3);
+
+    // a split never leaves out language versions
+    let sitemaps = render_sitemaps(&url_set, &sitemap_config, 2, usize::MAX);
+    assert_eq!(sitemaps.len(), 2);
+
+    assert!(url_set.flush_related("UHJvZHVjdDox"));
+    // translations of the category stay, it's still around
+    let localized = url_set.localized(&sneaker, "SK");
+    assert_eq!(localized.data.slug, "sneaker");
+    assert_eq!(localized.related.unwrap().slug, "obuv");
+
+    sitemap_config.locales = vec![];
+    let sitemaps = render_sitemaps(&url_set, &sitemap_config, 10, usize::MAX);
+    assert!(!sitemaps[0].contains("xhtml"));
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn translation_payloads_deserialize() {
+    let translation = TranslationUpdated {
+        translation: Some(TranslationTypes::CategoryTranslation(CategoryTranslation {
+            slug: Some("obuv".to_owned()),
+            language: LanguageDisplay {
+                code: LanguageCodeEnum("SK".to_owned()),
+            },
+            translatable_content: Some(CategoryTranslatableContent {
+                category_id: cynic::Id::new("Q2F0ZWdvcnk6MQ=="),
+            }),
+        })),
+    };
+    let payload = serde_json::to_string(&translation).unwrap();
+    let parsed: TranslationUpdated = serde_json::from_str(&payload).unwrap();
+    let Some(TranslationTypes::CategoryTranslation(parsed)) = parsed.translation else {
+        panic!("wrong translation type in {payload}");
+    };
+    assert_eq!(parsed.language.code, LanguageCodeEnum("SK".to_owned()));
+    assert_eq!(parsed.slug.as_deref(), Some("obuv"));
+
+    let parsed: TranslationUpdated = serde_json::from_str(
+        r#"{"translation": {"__typename": "AttributeTranslation", "name": "Farba"}}"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        parsed.translation,
+        Some(TranslationTypes::Unknown)
+    ));
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn sitemap_config_reads_locales() {
+    let config = envy::from_iter::<_, SitemapConfig>([
+        ("SITEMAP_TARGET_FOLDER".to_owned(), "./temp".to_owned()),
+        (
+            "SITEMAP_LOCALES".to_owned(),
+            serde_json::to_string(&vec![
+                locale("sk", Some("SK")),
+                locale("cs", Some("SK")),
+                locale("en-GB", None),
+            ])
+            .unwrap(),
+        ),
+        ("SITEMAP_DEFAULT_HREFLANG".to_owned(), "".to_owned()),
+    ])
+    .unwrap();
+    assert_eq!(config.locales.len(), 3);
+    assert_eq!(config.language_codes(), vec!["SK"]);
+    assert_eq!(config.default_hreflang, None);
+
+    let (app_config, mut sitemap_config, _) = testing_configs();
+    let manifests =
+        webhooks(Arc::new(MemoryApl::new()), &sitemap_config).webhook_manifests(&app_config);
+    assert_eq!(manifests.len(), 2);
+    sitemap_config.locales = config.locales;
+    let manifests =
+        webhooks(Arc::new(MemoryApl::new()), &sitemap_config).webhook_manifests(&app_config);
+    assert_eq!(manifests.len(), 3);
+    assert_eq!(manifests[2].query, TRANSLATIONS_QUERY);
+
+    let mut bad_template = locale("sk", None);
+    bad_template.pages_template = "https://example.com/sk/{page.slug".to_owned();
+    for locales in [
+        "{\"hreflang\": \"sk\"}".to_owned(),
+        serde_json::to_string(&vec![bad_template]).unwrap(),
+    ] {
+        assert!(envy::from_iter::<_, SitemapConfig>([
+            ("SITEMAP_TARGET_FOLDER".to_owned(), "./temp".to_owned()),
+            ("SITEMAP_LOCALES".to_owned(), locales),
+        ])
+        .is_err());
+    }
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn instance_folders_dont_collide() {
+    let (_, sitemap_config, _) = testing_configs();
+    let folder = sitemap_config.instance_folder("https://api.example.com/graphql/");
+    assert!(folder.starts_with(&format!(
+        "{}/api.example.com_graphql-",
+        sitemap_config.target_folder
+    )));
+    assert_eq!(
+        folder,
+        sitemap_config.instance_folder("https://api.example.com/graphql/")
+    );
+    for other in [
+        "http://api.example.com/graphql/",
+        "https://api.example.com_graphql/",
+    ] {
+        assert_ne!(folder, sitemap_config.instance_folder(other));
+    }
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn sitemap_config_validates_hints() {
+    let config = envy::from_iter::<_, SitemapConfig>([
+        ("SITEMAP_TARGET_FOLDER".to_owned(), "./temp".to_owned()),
+        ("SITEMAP_PAGES_CHANGEFREQ".to_owned(), "monthly".to_owned()),
+        ("SITEMAP_PAGES_PRIORITY".to_owned(), "0.3".to_owned()),
+        ("SITEMAP_PRODUCT_PRIORITY".to_owned(), "".to_owned()),
+        ("SITEMAP_PRODUCT_CHANGEFREQ".to_owned(), "".to_owned()),
+    ])
+    .unwrap();
+    assert_eq!(config.pages_changefreq, Some(ChangeFreq::Monthly));
+    assert_eq!(config.pages_priority, Some(0.3));
+    assert_eq!(config.product_priority, None);
+    assert_eq!(config.product_changefreq, None);
+
+    assert!(envy::from_iter::<_, SitemapConfig>([
+        ("SITEMAP_TARGET_FOLDER".to_owned(), "./temp".to_owned()),
+        ("SITEMAP_PAGES_PRIORITY".to_owned(), "1.5".to_owned()),
+    ])
+    .is_err());
+    assert!(envy::from_iter::<_, SitemapConfig>([
+        ("SITEMAP_TARGET_FOLDER".to_owned(), "./temp".to_owned()),
+        (
+            "SITEMAP_PAGES_CHANGEFREQ".to_owned(),
+            "sometimes".to_owned()
+        ),
+    ])
+    .is_err());
+}
+
+#[rstest]
+#[traced_test]
+#[parallel]
+fn url_sets_without_timestamps_still_load() {
+    // db.cbor files written before urls had timestamps
+    let old = serde_cbor::to_vec(&serde_json::json!({
+        "urls": [{
+            "url": "https://example.com/old",
+            "data": { "id": "UGFnZTox", "slug": "old", "typ": "Page" },
+            "related": null
+        }]
+    }))
+    .unwrap();
+    let url_set: UrlSet = serde_cbor::de::from_slice(&old).unwrap();
+    assert_eq!(url_set.iter().next().unwrap().data.updated_at, None);
+    assert_eq!(url_set.iter().next().unwrap().lastmod(), None);
+}
+
 #[rstest]
 #[traced_test]
 #[parallel]
 fn urlset_serialisation_isnt_lossy() {
     std::env::set_var("APP_API_BASE_URL", "http://localhost:3000");
-    let (_, sitemap_config) = testing_configs();
+    let (_, _, settings) = testing_configs();
 
-    let urls = gen_random_url_set(100, &sitemap_config);
+    let urls = gen_random_url_set(100, &settings);
 
-    let mut url_set = UrlSet::new();
-    url_set.urls = urls.into_iter().map(|u| u.1).collect();
+    let url_set = urls.into_iter().map(|u| u.1).collect::<UrlSet>();
     let file_str = serde_cbor::to_vec(&url_set).unwrap();
     let deserialized_url_set: UrlSet = serde_cbor::de::from_slice(&file_str).unwrap();
     assert_eq!(url_set, deserialized_url_set);
 }
 
+#[rstest]
+#[traced_test]
+#[parallel]
+fn flush_related_only_removes_related_urls() {
+    std::env::set_var("APP_API_BASE_URL", "http://localhost:3000");
+    let (_, _, settings) = testing_configs();
+    let urls = gen_random_url_set(100, &settings)
+        .into_iter()
+        .map(|u| u.1)
+        .collect::<Vec<_>>();
+    let mut url_set = urls.iter().cloned().collect::<UrlSet>();
+    let category = urls
+        .iter()
+        .find_map(|u| u.related.clone().filter(|r| r.typ == ItemType::Category))
+        .unwrap();
+
+    assert!(url_set.flush_related(&category.id));
+    let related = |u: &Url| {
+        u.data.id == category.id || u.related.as_ref().is_some_and(|r| r.id == category.id)
+    };
+    assert_eq!(
+        url_set.iter().cloned().collect::<Vec<_>>(),
+        urls.iter()
+            .filter(|u| !related(u))
+            .cloned()
+            .collect::<Vec<_>>()
+    );
+    assert!(url_set.find_related(&category.id).is_empty());
+    assert!(!url_set.flush_related(&category.id));
+    // positions moved, lookups still find the rest
+    let last = urls.iter().rev().find(|u| !related(u)).unwrap();
+    assert!(url_set.find_related(&last.data.id).contains(&last));
+}
+
+#[rstest]
+#[traced_test]
+#[serial]
+fn removals_keep_order_and_replay() {
+    std::env::set_var("APP_API_BASE_URL", "http://localhost:3000");
+    let (_, _, settings) = testing_configs();
+    let folder = "./temp/url_store_removals";
+    _ = std::fs::remove_dir_all(folder);
+    std::fs::create_dir_all(folder).unwrap();
+    let urls = gen_random_url_set(100, &settings)
+        .into_iter()
+        .map(|u| u.1)
+        .collect::<Vec<_>>();
+    let mut store = UrlStore::open(folder).unwrap();
+    store.url_set.extend(urls.iter().cloned());
+    store.commit().unwrap();
+
+    // enough removals to drop the holes midway, the journal stays
+    let removed = urls.iter().take(70).map(|u| &u.data.id).collect::<Vec<_>>();
+    for id in removed.iter() {
+        store.url_set.flush_related(id);
+    }
+    store.commit().unwrap();
+    let left = urls
+        .iter()
+        .filter(|u| {
+            !removed.contains(&&u.data.id)
+                && !u.related.as_ref().is_some_and(|r| removed.contains(&&r.id))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(store.url_set.iter().cloned().collect::<Vec<_>>(), left);
+    assert_eq!(store.url_set.len(), left.len());
+    for url in left.iter() {
+        assert!(store.url_set.find_related(&url.data.id).contains(&url));
+    }
+
+    let replayed = get_db_from_file(folder).unwrap();
+    assert_eq!(replayed, store.url_set);
+    assert_eq!(
+        serde_cbor::from_slice::<UrlSet>(&serde_cbor::to_vec(&replayed).unwrap()).unwrap(),
+        left.into_iter().collect::<UrlSet>()
+    );
+}
+
+#[rstest]
+#[traced_test]
+#[serial]
+fn url_store_journals_and_compacts_changes() {
+    std::env::set_var("APP_API_BASE_URL", "http://localhost:3000");
+    let (_, _, settings) = testing_configs();
+    let folder = "./temp/url_store";
+    _ = std::fs::remove_dir_all(folder);
+    std::fs::create_dir_all(folder).unwrap();
+    let urls = gen_random_url_set(50, &settings)
+        .into_iter()
+        .map(|u| u.1)
+        .collect::<Vec<_>>();
+
+    let mut store = UrlStore::open(folder).unwrap();
+    assert!(store.url_set.is_empty());
+    store.url_set.extend(urls.iter().cloned());
+    assert!(store.commit().unwrap());
+    assert!(!store.commit().unwrap());
+    // only the journal got written, there's nothing to compact yet
+    assert!(!std::fs::exists(format!("{folder}/db.cbor")).unwrap());
+
+    // nothing relates to it, so it's the only url that goes
+    let removed = urls
+        .iter()
+        .rfind(|u| {
+            !urls
+                .iter()
+                .any(|o| o.related.as_ref().is_some_and(|r| r.id == u.data.id))
+        })
+        .unwrap();
+    assert!(store.url_set.flush_related(&removed.data.id));
+    assert!(store
+        .url_set
+        .set_translated_slug(&urls[1].data.id, "SK", Some("preklad".to_owned())));
+    assert!(store.commit().unwrap());
+    assert_eq!(store.url_set.len(), urls.len() - 1);
+    assert_eq!(UrlStore::open(folder).unwrap().url_set, store.url_set);
+
+    // a write cut short doesn't lose what was journaled before it
+    let mut journal = std::fs::read(format!("{folder}/db.journal")).unwrap();
+    let journaled = store.url_set.clone();
+    // a single change, which gets cut
+    let lone = store
+        .url_set
+        .iter()
+        .map(|u| u.data.id.clone())
+        .find(|id| *id != urls[1].data.id && store.url_set.find_related(id).len() == 1)
+        .unwrap();
+    store.url_set.flush_related(&lone);
+    assert!(store.commit().unwrap());
+    let appended = std::fs::read(format!("{folder}/db.journal")).unwrap();
+    journal.extend_from_slice(&appended[journal.len()..appended.len() - 1]);
+    std::fs::write(format!("{folder}/db.journal"), &journal).unwrap();
+    let mut store = UrlStore::open(folder).unwrap();
+    assert_eq!(store.url_set, journaled);
+    // and gets compacted right away, so new changes aren't appended after the broken one
+    assert!(!std::fs::exists(format!("{folder}/db.journal")).unwrap());
+
+    // plenty of changes get compacted into a snapshot
+    let snapshot = std::fs::read(format!("{folder}/db.cbor")).unwrap();
+    let items = store
+        .url_set
+        .iter()
+        .map(|u| u.data.clone())
+        .collect::<Vec<_>>();
+    for i in 0..2000 {
+        let mut item = items[i % items.len()].clone();
+        item.updated_at = chrono::DateTime::from_timestamp(i as i64, 0);
+        assert!(store.url_set.refresh(&item, None));
+        assert!(store.commit().unwrap());
+    }
+    assert_ne!(
+        std::fs::read(format!("{folder}/db.cbor")).unwrap(),
+        snapshot
+    );
+    assert_eq!(UrlStore::open(folder).unwrap().url_set, store.url_set);
+}
+
 // #[rstest]
 // #[traced_test]
 // #[parallel]
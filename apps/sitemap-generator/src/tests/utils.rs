@@ -9,16 +9,17 @@ use rand::{
     Rng,
 };
 use saleor_app_sdk::{
-    apl::AplType,
+    apl::{memory_apl::MemoryApl, AplType, APL},
     config::Config,
     headers::{SALEOR_API_URL_HEADER, SALEOR_EVENT_HEADER},
     webhooks::{utils::EitherWebhookType, AsyncWebhookEventType},
+    AuthData,
 };
 use tower::{Service, ServiceExt};
 use tracing::Level;
 
 use crate::{
-    app::{trace_to_std, SitemapConfig},
+    app::{trace_to_std, SitemapConfig, SitemapSettings},
     queries::event_subjects_updated::{
         Category, Category2, CategoryUpdated, Collection, CollectionUpdated, Page, PageUpdated,
         Product, ProductUpdated,
@@ -28,8 +29,12 @@ use crate::{
 
 pub fn init_tracing() {
     let config = Config {
-        apl: AplType::File,
-        apl_url: "redis://localhost:6379".to_string(),
+        apl: AplType::Memory,
+        apl_url: "".to_string(),
+        allowed_saleor_api_urls: vec![],
+        secret_key: None,
+        apl_encryption_keys: vec![],
+        apl_namespace: None,
         log_level: Level::TRACE,
         app_api_base_url: "http://localhost:3000".to_string(),
         app_iframe_base_url: "http://localhost:3000".to_string(),
@@ -50,29 +55,86 @@ impl Distribution<ItemType> for Standard {
     }
 }
 
-pub fn testing_configs() -> (Config, SitemapConfig) {
+pub fn testing_configs() -> (Config, SitemapConfig, SitemapSettings) {
     (
         Config {
-            apl: AplType::File,
-            apl_url: "redis://localhost:6379".to_string(),
+            apl: AplType::Memory,
+            apl_url: "".to_string(),
+            allowed_saleor_api_urls: vec![],
+            secret_key: None,
+            apl_encryption_keys: vec![],
+            apl_namespace: None,
             log_level: Level::TRACE,
             app_api_base_url: "http://localhost:3000".to_string(),
             app_iframe_base_url: "http://localhost:3000".to_string(),
             required_saleor_version: "^3.13".to_string(),
         },
         SitemapConfig {
-            allowed_host: "https://api.example.com".to_string(),
             target_folder: "./temp/sitemaps".to_string(),
+            product_changefreq: None,
+            category_changefreq: None,
+            collection_changefreq: None,
+            pages_changefreq: None,
+            product_priority: None,
+            category_priority: None,
+            collection_priority: None,
+            pages_priority: None,
+            include_images: false,
+            locales: vec![],
+            default_hreflang: None,
+            write_delay_ms: Some(100),
+        },
+        SitemapSettings {
             pages_template: "https://example.com/{page.slug}".to_string(),
             index_hostname: "https://example.com".to_string(),
             product_template: "https://example.com/{product.category.slug}/{product.slug}"
                 .to_string(),
             category_template: "https://example.com/{category.slug}".to_string(),
             collection_template: "https://example.com/collection/{collection.slug}".to_string(),
+            channel_slug: "default-channel".to_string(),
         },
     )
 }
 
+/// Webhooks only get dispatched for saleor instances in the APL
+pub async fn register_test_instance(apl: &MemoryApl, config: &Config, saleor_api_url: &str) {
+    apl.set(AuthData {
+        domain: Some(config.app_api_base_url.clone()),
+        token: "test_token".to_string(),
+        saleor_api_url: saleor_api_url.to_string(),
+        app_id: "test_app".to_string(),
+        jwks: None,
+    })
+    .await
+    .unwrap();
+}
+
+/// Urls of the sitemaps `sitemap_index.xml` in `folder` points to, joined by newlines
+pub fn read_sitemap_urls(folder: &str) -> String {
+    let locs = |xml: &str| -> Vec<String> {
+        xml.split("<loc>")
+            .skip(1)
+            .filter_map(|l| l.split_once("</loc>"))
+            .map(|(loc, _)| {
+                loc.replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&")
+            })
+            .collect()
+    };
+    let index = std::fs::read_to_string(format!("{folder}/sitemap_index.xml")).unwrap();
+    locs(&index)
+        .iter()
+        .flat_map(|sitemap| {
+            let file_name = sitemap.rsplit('/').next().unwrap();
+            locs(&std::fs::read_to_string(format!("{folder}/{file_name}")).unwrap())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub async fn create_query(
     mut app: RouterIntoService<Body>,
     body: String,
@@ -84,6 +146,7 @@ pub async fn create_query(
         .unwrap()
         .call(
             Request::builder()
+                .method("POST")
                 .uri("/api/webhooks")
                 .header(SALEOR_API_URL_HEADER, "https://api.example.com")
                 .header(
@@ -257,7 +320,7 @@ impl Distribution<ActionType> for Standard {
 //
 pub fn gen_random_url_set(
     len: usize,
-    sitemap_config: &SitemapConfig,
+    settings: &SitemapSettings,
 ) -> Vec<(String, Url, EitherWebhookType)> {
     let mut res: Vec<(String, Url, EitherWebhookType)> = vec![];
     for _ in 0..len {
@@ -312,24 +375,29 @@ pub fn gen_random_url_set(
                     product: Some(Product {
                         id: id.clone(),
                         slug: slug.clone(),
+                        updated_at: None,
                         category: Some(Category {
                             slug: rel_slug.clone(),
                             id: rel_id.clone(),
+                            updated_at: None,
                         }),
+                        media: None,
                     }),
                 };
                 let url = Url::new(
                     product_updated.clone(),
-                    sitemap_config,
+                    settings,
                     ItemData {
                         id: id.clone().inner().to_owned(),
                         slug: slug.clone(),
                         typ: ItemType::Product,
+                        updated_at: None,
                     },
                     Some(ItemData {
                         id: rel_id.inner().to_owned(),
                         slug: rel_slug.clone(),
                         typ: ItemType::Category,
+                        updated_at: None,
                     }),
                 )
                 .unwrap();
@@ -339,16 +407,18 @@ pub fn gen_random_url_set(
                         category: Some(Category2 {
                             id: rel_id.clone(),
                             slug: rel_slug.clone(),
+                            updated_at: None,
                         }),
                     };
 
                     let cat_url = Url::new(
                         category_updated.clone(),
-                        sitemap_config,
+                        settings,
                         ItemData {
-                            id: id.clone().inner().to_owned(),
-                            slug: slug.clone(),
+                            id: rel_id.clone().inner().to_owned(),
+                            slug: rel_slug.clone(),
                             typ: ItemType::Category,
+                            updated_at: None,
                         },
                         None,
                     )
@@ -371,16 +441,18 @@ pub fn gen_random_url_set(
                     category: Some(Category2 {
                         id: id.clone(),
                         slug: slug.clone(),
+                        updated_at: None,
                     }),
                 };
 
                 let url = Url::new(
                     category_updated.clone(),
-                    sitemap_config,
+                    settings,
                     ItemData {
                         id: id.clone().inner().to_owned(),
                         slug: slug.clone(),
                         typ: ItemType::Category,
+                        updated_at: None,
                     },
                     None,
                 )
@@ -401,11 +473,12 @@ pub fn gen_random_url_set(
 
                 let url = Url::new(
                     collection_updated.clone(),
-                    sitemap_config,
+                    settings,
                     ItemData {
                         id: id.clone().inner().to_owned(),
                         slug: slug.clone(),
                         typ: ItemType::Collection,
+                        updated_at: None,
                     },
                     None,
                 )
@@ -426,11 +499,12 @@ pub fn gen_random_url_set(
 
                 let url = Url::new(
                     page_updated.clone(),
-                    sitemap_config,
+                    settings,
                     ItemData {
                         id: id.clone().inner().to_owned(),
                         slug: slug.clone(),
                         typ: ItemType::Page,
+                        updated_at: None,
                     },
                     None,
                 )
@@ -0,0 +1,253 @@
+use std::sync::Arc;
+
+use crate::AuthData;
+
+use super::{AplError, APL};
+use crate::cipher::Cipher;
+use async_trait::async_trait;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+const KDF_INFO: &[u8] = b"saleor-app-sdk encrypted apl token";
+/// Stored tokens look like `enc:{key_id}:{base64 of nonce and ciphertext}`
+const TOKEN_PREFIX: &str = "enc:";
+
+#[derive(Clone)]
+struct AplKey {
+    id: String,
+    cipher: Cipher,
+}
+
+/**
+ Wraps any other APL and stores the app tokens encrypted with XChaCha20-Poly1305, keys derived
+ from the configured secrets through HKDF-SHA256. The saleor api url is authenticated with
+ every token, so tokens can't be swapped between instances.
+
+ Keys are given as `key_id:secret`, the first one encrypts and all of them decrypt. To rotate,
+ put the new key first and keep the old ones until [`EncryptedApl::reencrypt_all`] ran.
+ Tokens stored before encryption was enabled are read as they are and get encrypted on the next
+ write.
+*/
+#[derive(Clone)]
+pub struct EncryptedApl {
+    inner: Arc<dyn APL>,
+    keys: Vec<AplKey>,
+}
+
+impl std::fmt::Debug for EncryptedApl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedApl")
+            .field("inner", &self.inner)
+            .field(
+                "key_ids",
+                &self.keys.iter().map(|k| k.id.as_str()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[async_trait]
+impl APL for EncryptedApl {
+    async fn get(&self, saleor_api_url: &str) -> Result<AuthData, AplError> {
+        self.decrypt(self.inner.get(saleor_api_url).await?)
+    }
+
+    async fn set(&self, mut auth_data: AuthData) -> Result<(), AplError> {
+        auth_data.token = self.encrypt(&auth_data.token, &auth_data.saleor_api_url)?;
+        self.inner.set(auth_data).await
+    }
+
+    async fn delete(&self, saleor_api_url: &str) -> Result<(), AplError> {
+        self.inner.delete(saleor_api_url).await
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        self.inner
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|auth_data| self.decrypt(auth_data))
+            .collect()
+    }
+
+    async fn is_ready(&self) -> Result<(), AplError> {
+        self.inner.is_ready().await
+    }
+
+    async fn is_configured(&self) -> Result<(), AplError> {
+        self.inner.is_configured().await
+    }
+}
+
+impl EncryptedApl {
+    pub fn new(inner: Arc<dyn APL>, keys: &[String]) -> Result<Self, AplError> {
+        let mut parsed: Vec<AplKey> = vec![];
+        for key in keys.iter().filter(|k| !k.is_empty()) {
+            let (id, secret) = key
+                .split_once(':')
+                .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+                .ok_or(AplError::Encryption(
+                    "keys have to be in the form of key_id:secret".to_owned(),
+                ))?;
+            if parsed.iter().any(|k| k.id == id) {
+                return Err(AplError::Encryption(format!("key id {id} is used twice")));
+            }
+            parsed.push(AplKey {
+                id: id.to_owned(),
+                cipher: Cipher::new(secret, KDF_INFO),
+            });
+        }
+        if parsed.is_empty() {
+            return Err(AplError::Encryption("no encryption key given".to_owned()));
+        }
+        Ok(Self {
+            inner,
+            keys: parsed,
+        })
+    }
+
+    /// Rewrites every token not encrypted with the current key, returns how many were rewritten
+    pub async fn reencrypt_all(&self) -> Result<usize, AplError> {
+        let current = format!("{TOKEN_PREFIX}{}:", self.keys[0].id);
+        let mut count = 0;
+        for auth_data in self.inner.get_all().await? {
+            if auth_data.token.starts_with(&current) {
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            debug!("reencrypting token of {}", &auth_data.saleor_api_url);
+            self.set(self.decrypt(auth_data)?).await?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn encrypt(&self, token: &str, saleor_api_url: &str) -> Result<String, AplError> {
+        let key = &self.keys[0];
+        let encrypted = key
+            .cipher
+            .encrypt(token, saleor_api_url)
+            .ok_or(AplError::Encryption("failed encrypting token".to_owned()))?;
+        Ok(format!("{TOKEN_PREFIX}{}:{encrypted}", key.id))
+    }
+
+    fn decrypt(&self, mut auth_data: AuthData) -> Result<AuthData, AplError> {
+        let Some(encrypted) = auth_data.token.strip_prefix(TOKEN_PREFIX) else {
+            #[cfg(feature = "tracing")]
+            debug!("token of {} isn't encrypted", &auth_data.saleor_api_url);
+            return Ok(auth_data);
+        };
+        let failed = || {
+            AplError::Encryption(format!(
+                "failed decrypting token of {}, it's corrupted or was encrypted with a different key",
+                auth_data.saleor_api_url
+            ))
+        };
+        let (key_id, encrypted) = encrypted.split_once(':').ok_or_else(failed)?;
+        let key = self.keys.iter().find(|k| k.id == key_id).ok_or_else(|| {
+            AplError::Encryption(format!(
+                "token of {} was encrypted with unknown key {key_id}",
+                auth_data.saleor_api_url
+            ))
+        })?;
+        auth_data.token = key
+            .cipher
+            .decrypt(encrypted, &auth_data.saleor_api_url)
+            .ok_or_else(failed)?;
+        Ok(auth_data)
+    }
+}
+
+#[cfg(all(test, feature = "memory_apl"))]
+mod tests {
+    use super::*;
+    use crate::apl::memory_apl::MemoryApl;
+
+    fn auth_data(saleor_api_url: &str) -> AuthData {
+        AuthData {
+            domain: Some("http://localhost:3000".to_owned()),
+            token: "token".to_owned(),
+            saleor_api_url: saleor_api_url.to_owned(),
+            app_id: "QXBwOjE=".to_owned(),
+            jwks: None,
+        }
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn tokens_are_encrypted_at_rest() {
+        let inner = Arc::new(MemoryApl::new());
+        let apl = EncryptedApl::new(inner.clone(), &keys(&["one:secret"])).unwrap();
+        let saleor_api_url = "https://one.example.com/graphql/";
+        apl.set(auth_data(saleor_api_url)).await.unwrap();
+
+        let stored = inner.get(saleor_api_url).await.unwrap();
+        assert!(stored.token.starts_with("enc:one:"));
+        assert_eq!(apl.get(saleor_api_url).await.unwrap().token, "token");
+        assert_eq!(apl.get_all().await.unwrap()[0].token, "token");
+
+        let other = EncryptedApl::new(inner.clone(), &keys(&["one:other secret"])).unwrap();
+        assert!(matches!(
+            other.get(saleor_api_url).await,
+            Err(AplError::Encryption(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tokens_cant_be_moved_between_instances() {
+        let inner = Arc::new(MemoryApl::new());
+        let apl = EncryptedApl::new(inner.clone(), &keys(&["one:secret"])).unwrap();
+        apl.set(auth_data("https://one.example.com/graphql/"))
+            .await
+            .unwrap();
+
+        let mut moved = inner.get("https://one.example.com/graphql/").await.unwrap();
+        moved.saleor_api_url = "https://two.example.com/graphql/".to_owned();
+        inner.set(moved).await.unwrap();
+        assert!(apl.get("https://two.example.com/graphql/").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn keys_rotate() {
+        let inner = Arc::new(MemoryApl::new());
+        inner
+            .set(auth_data("https://plain.example.com/graphql/"))
+            .await
+            .unwrap();
+        let old = EncryptedApl::new(inner.clone(), &keys(&["old:secret"])).unwrap();
+        old.set(auth_data("https://old.example.com/graphql/"))
+            .await
+            .unwrap();
+
+        let rotated =
+            EncryptedApl::new(inner.clone(), &keys(&["new:new secret", "old:secret"])).unwrap();
+        assert_eq!(
+            rotated
+                .get("https://plain.example.com/graphql/")
+                .await
+                .unwrap()
+                .token,
+            "token"
+        );
+        assert_eq!(rotated.reencrypt_all().await.unwrap(), 2);
+        assert_eq!(rotated.reencrypt_all().await.unwrap(), 0);
+
+        let new = EncryptedApl::new(inner.clone(), &keys(&["new:new secret"])).unwrap();
+        for auth_data in new.get_all().await.unwrap() {
+            assert_eq!(auth_data.token, "token");
+        }
+        assert!(old.get("https://old.example.com/graphql/").await.is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let inner = Arc::new(MemoryApl::new());
+        assert!(EncryptedApl::new(inner.clone(), &keys(&[])).is_err());
+        assert!(EncryptedApl::new(inner.clone(), &keys(&["no key id"])).is_err());
+        assert!(EncryptedApl::new(inner.clone(), &keys(&["one:a", "one:b"])).is_err());
+        assert!(EncryptedApl::new(inner, &keys(&["one:a:b"])).is_ok());
+    }
+}
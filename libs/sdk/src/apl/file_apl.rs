@@ -13,7 +13,8 @@ use tracing::debug;
 
 #[derive(Clone, Debug)]
 /**
- Only works for this app, can't have multiple apps use same file
+ Only works for this app, can't have multiple apps use same file. For that, or for concurrent
+ writers, use `SqliteApl` from the `sqlite_apl` feature instead.
 */
 pub struct FileApl {
     pub path: String,
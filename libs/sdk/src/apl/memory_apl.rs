@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::AuthData;
+
+use super::{AplError, APL};
+use async_trait::async_trait;
+use serde::Deserialize;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+/**
+ Keeps auth data in memory only, everything is lost on restart. Meant for tests and trying
+ apps out locally. Clones share the same storage, so a test can keep a clone to register fake
+ saleor instances in the app it runs.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct MemoryApl {
+    auths: Arc<RwLock<HashMap<String, AuthData>>>,
+}
+
+/// Either a list of auth data, or the `FileApl` file structure
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Fixture {
+    List(Vec<AuthData>),
+    FileApl(HashMap<String, AuthData>),
+}
+
+#[async_trait]
+impl APL for MemoryApl {
+    async fn get(&self, saleor_api_url: &str) -> Result<AuthData, AplError> {
+        self.auths
+            .read()
+            .map_err(|e| AplError::Connection(e.to_string()))?
+            .get(saleor_api_url)
+            .cloned()
+            .ok_or(AplError::NotFound(
+                "haven't found entry for given url".to_owned(),
+            ))
+    }
+
+    async fn set(&self, auth_data: AuthData) -> Result<(), AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("set(), {}", &auth_data.saleor_api_url);
+        self.auths
+            .write()
+            .map_err(|e| AplError::Connection(e.to_string()))?
+            .insert(auth_data.saleor_api_url.clone(), auth_data);
+        Ok(())
+    }
+
+    async fn delete(&self, saleor_api_url: &str) -> Result<(), AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("delete(), {}", saleor_api_url);
+        self.auths
+            .write()
+            .map_err(|e| AplError::Connection(e.to_string()))?
+            .remove(saleor_api_url)
+            .map(|_| ())
+            .ok_or(AplError::NotFound(
+                "haven't found entry for given url".to_owned(),
+            ))
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        Ok(self
+            .auths
+            .read()
+            .map_err(|e| AplError::Connection(e.to_string()))?
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn is_ready(&self) -> Result<(), AplError> {
+        Ok(())
+    }
+
+    async fn is_configured(&self) -> Result<(), AplError> {
+        Ok(())
+    }
+}
+
+impl MemoryApl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeds the APL from json, either a list of auth data or a `FileApl` file
+    pub fn from_json(json: &str) -> Result<Self, AplError> {
+        let auths =
+            match serde_json::from_str(json).map_err(|e| AplError::Serialization(e.to_string()))? {
+                Fixture::List(auths) => auths
+                    .into_iter()
+                    .map(|a| (a.saleor_api_url.clone(), a))
+                    .collect(),
+                Fixture::FileApl(auths) => auths,
+            };
+        Ok(Self {
+            auths: Arc::new(RwLock::new(auths)),
+        })
+    }
+
+    /// [`Self::from_json`] with the contents of `path`, empty path gives an empty APL
+    pub fn from_file(path: &str) -> Result<Self, AplError> {
+        if path.is_empty() {
+            return Ok(Self::new());
+        }
+        #[cfg(feature = "tracing")]
+        debug!("seeding memory apl from {path}");
+        Self::from_json(&std::fs::read_to_string(path).map_err(|e| AplError::IO(e.to_string()))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth_data(saleor_api_url: &str) -> AuthData {
+        AuthData {
+            domain: Some("http://localhost:3000".to_owned()),
+            token: "token".to_owned(),
+            saleor_api_url: saleor_api_url.to_owned(),
+            app_id: "QXBwOjE=".to_owned(),
+            jwks: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn clones_share_storage() {
+        let apl = MemoryApl::new();
+        let clone = apl.clone();
+        let saleor_api_url = "https://one.example.com/graphql/";
+        assert!(matches!(
+            apl.get(saleor_api_url).await,
+            Err(AplError::NotFound(_))
+        ));
+
+        clone.set(auth_data(saleor_api_url)).await.unwrap();
+        assert_eq!(apl.get(saleor_api_url).await.unwrap().token, "token");
+        assert_eq!(apl.get_all().await.unwrap().len(), 1);
+
+        apl.delete(saleor_api_url).await.unwrap();
+        assert!(clone.get_all().await.unwrap().is_empty());
+        assert!(matches!(
+            clone.delete(saleor_api_url).await,
+            Err(AplError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn seeds_from_fixtures() {
+        let list =
+            serde_json::to_string(&vec![auth_data("https://one.example.com/graphql/")]).unwrap();
+        let apl = MemoryApl::from_json(&list).unwrap();
+        apl.get("https://one.example.com/graphql/").await.unwrap();
+
+        let file_apl = serde_json::to_string(&HashMap::from([(
+            "https://two.example.com/graphql/",
+            auth_data("https://two.example.com/graphql/"),
+        )]))
+        .unwrap();
+        let apl = MemoryApl::from_json(&file_apl).unwrap();
+        apl.get("https://two.example.com/graphql/").await.unwrap();
+
+        assert!(MemoryApl::from_json("{\"not\": \"auth data\"}").is_err());
+        assert!(MemoryApl::from_file("")
+            .unwrap()
+            .get_all()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
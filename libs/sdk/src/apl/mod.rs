@@ -1,16 +1,34 @@
+#[cfg(feature = "encrypted_apl")]
+pub mod encrypted_apl;
 #[cfg(feature = "file_apl")]
 pub mod file_apl;
+#[cfg(feature = "memory_apl")]
+pub mod memory_apl;
+#[cfg(feature = "postgres_apl")]
+pub mod postgres_apl;
 #[cfg(feature = "redis_apl")]
 pub mod redis_apl;
+#[cfg(any(feature = "postgres_apl", feature = "sqlite_apl"))]
+mod sql;
+#[cfg(feature = "sqlite_apl")]
+pub mod sqlite_apl;
 
 use crate::AuthData;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use strum_macros::{EnumString, VariantNames};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Namespace the SQL APLs keep auth data under when [`crate::config::Config::apl_namespace`] isn't set
+pub const DEFAULT_APL_NAMESPACE: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize, EnumString, VariantNames)]
+#[strum(ascii_case_insensitive)]
 pub enum AplType {
     Redis,
     File,
+    Postgres,
+    Sqlite,
+    Memory,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -25,6 +43,8 @@ pub enum AplError {
     NotSupported(String),
     #[error("Key or value wasn't found during Get")]
     NotFound(String),
+    #[error("APL failed en/decrypting auth data: {0}")]
+    Encryption(String),
 }
 
 #[async_trait]
@@ -0,0 +1,215 @@
+//! Schema, statements, queries and row mapping shared by the SQL APLs. Statements use `$n`
+//! placeholders, which both postgres and sqlite understand.
+
+use std::sync::LazyLock;
+
+use sqlx::{ColumnIndex, Database, Decode, Executor, IntoArguments, Pool, Row, Type};
+
+use super::AplError;
+use crate::AuthData;
+
+/// Table the auth data of every app using the database is stored in
+pub const APL_TABLE: &str = "saleor_app_apl";
+
+const COLUMNS: [&str; 6] = [
+    "namespace",
+    "saleor_api_url",
+    "domain",
+    "token",
+    "app_id",
+    "jwks",
+];
+
+pub static CREATE_TABLE: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {APL_TABLE} (
+            namespace TEXT NOT NULL,
+            saleor_api_url TEXT NOT NULL,
+            domain TEXT,
+            token TEXT NOT NULL,
+            app_id TEXT NOT NULL,
+            jwks TEXT,
+            PRIMARY KEY (namespace, saleor_api_url)
+        )"
+    )
+});
+
+/// Binds `namespace`, `saleor_api_url`
+pub static GET: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "SELECT domain, token, saleor_api_url, app_id, jwks FROM {APL_TABLE}
+         WHERE namespace = $1 AND saleor_api_url = $2"
+    )
+});
+
+/// Binds `namespace`
+pub static GET_ALL: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "SELECT domain, token, saleor_api_url, app_id, jwks FROM {APL_TABLE}
+         WHERE namespace = $1 ORDER BY saleor_api_url"
+    )
+});
+
+/// Binds the columns in order, see [`bind_auth_data`]
+pub static SET: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "INSERT INTO {APL_TABLE} ({})
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (namespace, saleor_api_url) DO UPDATE SET
+            domain = EXCLUDED.domain,
+            token = EXCLUDED.token,
+            app_id = EXCLUDED.app_id,
+            jwks = EXCLUDED.jwks",
+        COLUMNS.join(", ")
+    )
+});
+
+/// Binds `namespace`, `saleor_api_url`, returns the deleted `saleor_api_url`
+pub static DELETE: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "DELETE FROM {APL_TABLE} WHERE namespace = $1 AND saleor_api_url = $2
+         RETURNING saleor_api_url"
+    )
+});
+
+pub fn connection_error(e: sqlx::Error) -> AplError {
+    AplError::Connection(e.to_string())
+}
+
+pub async fn get<DB>(
+    pool: &Pool<DB>,
+    namespace: &str,
+    saleor_api_url: &str,
+) -> Result<AuthData, AplError>
+where
+    DB: Database,
+    for<'q> &'q str: sqlx::Encode<'q, DB> + Type<DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'r> &'r str: ColumnIndex<DB::Row>,
+    for<'r> String: Decode<'r, DB> + Type<DB>,
+{
+    let row = sqlx::query(&GET)
+        .bind(namespace)
+        .bind(saleor_api_url)
+        .fetch_optional(pool)
+        .await
+        .map_err(connection_error)?
+        .ok_or(AplError::NotFound(
+            "haven't found entry for given url".to_owned(),
+        ))?;
+    row_to_auth_data(&row)
+}
+
+pub async fn get_all<DB>(pool: &Pool<DB>, namespace: &str) -> Result<Vec<AuthData>, AplError>
+where
+    DB: Database,
+    for<'q> &'q str: sqlx::Encode<'q, DB> + Type<DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'r> &'r str: ColumnIndex<DB::Row>,
+    for<'r> String: Decode<'r, DB> + Type<DB>,
+{
+    sqlx::query(&GET_ALL)
+        .bind(namespace)
+        .fetch_all(pool)
+        .await
+        .map_err(connection_error)?
+        .iter()
+        .map(row_to_auth_data)
+        .collect()
+}
+
+pub async fn set<DB>(pool: &Pool<DB>, namespace: &str, auth_data: &AuthData) -> Result<(), AplError>
+where
+    DB: Database,
+    for<'q> &'q str: sqlx::Encode<'q, DB> + Type<DB>,
+    for<'q> Option<&'q str>: sqlx::Encode<'q, DB> + Type<DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    bind_auth_data(sqlx::query(&SET), namespace, auth_data)
+        .execute(pool)
+        .await
+        .map_err(connection_error)?;
+    Ok(())
+}
+
+/// Fails with [`AplError::NotFound`] if there was nothing to delete
+pub async fn delete<DB>(
+    pool: &Pool<DB>,
+    namespace: &str,
+    saleor_api_url: &str,
+) -> Result<(), AplError>
+where
+    DB: Database,
+    for<'q> &'q str: sqlx::Encode<'q, DB> + Type<DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    sqlx::query(&DELETE)
+        .bind(namespace)
+        .bind(saleor_api_url)
+        .fetch_optional(pool)
+        .await
+        .map_err(connection_error)?
+        .map(|_| ())
+        .ok_or(AplError::NotFound(
+            "haven't found entry for given url".to_owned(),
+        ))
+}
+
+/// Binds the arguments of [`SET`]
+fn bind_auth_data<'q, DB: sqlx::Database>(
+    query: sqlx::query::Query<'q, DB, <DB as sqlx::Database>::Arguments<'q>>,
+    namespace: &'q str,
+    auth_data: &'q AuthData,
+) -> sqlx::query::Query<'q, DB, <DB as sqlx::Database>::Arguments<'q>>
+where
+    &'q str: sqlx::Encode<'q, DB> + Type<DB>,
+    Option<&'q str>: sqlx::Encode<'q, DB> + Type<DB>,
+{
+    query
+        .bind(namespace)
+        .bind(auth_data.saleor_api_url.as_str())
+        .bind(auth_data.domain.as_deref())
+        .bind(auth_data.token.as_str())
+        .bind(auth_data.app_id.as_str())
+        .bind(auth_data.jwks.as_deref())
+}
+
+fn row_to_auth_data<'r, R>(row: &'r R) -> Result<AuthData, AplError>
+where
+    R: Row,
+    &'r str: ColumnIndex<R>,
+    String: Decode<'r, R::Database> + Type<R::Database>,
+{
+    let get = |e: sqlx::Error| AplError::Serialization(e.to_string());
+    Ok(AuthData {
+        domain: row.try_get("domain").map_err(get)?,
+        token: row.try_get("token").map_err(get)?,
+        saleor_api_url: row.try_get("saleor_api_url").map_err(get)?,
+        app_id: row.try_get("app_id").map_err(get)?,
+        jwks: row.try_get("jwks").map_err(get)?,
+    })
+}
+
+/// Fails if `columns` of the existing table are missing any the APL needs
+pub fn check_columns(columns: &[String]) -> Result<(), AplError> {
+    if columns.is_empty() {
+        return Err(AplError::NotSupported(format!(
+            "table {APL_TABLE} doesn't exist"
+        )));
+    }
+    let missing: Vec<&str> = COLUMNS
+        .into_iter()
+        .filter(|c| !columns.iter().any(|col| col == c))
+        .collect();
+    if !missing.is_empty() {
+        return Err(AplError::NotSupported(format!(
+            "table {APL_TABLE} is missing columns {}",
+            missing.join(", ")
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use std::{str::FromStr, time::Duration};
+
+use async_trait::async_trait;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+};
+use tokio::sync::OnceCell;
+#[cfg(feature = "tracing")]
+use tracing::{debug, info};
+
+use super::{sql, AplError, APL};
+use crate::AuthData;
+
+/// Table the auth data of every app using the database is stored in
+pub const SQLITE_APL_TABLE: &str = sql::APL_TABLE;
+
+/**
+ Stores auth data in an sqlite database, keyed by the saleor api url within `namespace`, in the
+ same table layout as the postgres APL. Unlike [`super::file_apl::FileApl`], every write is a
+ single atomic statement and the database runs in WAL mode with a busy timeout, so multiple apps
+ or processes can share the file.
+ The database and table get created on first use.
+
+ `apl_url` is either a path, eg. `./apl.sqlite`, or an sqlite url, eg. `sqlite://apl.sqlite`.
+*/
+#[derive(Debug, Clone)]
+pub struct SqliteApl {
+    pub pool: SqlitePool,
+    pub namespace: String,
+    schema: OnceCell<()>,
+}
+
+#[async_trait]
+impl APL for SqliteApl {
+    async fn get(&self, saleor_api_url: &str) -> Result<AuthData, AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("get(), {}", saleor_api_url);
+        let auth_data = sql::get(self.pool().await?, &self.namespace, saleor_api_url).await?;
+        #[cfg(feature = "tracing")]
+        info!("sucessful get");
+        Ok(auth_data)
+    }
+
+    async fn set(&self, auth_data: AuthData) -> Result<(), AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("set(), {}", auth_data.saleor_api_url);
+        sql::set(self.pool().await?, &self.namespace, &auth_data).await?;
+        #[cfg(feature = "tracing")]
+        info!("sucessful set");
+        Ok(())
+    }
+
+    async fn delete(&self, saleor_api_url: &str) -> Result<(), AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("delete(), {}", saleor_api_url);
+        sql::delete(self.pool().await?, &self.namespace, saleor_api_url).await?;
+        #[cfg(feature = "tracing")]
+        info!("sucessful delete");
+        Ok(())
+    }
+
+    async fn get_all(&self) -> Result<Vec<AuthData>, AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("get_all()");
+        sql::get_all(self.pool().await?, &self.namespace).await
+    }
+
+    async fn is_ready(&self) -> Result<(), AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("is_ready()");
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(sql::connection_error)?;
+        #[cfg(feature = "tracing")]
+        info!("sucessful is_ready");
+        Ok(())
+    }
+
+    async fn is_configured(&self) -> Result<(), AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("is_configured()");
+        let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?1)")
+            .bind(SQLITE_APL_TABLE)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(sql::connection_error)?;
+
+        sql::check_columns(&columns)?;
+        #[cfg(feature = "tracing")]
+        info!("sucessful is_configured");
+        Ok(())
+    }
+}
+
+impl SqliteApl {
+    /// Doesn't open the database until first used, as [`crate::SaleorApp::new`] isn't async
+    pub fn new(sqlite_url: &str, namespace: &str) -> Result<Self, AplError> {
+        #[cfg(feature = "tracing")]
+        debug!("creating sqlite apl with {sqlite_url}...");
+        let options = match sqlite_url.starts_with("sqlite:") {
+            true => SqliteConnectOptions::from_str(sqlite_url).map_err(sql::connection_error)?,
+            false => SqliteConnectOptions::new().filename(sqlite_url),
+        }
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(Duration::from_secs(5));
+
+        let pool = SqlitePoolOptions::new()
+            .acquire_timeout(Duration::from_secs(5))
+            .connect_lazy_with(options);
+        Ok(Self {
+            pool,
+            namespace: namespace.to_owned(),
+            schema: OnceCell::new(),
+        })
+    }
+
+    /// Opens the database and creates the table right away
+    pub async fn connect(sqlite_url: &str, namespace: &str) -> Result<Self, AplError> {
+        let apl = Self::new(sqlite_url, namespace)?;
+        apl.pool().await?;
+        Ok(apl)
+    }
+
+    /// Pool with the table created
+    async fn pool(&self) -> Result<&SqlitePool, AplError> {
+        self.schema
+            .get_or_try_init(|| async {
+                #[cfg(feature = "tracing")]
+                debug!("creating table {} if missing", SQLITE_APL_TABLE);
+                sqlx::query(&sql::CREATE_TABLE)
+                    .execute(&self.pool)
+                    .await
+                    .map(|_| ())
+                    .map_err(sql::connection_error)
+            })
+            .await?;
+        Ok(&self.pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join("saleor-app-sdk-sqlite-apl");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.sqlite"));
+        for suffix in ["", "-wal", "-shm"] {
+            _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+        path.display().to_string()
+    }
+
+    fn auth_data(saleor_api_url: &str) -> AuthData {
+        AuthData {
+            domain: Some("http://localhost:3000".to_owned()),
+            token: "token".to_owned(),
+            saleor_api_url: saleor_api_url.to_owned(),
+            app_id: "QXBwOjE=".to_owned(),
+            jwks: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn set_get_delete() {
+        let apl = SqliteApl::new(&db_path("set_get_delete"), "app").unwrap();
+        let saleor_api_url = "https://one.example.com/graphql/";
+        assert!(matches!(
+            apl.get(saleor_api_url).await,
+            Err(AplError::NotFound(_))
+        ));
+
+        apl.set(auth_data(saleor_api_url)).await.unwrap();
+        assert_eq!(apl.get(saleor_api_url).await.unwrap().token, "token");
+
+        let mut updated = auth_data(saleor_api_url);
+        updated.token = "new token".to_owned();
+        updated.jwks = Some("{}".to_owned());
+        apl.set(updated).await.unwrap();
+        let stored = apl.get(saleor_api_url).await.unwrap();
+        assert_eq!(stored.token, "new token");
+        assert_eq!(stored.jwks.as_deref(), Some("{}"));
+
+        apl.delete(saleor_api_url).await.unwrap();
+        assert!(matches!(
+            apl.delete(saleor_api_url).await,
+            Err(AplError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn is_ready_and_configured() {
+        let apl = SqliteApl::new(
+            &format!("sqlite://{}", db_path("is_ready_and_configured")),
+            "app",
+        )
+        .unwrap();
+        apl.is_ready().await.unwrap();
+        assert!(apl.is_configured().await.is_err());
+        apl.get_all().await.unwrap();
+        apl.is_configured().await.unwrap();
+    }
+
+    /// Separate pools stand in for separate processes sharing the file
+    #[tokio::test]
+    async fn concurrent_writers_share_the_database() {
+        let path = db_path("concurrent_writers");
+        let writers = (0..4)
+            .map(|w| {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    let apl = SqliteApl::new(&path, "app").unwrap();
+                    for i in 0..25 {
+                        apl.set(auth_data(&format!("https://{w}-{i}.example.com/graphql/")))
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let apl = SqliteApl::new(&path, "app").unwrap();
+        assert_eq!(apl.get_all().await.unwrap().len(), 100);
+        let other_app = SqliteApl::new(&path, "other-app").unwrap();
+        assert!(other_app.get_all().await.unwrap().is_empty());
+    }
+}
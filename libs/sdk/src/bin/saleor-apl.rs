@@ -0,0 +1,260 @@
+//! Lists, exports, imports, deletes and migrates auth data of APLs. Configured with the same env
+//! variables as the apps (`APL`, `APL_URL`, `APP_API_BASE_URL`...), options override them.
+
+use std::{collections::HashMap, io::Read, process::ExitCode, sync::Arc};
+
+use pico_args::Arguments;
+use saleor_app_sdk::{
+    apl::{AplError, AplType, APL},
+    config::Config,
+    AuthData, CreateSaleorAppError, SaleorApp,
+};
+use serde::Deserialize;
+use strum::VariantNames;
+
+fn help() -> String {
+    format!(
+        "\
+Inspects and migrates Saleor app APLs, configured by the same env variables as the apps
+
+USAGE:
+  saleor-apl <COMMAND> [OPTIONS]
+
+COMMANDS:
+  list                       Lists registered saleor instances, without tokens
+  export [FILE]              Writes all auth data as json to FILE, or stdout
+  import [FILE]              Stores auth data from json FILE, or stdin
+  delete <SALEOR_API_URL>    Removes the saleor instance
+  migrate                    Copies all auth data to the APL set by --to-apl and --to-apl-url
+  health                     Checks whether the APL is configured and ready
+
+OPTIONS:
+  --apl <TYPE>               Overrides APL, one of {}
+  --apl-url <URL>            Overrides APL_URL
+  --to-apl <TYPE>            APL to migrate to, defaults to the source APL type
+  --to-apl-url <URL>         APL_URL to migrate to
+  -h, --help                 Prints this help
+",
+        AplType::VARIANTS.join(", ")
+    )
+}
+
+#[derive(thiserror::Error, Debug)]
+enum CliError {
+    #[error("{0}")]
+    Args(#[from] pico_args::Error),
+    #[error("Failed loading config from env, {0}")]
+    Config(#[from] envy::Error),
+    #[error("{0}")]
+    CreateApl(#[from] CreateSaleorAppError),
+    #[error("{0}")]
+    Apl(#[from] AplError),
+    #[error("{0}")]
+    IO(#[from] std::io::Error),
+    #[error("Invalid auth data json, {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("{0}\n\n{}", help())]
+    Usage(&'static str),
+}
+
+/// Accepts both the output of `export` and the file of `FileApl`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Import {
+    List(Vec<AuthData>),
+    FileApl(HashMap<String, AuthData>),
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Arguments::from_env()).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(mut args: Arguments) -> Result<ExitCode, CliError> {
+    if args.contains(["-h", "--help"]) {
+        print!("{}", help());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut config = Config::load()?;
+    if let Some(apl) = args.opt_value_from_str::<_, AplType>("--apl")? {
+        config.apl = apl;
+    }
+    if let Some(apl_url) = args.opt_value_from_str("--apl-url")? {
+        config.apl_url = apl_url;
+    }
+    let to_apl: Option<AplType> = args.opt_value_from_str("--to-apl")?;
+    let to_apl_url: Option<String> = args.opt_value_from_str("--to-apl-url")?;
+
+    let command: Option<String> = args.subcommand()?;
+    let apl = SaleorApp::new(&config)?.apl;
+
+    match command.as_deref() {
+        Some("list") => {
+            for auth_data in apl.get_all().await? {
+                println!(
+                    "{}\tapp_id:{}\tdomain:{}",
+                    auth_data.saleor_api_url,
+                    auth_data.app_id,
+                    auth_data.domain.unwrap_or_default()
+                );
+            }
+        }
+        Some("export") => {
+            let json = serde_json::to_string_pretty(&apl.get_all().await?)?;
+            match args.opt_free_from_str::<String>()? {
+                Some(path) => std::fs::write(path, json)?,
+                None => println!("{json}"),
+            }
+        }
+        Some("import") => {
+            let json = match args.opt_free_from_str::<String>()? {
+                Some(path) => std::fs::read_to_string(path)?,
+                None => {
+                    let mut json = String::new();
+                    std::io::stdin().read_to_string(&mut json)?;
+                    json
+                }
+            };
+            let auth_data = parse_import(&json)?;
+            let count = auth_data.len();
+            for auth_data in auth_data {
+                apl.set(auth_data).await?;
+            }
+            println!("imported {count} saleor instances");
+        }
+        Some("delete") => {
+            let saleor_api_url: String = args
+                .opt_free_from_str()?
+                .ok_or(CliError::Usage("delete needs the saleor api url"))?;
+            apl.delete(&saleor_api_url).await?;
+            println!("deleted {saleor_api_url}");
+        }
+        Some("migrate") => {
+            let mut to_config = config.clone();
+            to_config.apl = to_apl.unwrap_or(config.apl.clone());
+            to_config.apl_url = to_apl_url.ok_or(CliError::Usage("migrate needs --to-apl-url"))?;
+            let to = SaleorApp::new(&to_config)?.apl;
+            let count = migrate(apl.as_ref(), to.as_ref()).await?;
+            println!("migrated {count} saleor instances");
+        }
+        Some("health") => return Ok(health(apl).await),
+        Some(_) => return Err(CliError::Usage("unknown command")),
+        None => return Err(CliError::Usage("missing command")),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn parse_import(json: &str) -> Result<Vec<AuthData>, serde_json::Error> {
+    Ok(match serde_json::from_str(json)? {
+        Import::List(auth_data) => auth_data,
+        Import::FileApl(auth_data) => auth_data.into_values().collect(),
+    })
+}
+
+/// Copies every auth data, entries already in `to` get overwritten. Database APLs create their
+/// tables on first write, so `to` only has to be reachable
+async fn migrate(from: &dyn APL, to: &dyn APL) -> Result<usize, AplError> {
+    to.is_ready().await?;
+    let all = from.get_all().await?;
+    let count = all.len();
+    for auth_data in all {
+        to.set(auth_data).await?;
+    }
+    Ok(count)
+}
+
+async fn health(apl: Arc<dyn APL>) -> ExitCode {
+    let mut code = ExitCode::SUCCESS;
+    match apl.is_configured().await {
+        Ok(_) => println!("configured: ok"),
+        Err(e) => {
+            println!("configured: {e}");
+            code = ExitCode::FAILURE;
+        }
+    }
+    match apl.is_ready().await {
+        Ok(_) => println!("ready: ok"),
+        Err(e) => {
+            println!("ready: {e}");
+            code = ExitCode::FAILURE;
+        }
+    }
+    code
+}
+
+#[cfg(all(test, feature = "memory_apl"))]
+mod tests {
+    use saleor_app_sdk::apl::memory_apl::MemoryApl;
+
+    use super::*;
+
+    fn auth_data(saleor_api_url: &str) -> AuthData {
+        AuthData {
+            domain: Some("example.com".into()),
+            token: "token".into(),
+            saleor_api_url: saleor_api_url.into(),
+            app_id: "app".into(),
+            jwks: None,
+        }
+    }
+
+    fn urls_and_tokens(all: Vec<AuthData>) -> Vec<(String, String)> {
+        all.into_iter()
+            .map(|a| (a.saleor_api_url, a.token))
+            .collect()
+    }
+
+    #[test]
+    fn help_lists_every_apl_type() {
+        assert!(help().contains("one of Redis, File, Postgres, Sqlite, Memory\n"));
+    }
+
+    #[tokio::test]
+    async fn migrates_between_apls() {
+        let from = MemoryApl::new();
+        let to = MemoryApl::new();
+        from.set(auth_data("https://one.example.com/graphql/"))
+            .await
+            .unwrap();
+        from.set(auth_data("https://two.example.com/graphql/"))
+            .await
+            .unwrap();
+        to.set(AuthData {
+            token: "stale".into(),
+            ..auth_data("https://one.example.com/graphql/")
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(migrate(&from, &to).await.unwrap(), 2);
+        let mut migrated = urls_and_tokens(to.get_all().await.unwrap());
+        migrated.sort();
+        assert_eq!(
+            migrated,
+            vec![
+                ("https://one.example.com/graphql/".into(), "token".into()),
+                ("https://two.example.com/graphql/".into(), "token".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_export_and_file_apl_json() {
+        let one = auth_data("https://one.example.com/graphql/");
+        let export = serde_json::to_string(&vec![one.clone()]).unwrap();
+        let file_apl =
+            serde_json::to_string(&HashMap::from([(one.saleor_api_url.clone(), one.clone())]))
+                .unwrap();
+
+        let expected = urls_and_tokens(vec![one]);
+        assert_eq!(urls_and_tokens(parse_import(&export).unwrap()), expected);
+        assert_eq!(urls_and_tokens(parse_import(&file_apl).unwrap()), expected);
+        assert!(parse_import("{\"token\": \"token\"}").is_err());
+    }
+}
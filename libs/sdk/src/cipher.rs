@@ -0,0 +1,66 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+const NONCE_LEN: usize = 24;
+
+/**
+ XChaCha20-Poly1305 with the key derived from a secret through HKDF-SHA256, used by the
+ encrypted APL and the encrypted settings manager. Encrypted values are base64 of the random
+ nonce followed by the ciphertext.
+*/
+#[derive(Clone)]
+pub(crate) struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    /// `info` tells apart keys derived from the same secret for different uses
+    pub fn new(secret: &str, info: &[u8]) -> Self {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, secret.as_bytes())
+            .expand(info, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Self(XChaCha20Poly1305::new(&key.into()))
+    }
+
+    /// `aad` gets authenticated with the value, decrypting needs the same one
+    pub fn encrypt(&self, plaintext: &str, aad: &str) -> Option<String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .ok()?;
+        let mut out = nonce.to_vec();
+        out.extend(ciphertext);
+        Some(STANDARD.encode(out))
+    }
+
+    /// None if `encrypted` is corrupted, or was encrypted with another key or `aad`
+    pub fn decrypt(&self, encrypted: &str, aad: &str) -> Option<String> {
+        let raw = STANDARD.decode(encrypted).ok()?;
+        if raw.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = raw.split_at(NONCE_LEN);
+        let plaintext = self
+            .0
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
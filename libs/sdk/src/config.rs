@@ -20,7 +20,7 @@ fn version_default() -> String {
     ">=3.11.7<4".to_owned()
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Clone)]
 //#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct Config {
     #[serde(default = "version_default")]
@@ -29,11 +29,52 @@ pub struct Config {
     pub app_iframe_base_url: String,
     pub apl: AplType,
     pub apl_url: String,
+    /// Namespace the Redis, Postgres and SQLite APLs keep auth data under, set a different one per
+    /// app when apps share a database. Required by Redis, the others default to
+    /// [`crate::apl::DEFAULT_APL_NAMESPACE`]
+    pub apl_namespace: Option<String>,
+    /// Saleor api urls allowed to register the app, comma separated. Empty allows any instance
+    #[serde(default)]
+    pub allowed_saleor_api_urls: Vec<String>,
+    /// Key settings managers encrypt private metadata with, keep it secret and don't change it
+    pub secret_key: Option<String>,
+    /// Keys app tokens get encrypted with in the APL as `key_id:secret`, comma separated. The
+    /// first one encrypts, the rest are only used for decrypting. Empty stores tokens as they are
+    #[serde(default)]
+    pub apl_encryption_keys: Vec<String>,
     #[cfg(feature = "tracing")]
     #[serde(with = "LocalTracingLevel")]
     pub log_level: tracing::Level,
 }
 
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct("Config");
+        s.field("required_saleor_version", &self.required_saleor_version)
+            .field("app_api_base_url", &self.app_api_base_url)
+            .field("app_iframe_base_url", &self.app_iframe_base_url)
+            .field("apl", &self.apl)
+            .field("apl_url", &self.apl_url)
+            .field("apl_namespace", &self.apl_namespace)
+            .field("allowed_saleor_api_urls", &self.allowed_saleor_api_urls)
+            .field(
+                "secret_key",
+                &self.secret_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field(
+                "apl_encryption_keys",
+                &self
+                    .apl_encryption_keys
+                    .iter()
+                    .map(|k| k.split_once(':').map_or("<redacted>", |(id, _)| id))
+                    .collect::<Vec<_>>(),
+            );
+        #[cfg(feature = "tracing")]
+        s.field("log_level", &self.log_level);
+        s.finish()
+    }
+}
+
 impl std::fmt::Display for Config {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -48,4 +89,14 @@ impl Config {
         debug!("{:?}", &env);
         env
     }
+
+    /// Whether `saleor_api_url` is in `allowed_saleor_api_urls`, or the list is empty
+    pub fn is_saleor_api_url_allowed(&self, saleor_api_url: &str) -> bool {
+        let mut allowed = self
+            .allowed_saleor_api_urls
+            .iter()
+            .filter(|allowed| !allowed.is_empty())
+            .peekable();
+        allowed.peek().is_none() || allowed.any(|allowed| allowed == saleor_api_url)
+    }
 }
@@ -1,4 +1,16 @@
-use serde::{Deserialize, Serialize};
+#[cfg(feature = "middleware")]
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::CONTENT_LENGTH, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[cfg(feature = "middleware")]
+use crate::webhooks::utils::{get_webhook_event_type, GetWebhookTypeError};
+use crate::webhooks::EitherWebhookType;
 
 pub const SALEOR_DOMAIN_HEADER: &str = "saleor-domain";
 pub const SALEOR_EVENT_HEADER: &str = "saleor-event";
@@ -7,21 +19,239 @@ pub const SALEOR_AUTHORIZATION_BEARER_HEADER: &str = "authorization-bearer";
 pub const SALEOR_API_URL_HEADER: &str = "saleor-api-url";
 pub const SALEOR_SCHEMA_VERSION: &str = "saleor-schema-version";
 
-#[derive(Serialize, Deserialize)]
-pub struct SaleorHeaders<'a> {
-    #[serde(rename = "saleor-domain")]
-    #[serde(alias = "x-saleor-domain")]
-    domain: Option<&'a str>,
-    #[serde(rename = "authorization-bearer")]
-    authorization_bearer: Option<&'a str>,
-    #[serde(rename = "saleor-signature")]
-    #[serde(alias = "x-saleor-signature")]
-    signature: Option<&'a str>,
-    #[serde(rename = "saleor-event")]
-    #[serde(alias = "x-saleor-event")]
-    event: Option<&'a str>,
-    #[serde(rename = "saleor-api-url")]
-    saleor_api_url: Option<&'a str>,
-    #[serde(rename = "content-length")]
-    content_length: u16,
+/// Names older saleor versions send the headers under
+pub const LEGACY_SALEOR_DOMAIN_HEADER: &str = "x-saleor-domain";
+pub const LEGACY_SALEOR_EVENT_HEADER: &str = "x-saleor-event";
+pub const LEGACY_SALEOR_SIGNATURE_HEADER: &str = "x-saleor-signature";
+
+/// Legacy name of a header, if it has one
+pub fn legacy_header_name(name: &str) -> Option<&'static str> {
+    match name {
+        SALEOR_DOMAIN_HEADER => Some(LEGACY_SALEOR_DOMAIN_HEADER),
+        SALEOR_EVENT_HEADER => Some(LEGACY_SALEOR_EVENT_HEADER),
+        SALEOR_SIGNATURE_HEADER => Some(LEGACY_SALEOR_SIGNATURE_HEADER),
+        _ => None,
+    }
+}
+
+/// Gets header `name`, falling back to its `x-saleor-*` legacy name
+#[cfg(feature = "webhook_utils")]
+pub fn get_saleor_header<'a>(
+    headers: &'a http::HeaderMap,
+    name: &str,
+) -> Option<&'a http::HeaderValue> {
+    headers
+        .get(name)
+        .or_else(|| legacy_header_name(name).and_then(|legacy| headers.get(legacy)))
+}
+
+#[cfg(feature = "middleware")]
+#[derive(thiserror::Error, Debug)]
+pub enum SaleorHeadersError {
+    #[error("Header {0} isn't valid ascii")]
+    NonAscii(&'static str),
+    #[error("Failed parsing saleor-event, {0}")]
+    Event(#[from] GetWebhookTypeError),
+    #[error("Failed parsing saleor-schema-version, {0}")]
+    SchemaVersion(#[from] semver::Error),
+    #[error("Failed parsing content-length")]
+    ContentLength,
+}
+
+#[cfg(feature = "middleware")]
+impl IntoResponse for SaleorHeadersError {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+    }
+}
+
+/**
+ Headers saleor sends with webhooks and dashboard requests. Every header is optional, as
+ which ones are present depends on the request, but ones that are present have to parse.
+ Headers under their legacy `x-saleor-*` names are picked up too. De/serializes with the
+ header names as keys.
+
+ ```ignore
+ async fn webhooks(headers: SaleorHeaders, body: String) -> StatusCode {
+     if let Some(EitherWebhookType::Async(event)) = headers.event { .. }
+ }
+ ```
+*/
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SaleorHeaders {
+    #[serde(rename = "saleor-domain", alias = "x-saleor-domain", default)]
+    pub domain: Option<String>,
+    #[serde(rename = "saleor-api-url", default)]
+    pub saleor_api_url: Option<String>,
+    #[serde(rename = "saleor-event", alias = "x-saleor-event", default)]
+    pub event: Option<EitherWebhookType>,
+    #[serde(rename = "saleor-signature", alias = "x-saleor-signature", default)]
+    pub signature: Option<String>,
+    #[serde(rename = "authorization-bearer", default)]
+    pub authorization_bearer: Option<String>,
+    #[serde(
+        rename = "saleor-schema-version",
+        default,
+        deserialize_with = "schema_version"
+    )]
+    pub schema_version: Option<semver::Version>,
+    #[serde(rename = "content-length", default)]
+    pub content_length: Option<u64>,
+}
+
+#[cfg(feature = "middleware")]
+impl SaleorHeaders {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, SaleorHeadersError> {
+        let get = |name: &'static str| -> Result<Option<String>, SaleorHeadersError> {
+            get_saleor_header(headers, name)
+                .map(|h| h.to_str().map(str::to_owned))
+                .transpose()
+                .map_err(|_| SaleorHeadersError::NonAscii(name))
+        };
+
+        let event = match get_webhook_event_type(headers) {
+            Ok(event) => Some(event),
+            Err(GetWebhookTypeError::MissingWebhookTypeHeader) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let schema_version = get(SALEOR_SCHEMA_VERSION)?
+            .map(|v| parse_schema_version(&v))
+            .transpose()?;
+        let content_length = get(CONTENT_LENGTH.as_str())?
+            .map(|l| l.parse().map_err(|_| SaleorHeadersError::ContentLength))
+            .transpose()?;
+
+        Ok(Self {
+            domain: get(SALEOR_DOMAIN_HEADER)?,
+            saleor_api_url: get(SALEOR_API_URL_HEADER)?,
+            event,
+            signature: get(SALEOR_SIGNATURE_HEADER)?,
+            authorization_bearer: get(SALEOR_AUTHORIZATION_BEARER_HEADER)?,
+            schema_version,
+            content_length,
+        })
+    }
+}
+
+/// Saleor sends versions like `3.20`, which semver needs padded to `3.20.0`
+pub fn parse_schema_version(version: &str) -> Result<semver::Version, semver::Error> {
+    let version = version.trim();
+    match version.matches('.').count() {
+        0 => semver::Version::parse(&format!("{version}.0.0")),
+        1 => semver::Version::parse(&format!("{version}.0")),
+        _ => semver::Version::parse(version),
+    }
+}
+
+fn schema_version<'de, D>(deserializer: D) -> Result<Option<semver::Version>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|v| parse_schema_version(&v))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(feature = "middleware")]
+#[async_trait]
+impl<S> FromRequestParts<S> for SaleorHeaders
+where
+    S: Send + Sync,
+{
+    type Rejection = SaleorHeadersError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Self::from_headers(&parts.headers)
+    }
+}
+
+#[cfg(all(test, feature = "middleware"))]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::webhooks::AsyncWebhookEventType;
+
+    #[test]
+    fn pads_short_schema_versions() {
+        assert_eq!(
+            parse_schema_version("3.20").unwrap(),
+            semver::Version::new(3, 20, 0)
+        );
+        assert_eq!(
+            parse_schema_version("3").unwrap(),
+            semver::Version::new(3, 0, 0)
+        );
+        assert_eq!(
+            parse_schema_version("3.20.1").unwrap(),
+            semver::Version::new(3, 20, 1)
+        );
+        assert!(parse_schema_version("three").is_err());
+    }
+
+    #[test]
+    fn reads_legacy_header_names() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            LEGACY_SALEOR_EVENT_HEADER,
+            HeaderValue::from_static("product_updated"),
+        );
+        headers.insert(
+            LEGACY_SALEOR_DOMAIN_HEADER,
+            HeaderValue::from_static("example.com"),
+        );
+        headers.insert(SALEOR_SCHEMA_VERSION, HeaderValue::from_static("3.20"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("100000"));
+
+        let saleor_headers = SaleorHeaders::from_headers(&headers).unwrap();
+        assert!(matches!(
+            saleor_headers.event,
+            Some(EitherWebhookType::Async(
+                AsyncWebhookEventType::ProductUpdated
+            ))
+        ));
+        assert_eq!(saleor_headers.domain.as_deref(), Some("example.com"));
+        assert_eq!(
+            saleor_headers.schema_version,
+            Some(semver::Version::new(3, 20, 0))
+        );
+        assert_eq!(saleor_headers.content_length, Some(100000));
+        assert!(saleor_headers.saleor_api_url.is_none());
+    }
+
+    #[test]
+    fn deserializes_from_header_names() {
+        let saleor_headers: SaleorHeaders = serde_json::from_value(serde_json::json!({
+            "x-saleor-event": "product_updated",
+            "saleor-api-url": "https://example.com/graphql/",
+            "saleor-schema-version": "3.20",
+        }))
+        .unwrap();
+        assert!(matches!(
+            saleor_headers.event,
+            Some(EitherWebhookType::Async(
+                AsyncWebhookEventType::ProductUpdated
+            ))
+        ));
+        assert_eq!(
+            saleor_headers.schema_version,
+            Some(semver::Version::new(3, 20, 0))
+        );
+
+        let json = serde_json::to_value(&saleor_headers).unwrap();
+        assert_eq!(json["saleor-event"], "product_updated");
+        let again: SaleorHeaders = serde_json::from_value(json).unwrap();
+        assert_eq!(again.saleor_api_url, saleor_headers.saleor_api_url);
+    }
+
+    #[test]
+    fn rejects_unknown_events() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SALEOR_EVENT_HEADER,
+            HeaderValue::from_static("not_an_event"),
+        );
+        assert!(SaleorHeaders::from_headers(&headers).is_err());
+    }
 }
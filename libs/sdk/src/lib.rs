@@ -1,30 +1,44 @@
 pub mod apl;
 #[cfg(feature = "bridge")]
 pub mod bridge;
+#[cfg(any(feature = "encrypted_apl", feature = "settings_manager"))]
+mod cipher;
 pub mod config;
 pub mod headers;
 pub mod manifest;
 #[cfg(feature = "middleware")]
 pub mod middleware;
+#[cfg(feature = "mock_saleor")]
+pub mod mock_saleor;
 #[cfg(feature = "settings_manager")]
 pub mod settings_manager;
 pub mod webhooks;
 
+use std::sync::Arc;
+
 use apl::{AplError, AplType, APL};
 use config::Config;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "encrypted_apl")]
+use crate::apl::encrypted_apl::EncryptedApl;
 #[cfg(feature = "file_apl")]
 use crate::apl::file_apl::FileApl;
+#[cfg(feature = "memory_apl")]
+use crate::apl::memory_apl::MemoryApl;
+#[cfg(feature = "postgres_apl")]
+use crate::apl::postgres_apl::PostgresApl;
 #[cfg(feature = "redis_apl")]
 use crate::apl::redis_apl::RedisApl;
+#[cfg(feature = "sqlite_apl")]
+use crate::apl::sqlite_apl::SqliteApl;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthToken {
     pub auth_token: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthData {
     pub domain: Option<String>,
@@ -34,13 +48,25 @@ pub struct AuthData {
     pub jwks: Option<String>,
 }
 
+/// Never prints the token, so auth data can be logged
+impl std::fmt::Debug for AuthData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthData")
+            .field("domain", &self.domain)
+            .field("token", &"<redacted>")
+            .field("saleor_api_url", &self.saleor_api_url)
+            .field("app_id", &self.app_id)
+            .field("jwks", &self.jwks)
+            .finish()
+    }
+}
+
 impl std::fmt::Display for AuthData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "(domain:{}\ntoken:{}\nsaleor_api_url:{}\napp_id:{}\njwks:{})",
+            "(domain:{}\ntoken:<redacted>\nsaleor_api_url:{}\napp_id:{}\njwks:{})",
             self.domain.clone().unwrap_or_default(),
-            self.token,
             self.saleor_api_url,
             self.app_id,
             self.jwks.clone().unwrap_or_default()
@@ -50,7 +76,7 @@ impl std::fmt::Display for AuthData {
 
 #[derive(Debug)]
 pub struct SaleorApp {
-    pub apl: Box<dyn APL>,
+    pub apl: Arc<dyn APL>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -63,14 +89,15 @@ pub enum CreateSaleorAppError {
 
 impl SaleorApp {
     pub fn new(config: &Config) -> Result<SaleorApp, CreateSaleorAppError> {
-        use AplType::{File, Redis};
-        fn decide_apl(config: &Config) -> Result<Box<dyn APL>, CreateSaleorAppError> {
+        use AplType::{File, Memory, Postgres, Redis, Sqlite};
+        fn decide_apl(config: &Config) -> Result<Arc<dyn APL>, CreateSaleorAppError> {
             match config.apl {
                 Redis => {
                     #[cfg(feature = "redis_apl")]
-                    return Ok(Box::new(RedisApl::new(
+                    return Ok(Arc::new(RedisApl::new(
                         &config.apl_url,
                         &config.app_api_base_url,
+                        config.apl_namespace.as_deref(),
                     )?));
 
                     #[cfg(not(feature = "redis_apl"))]
@@ -80,7 +107,7 @@ impl SaleorApp {
                 }
                 File => {
                     #[cfg(feature = "file_apl")]
-                    return Ok(Box::new(FileApl {
+                    return Ok(Arc::new(FileApl {
                         path: config.apl_url.to_owned(),
                     }));
                     #[cfg(not(feature = "file_apl"))]
@@ -88,11 +115,55 @@ impl SaleorApp {
                         return Err(CreateSaleorAppError ::MissingFeature("Tried starting app with file apl that wasn't present at compile time (cargo feature missing)".to_string()));
                     }
                 }
+                Postgres => {
+                    #[cfg(feature = "postgres_apl")]
+                    return Ok(Arc::new(PostgresApl::new(
+                        &config.apl_url,
+                        config
+                            .apl_namespace
+                            .as_deref()
+                            .unwrap_or(apl::DEFAULT_APL_NAMESPACE),
+                    )?));
+                    #[cfg(not(feature = "postgres_apl"))]
+                    {
+                        return Err(CreateSaleorAppError ::MissingFeature("Tried starting app with postgres apl that wasn't present at compile time (cargo feature missing)".to_string()));
+                    }
+                }
+                Sqlite => {
+                    #[cfg(feature = "sqlite_apl")]
+                    return Ok(Arc::new(SqliteApl::new(
+                        &config.apl_url,
+                        config
+                            .apl_namespace
+                            .as_deref()
+                            .unwrap_or(apl::DEFAULT_APL_NAMESPACE),
+                    )?));
+                    #[cfg(not(feature = "sqlite_apl"))]
+                    {
+                        return Err(CreateSaleorAppError ::MissingFeature("Tried starting app with sqlite apl that wasn't present at compile time (cargo feature missing)".to_string()));
+                    }
+                }
+                Memory => {
+                    #[cfg(feature = "memory_apl")]
+                    return Ok(Arc::new(MemoryApl::from_file(&config.apl_url)?));
+                    #[cfg(not(feature = "memory_apl"))]
+                    {
+                        return Err(CreateSaleorAppError ::MissingFeature("Tried starting app with memory apl that wasn't present at compile time (cargo feature missing)".to_string()));
+                    }
+                }
             }
         }
-        match decide_apl(config) {
-            Ok(apl) => Ok(SaleorApp { apl }),
-            Err(e) => Err(e),
+        let apl = decide_apl(config)?;
+        if config.apl_encryption_keys.iter().all(|k| k.is_empty()) {
+            return Ok(SaleorApp { apl });
+        }
+        #[cfg(feature = "encrypted_apl")]
+        return Ok(SaleorApp {
+            apl: Arc::new(EncryptedApl::new(apl, &config.apl_encryption_keys)?),
+        });
+        #[cfg(not(feature = "encrypted_apl"))]
+        {
+            return Err(CreateSaleorAppError ::MissingFeature("Tried starting app with APL_ENCRYPTION_KEYS but encrypted apl wasn't present at compile time (cargo feature missing)".to_string()));
         }
     }
 }
@@ -246,6 +246,13 @@ impl AppManifestBuilder {
         }
         self
     }
+    pub fn add_webhooks(mut self, mut webhooks: Vec<WebhookManifest>) -> Self {
+        match &mut self.manifest.webhooks {
+            Some(w) => w.append(&mut webhooks),
+            None => self.manifest.webhooks = Some(webhooks),
+        }
+        self
+    }
     pub fn add_permission(mut self, permissions: AppPermission) -> Self {
         self.manifest.permissions.push(permissions);
         self
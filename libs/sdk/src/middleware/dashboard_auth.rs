@@ -0,0 +1,347 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{decode, decode_header, Algorithm, Validation};
+use serde::{Deserialize, Deserializer};
+use tracing::debug;
+
+use super::jwks::{JwksError, JWKS_CACHE};
+use crate::{
+    apl::{AplError, APL},
+    headers::{SALEOR_API_URL_HEADER, SALEOR_AUTHORIZATION_BEARER_HEADER},
+    manifest::AppPermission,
+    AuthData,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum DashboardAuthError {
+    #[error("Missing or non-ascii header {0}")]
+    MissingHeader(&'static str),
+    #[error("Saleor instance isn't registered, {0}")]
+    NotRegistered(#[from] AplError),
+    #[error("Failed getting key to verify the token with, {0}")]
+    UnknownKey(#[from] JwksError),
+    #[error("Invalid dashboard token, {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    #[error("Token was issued for app {0}, not this one")]
+    WrongApp(String),
+    #[error("User is missing permissions {0:?}")]
+    MissingPermissions(Vec<AppPermission>),
+}
+
+impl IntoResponse for DashboardAuthError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::MissingPermissions(_) => {
+                (StatusCode::FORBIDDEN, self.to_string()).into_response()
+            }
+            _ => (StatusCode::UNAUTHORIZED, "Not authenticated\n").into_response(),
+        }
+    }
+}
+
+/**
+ Claims of the JWT the dashboard sends in `authorization-bearer`, see
+ `bridge::AppBridgeUser` for the client side counterpart.
+*/
+#[derive(Debug, Clone, Deserialize)]
+pub struct DashboardUser {
+    pub iat: u64,
+    pub exp: u64,
+    pub iss: String,
+    pub email: String,
+    pub user_id: String,
+    pub is_staff: bool,
+    /// Id of the app the token was issued for
+    pub app: String,
+    #[serde(default, deserialize_with = "known_permissions")]
+    pub user_permissions: Vec<AppPermission>,
+}
+
+impl DashboardUser {
+    pub fn has_permissions(&self, permissions: &[AppPermission]) -> bool {
+        permissions
+            .iter()
+            .all(|p| self.user_permissions.contains(p))
+    }
+}
+
+/// Saleor adds permissions over time, ones this SDK doesn't know yet get skipped
+fn known_permissions<'de, D>(deserializer: D) -> Result<Vec<AppPermission>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let permissions = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(permissions
+        .into_iter()
+        .filter_map(|p| serde_json::from_value(p).ok())
+        .collect())
+}
+
+/**
+ Extractor for requests made from the app iframe in the dashboard. Verifies the user token in
+ `authorization-bearer` against the JWKS of the saleor instance in `saleor-api-url`, and that
+ it was issued for the app registered in the APL. Needs `Arc<dyn APL>` to be extractable from
+ the router state via [`FromRef`].
+
+ ```ignore
+ async fn settings(DashboardAuth { user, auth_data }: DashboardAuth) -> impl IntoResponse { .. }
+ ```
+*/
+#[derive(Debug, Clone)]
+pub struct DashboardAuth {
+    pub user: DashboardUser,
+    pub auth_data: AuthData,
+}
+
+impl DashboardAuth {
+    pub fn require_permissions(
+        &self,
+        permissions: &[AppPermission],
+    ) -> Result<(), DashboardAuthError> {
+        let missing: Vec<AppPermission> = permissions
+            .iter()
+            .filter(|p| !self.user.user_permissions.contains(p))
+            .cloned()
+            .collect();
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(DashboardAuthError::MissingPermissions(missing)),
+        }
+    }
+}
+
+/**
+ Verifies the dashboard user token from `headers`, returning the user and [`AuthData`] of the
+ saleor instance it came from.
+*/
+pub async fn verify_dashboard_token(
+    apl: &dyn APL,
+    headers: &HeaderMap,
+) -> Result<DashboardAuth, DashboardAuthError> {
+    let header = |name: &'static str| {
+        headers
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .ok_or(DashboardAuthError::MissingHeader(name))
+    };
+    let saleor_api_url = header(SALEOR_API_URL_HEADER)?;
+    let token = header(SALEOR_AUTHORIZATION_BEARER_HEADER)?;
+    debug!("dashboard request from {:?}", saleor_api_url);
+
+    let auth_data = apl.get(saleor_api_url).await?;
+    let kid = decode_header(token)?.kid;
+    let key = JWKS_CACHE
+        .get_key_for(apl, &auth_data, kid.as_deref())
+        .await?;
+
+    let user = decode::<DashboardUser>(token, &key, &Validation::new(Algorithm::RS256))?.claims;
+    if user.app != auth_data.app_id {
+        return Err(DashboardAuthError::WrongApp(user.app));
+    }
+    Ok(DashboardAuth { user, auth_data })
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for DashboardAuth
+where
+    Arc<dyn APL>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = DashboardAuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let apl = Arc::<dyn APL>::from_ref(state);
+        verify_dashboard_token(apl.as_ref(), &parts.headers).await
+    }
+}
+
+/// Permissions a [`RequirePermissions`] guard checks the dashboard user for
+pub trait PermissionGuard {
+    const PERMISSIONS: &'static [AppPermission];
+}
+
+/**
+ Like [`DashboardAuth`], but also rejects users missing any of `G::PERMISSIONS` with 403.
+
+ ```ignore
+ struct ManageProducts;
+ impl PermissionGuard for ManageProducts {
+     const PERMISSIONS: &'static [AppPermission] = &[AppPermission::ManageProducts];
+ }
+
+ async fn update_prices(RequirePermissions(auth, _): RequirePermissions<ManageProducts>) { .. }
+ ```
+*/
+#[derive(Debug, Clone)]
+pub struct RequirePermissions<G>(pub DashboardAuth, pub PhantomData<G>);
+
+#[async_trait]
+impl<S, G> FromRequestParts<S> for RequirePermissions<G>
+where
+    Arc<dyn APL>: FromRef<S>,
+    S: Send + Sync,
+    G: PermissionGuard + Send + Sync,
+{
+    type Rejection = DashboardAuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let auth = DashboardAuth::from_request_parts(parts, state).await?;
+        auth.require_permissions(G::PERMISSIONS)?;
+        Ok(RequirePermissions(auth, PhantomData))
+    }
+}
+
+#[cfg(all(test, feature = "mock_saleor"))]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use axum::http::{HeaderValue, Request};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{apl::memory_apl::MemoryApl, mock_saleor::MockSaleor};
+
+    const SALEOR_API_URL: &str = "https://api.example.com/graphql/";
+    const OTHER_SALEOR_API_URL: &str = "https://other.example.com/graphql/";
+
+    #[derive(Debug)]
+    struct ManageProducts;
+    impl PermissionGuard for ManageProducts {
+        const PERMISSIONS: &'static [AppPermission] = &[AppPermission::ManageProducts];
+    }
+
+    /// APL with two instances sharing [`MockSaleor::jwks`], the app has a different id in each
+    async fn apl() -> Arc<dyn APL> {
+        let apl = MemoryApl::new();
+        for (saleor_api_url, app_id) in [(SALEOR_API_URL, "app"), (OTHER_SALEOR_API_URL, "other")] {
+            apl.set(AuthData {
+                domain: None,
+                token: "token".to_owned(),
+                saleor_api_url: saleor_api_url.to_owned(),
+                app_id: app_id.to_owned(),
+                jwks: serde_json::to_string(&MockSaleor::jwks()).ok(),
+            })
+            .await
+            .unwrap();
+        }
+        Arc::new(apl)
+    }
+
+    /// Dashboard token for `app`, signed like [`MockSaleor`] would
+    fn sign_token(app: &str, permissions: &[&str]) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        MockSaleor::sign_token(&json!({
+            "iat": now,
+            "exp": now + 60,
+            "iss": SALEOR_API_URL,
+            "email": "staff@example.com",
+            "user_id": "VXNlcjox",
+            "is_staff": true,
+            "app": app,
+            "user_permissions": permissions,
+        }))
+    }
+
+    fn headers(saleor_api_url: &str, token: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SALEOR_API_URL_HEADER,
+            HeaderValue::from_str(saleor_api_url).unwrap(),
+        );
+        if let Some(token) = token {
+            headers.insert(
+                SALEOR_AUTHORIZATION_BEARER_HEADER,
+                HeaderValue::from_str(token).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn request_parts(headers: HeaderMap) -> Parts {
+        let mut request = Request::builder().body(()).unwrap();
+        *request.headers_mut() = headers;
+        request.into_parts().0
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_token() {
+        let apl = apl().await;
+        let token = sign_token("app", &["MANAGE_PRODUCTS", "SOME_NEW_PERMISSION"]);
+        let auth = verify_dashboard_token(apl.as_ref(), &headers(SALEOR_API_URL, Some(&token)))
+            .await
+            .unwrap();
+        assert_eq!(auth.auth_data.saleor_api_url, SALEOR_API_URL);
+        assert_eq!(
+            auth.user.user_permissions,
+            vec![AppPermission::ManageProducts]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_token() {
+        let apl = apl().await;
+        let err = verify_dashboard_token(apl.as_ref(), &headers(SALEOR_API_URL, None))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DashboardAuthError::MissingHeader(SALEOR_AUTHORIZATION_BEARER_HEADER)
+        ));
+        assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_token() {
+        let apl = apl().await;
+        let mut token = sign_token("app", &[]);
+        // breaks the signature
+        token.push('A');
+        let err = verify_dashboard_token(apl.as_ref(), &headers(SALEOR_API_URL, Some(&token)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DashboardAuthError::InvalidToken(_)));
+        assert_eq!(err.into_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_token_from_other_instance() {
+        let apl = apl().await;
+        let token = sign_token("other", &[]);
+        let err = verify_dashboard_token(apl.as_ref(), &headers(SALEOR_API_URL, Some(&token)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, DashboardAuthError::WrongApp(app) if app == "other"));
+    }
+
+    #[tokio::test]
+    async fn guard_rejects_user_without_permissions() {
+        let apl = apl().await;
+        let token = sign_token("app", &["MANAGE_ORDERS"]);
+        let mut parts = request_parts(headers(SALEOR_API_URL, Some(&token)));
+        let err = RequirePermissions::<ManageProducts>::from_request_parts(&mut parts, &apl)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            DashboardAuthError::MissingPermissions(p) if p == &[AppPermission::ManageProducts]
+        ));
+        assert_eq!(err.into_response().status(), StatusCode::FORBIDDEN);
+
+        let token = sign_token("app", &["MANAGE_PRODUCTS"]);
+        let mut parts = request_parts(headers(SALEOR_API_URL, Some(&token)));
+        assert!(
+            RequirePermissions::<ManageProducts>::from_request_parts(&mut parts, &apl)
+                .await
+                .is_ok()
+        );
+    }
+}
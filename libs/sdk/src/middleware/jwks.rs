@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet},
+    DecodingKey,
+};
+use tracing::{debug, error};
+
+use crate::{apl::APL, AuthData};
+
+/// How long a fetched JWKS is trusted before it gets refetched from Saleor
+pub const DEFAULT_JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+/// Minimum time between two forced refetches for the same instance, so a burst of webhooks
+/// signed with an unknown `kid` can't be used to hammer Saleor
+pub const MIN_JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Process wide cache used by [`crate::middleware::verify_webhook_signature::webhook_signature_verifier`]
+pub static JWKS_CACHE: LazyLock<JwksCache> = LazyLock::new(|| JwksCache::new(DEFAULT_JWKS_TTL));
+
+#[derive(thiserror::Error, Debug)]
+pub enum JwksError {
+    #[error("Failed parsing saleor api url, {0}")]
+    UrlError(#[from] url::ParseError),
+    #[error("Failed fetching JWKS from saleor, {0}")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Failed creating decoding key from JWK, {0}")]
+    CryptoError(#[from] jsonwebtoken::errors::Error),
+    #[error("No key in JWKS matches kid {0:?}")]
+    UnknownKey(Option<String>),
+    #[error("Failed de/serializing JWKS, {0}")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone)]
+struct CachedJwks {
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+/**
+ Caches the JWKS of every Saleor instance (keyed by `saleor-api-url`) for `ttl`.
+ Keys get selected by the `kid` of the JWS header. If the `kid` isn't present in the cached set,
+ the set gets refetched once, as Saleor probably rotated its keys.
+*/
+#[derive(Debug)]
+pub struct JwksCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, CachedJwks>>,
+}
+
+impl JwksCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /**
+     Returns the key matching `kid` for given saleor instance, fetching or refetching the JWKS
+     when needed. If `kid` is `None`, the first key in the set is used.
+    */
+    pub async fn get_key(
+        &self,
+        saleor_api_url: &str,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey, JwksError> {
+        let jwks = self.get_jwks(saleor_api_url, kid).await?;
+        let jwk = find_key(&jwks, kid).ok_or(JwksError::UnknownKey(kid.map(|k| k.to_owned())))?;
+        Ok(DecodingKey::from_jwk(jwk)?)
+    }
+
+    /**
+     Returns the key matching `kid` from the JWKS persisted in `auth_data`. If there is none
+     stored or it doesn't contain `kid`, the JWKS gets (re)fetched from the registered saleor
+     instance and persisted back into the APL.
+    */
+    pub async fn get_key_for(
+        &self,
+        apl: &dyn APL,
+        auth_data: &AuthData,
+        kid: Option<&str>,
+    ) -> Result<DecodingKey, JwksError> {
+        if let Some(stored) = &auth_data.jwks {
+            let stored: JwkSet = serde_json::from_str(stored)?;
+            if let Some(jwk) = find_key(&stored, kid) {
+                return Ok(DecodingKey::from_jwk(jwk)?);
+            }
+            debug!("kid {:?} not in stored JWKS, refetching", kid);
+        }
+
+        let jwks = self.get_jwks(&auth_data.saleor_api_url, kid).await?;
+        let jwk = find_key(&jwks, kid).ok_or(JwksError::UnknownKey(kid.map(|k| k.to_owned())))?;
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        let mut auth_data = auth_data.clone();
+        auth_data.jwks = Some(serde_json::to_string(&jwks)?);
+        if let Err(e) = apl.set(auth_data).await {
+            error!("Failed persisting refetched JWKS into APL, {}", e);
+        }
+        Ok(key)
+    }
+
+    /**
+     Returns the JWKS of given saleor instance from cache, or fetches it if it's stale or
+     doesn't contain `kid`.
+    */
+    async fn get_jwks(&self, saleor_api_url: &str, kid: Option<&str>) -> Result<JwkSet, JwksError> {
+        let cached = self.get_cached(saleor_api_url);
+
+        if let Some(cached) = cached {
+            if cached.fetched_at.elapsed() < self.ttl {
+                if find_key(&cached.jwks, kid).is_some() {
+                    return Ok(cached.jwks);
+                }
+                if cached.fetched_at.elapsed() < MIN_JWKS_REFETCH_INTERVAL {
+                    debug!("unknown kid {:?}, but JWKS was refetched just now", kid);
+                    return Err(JwksError::UnknownKey(kid.map(|k| k.to_owned())));
+                }
+                debug!("unknown kid {:?}, refetching JWKS (key rotation?)", kid);
+            }
+        }
+
+        self.refetch(saleor_api_url).await
+    }
+
+    /// Drops the cached JWKS of given saleor instance
+    pub fn invalidate(&self, saleor_api_url: &str) {
+        if let Ok(mut entries) = self.entries.write() {
+            entries.remove(saleor_api_url);
+        }
+    }
+
+    fn get_cached(&self, saleor_api_url: &str) -> Option<CachedJwks> {
+        self.entries
+            .read()
+            .ok()
+            .and_then(|entries| entries.get(saleor_api_url).cloned())
+    }
+
+    async fn refetch(&self, saleor_api_url: &str) -> Result<JwkSet, JwksError> {
+        let jwks = fetch_jwks(saleor_api_url).await?;
+        if let Ok(mut entries) = self.entries.write() {
+            entries.insert(
+                saleor_api_url.to_owned(),
+                CachedJwks {
+                    jwks: jwks.clone(),
+                    fetched_at: Instant::now(),
+                },
+            );
+        }
+        Ok(jwks)
+    }
+}
+
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+}
+
+/// Fetches `/.well-known/jwks.json` from the saleor instance
+pub async fn fetch_jwks(saleor_api_url: &str) -> Result<JwkSet, JwksError> {
+    let mut jwks_url = url::Url::parse(saleor_api_url)?;
+    jwks_url.set_path("/.well-known/jwks.json");
+    debug!("fetching jwks from {}", &jwks_url);
+    let jwks = reqwest::get(jwks_url)
+        .await?
+        .error_for_status()?
+        .json::<JwkSet>()
+        .await?;
+    Ok(jwks)
+}
+
+/// Fetches the JWKS of the saleor instance, serialized the way it's stored in [`AuthData::jwks`]
+pub async fn fetch_jwks_string(saleor_api_url: &str) -> Result<String, JwksError> {
+    Ok(serde_json::to_string(&fetch_jwks(saleor_api_url).await?)?)
+}
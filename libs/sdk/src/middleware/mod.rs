@@ -1 +1,5 @@
+pub mod dashboard_auth;
+pub mod jwks;
+pub mod register;
 pub mod verify_webhook_signature;
+pub mod webhook_router;
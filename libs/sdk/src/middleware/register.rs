@@ -0,0 +1,147 @@
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::{debug, error, info};
+
+use super::jwks::{fetch_jwks_string, JwksError};
+use crate::{
+    apl::{AplError, APL},
+    config::Config,
+    headers::SALEOR_API_URL_HEADER,
+    AuthData, AuthToken,
+};
+
+const APP_ID_QUERY: &str = "query { app { id } }";
+
+#[derive(thiserror::Error, Debug)]
+pub enum RegisterError {
+    #[error("Missing or non-ascii header {0}")]
+    MissingHeader(&'static str),
+    #[error("Missing auth_token in request body")]
+    MissingToken,
+    #[error("Saleor api url is malformed, {0}")]
+    MalformedUrl(#[from] url::ParseError),
+    #[error("Saleor instance {0} isn't allowed to register this app")]
+    NotAllowed(String),
+    #[error("Saleor didn't accept the auth_token")]
+    InvalidToken,
+    #[error("Failed verifying auth_token with saleor, {0}")]
+    SaleorRequest(#[from] reqwest::Error),
+    #[error("Failed getting JWKS from saleor, {0}")]
+    Jwks(#[from] JwksError),
+    #[error("Failed saving auth data into APL, {0}")]
+    Apl(#[from] AplError),
+}
+
+impl IntoResponse for RegisterError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::MissingHeader(_) | Self::MissingToken | Self::MalformedUrl(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::NotAllowed(_) => StatusCode::FORBIDDEN,
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::SaleorRequest(_) | Self::Jwks(_) => StatusCode::BAD_GATEWAY,
+            Self::Apl(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = match status {
+            StatusCode::INTERNAL_SERVER_ERROR => "Failed registering app".to_owned(),
+            _ => self.to_string(),
+        };
+        (status, body).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct AppIdResponse {
+    data: Option<AppIdData>,
+}
+
+#[derive(Deserialize)]
+struct AppIdData {
+    app: Option<AppId>,
+}
+
+#[derive(Deserialize)]
+struct AppId {
+    id: String,
+}
+
+/**
+ Asks saleor for the app the token belongs to, returning its id. Fails with
+ [`RegisterError::InvalidToken`] if saleor doesn't recognise the token.
+*/
+pub async fn fetch_app_id(saleor_api_url: &str, token: &str) -> Result<String, RegisterError> {
+    let res = reqwest::Client::new()
+        .post(saleor_api_url)
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "query": APP_ID_QUERY }))
+        .send()
+        .await?;
+
+    if matches!(
+        res.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Err(RegisterError::InvalidToken);
+    }
+
+    res.error_for_status()?
+        .json::<AppIdResponse>()
+        .await?
+        .data
+        .and_then(|d| d.app)
+        .map(|a| a.id)
+        .ok_or(RegisterError::InvalidToken)
+}
+
+/**
+ Handles the body of `/api/register`. Checks `saleor-api-url` against
+ [`Config::allowed_saleor_api_urls`], verifies the token by querying saleor for the app it
+ belongs to, then stores the [`AuthData`] with the apps real id and the instances JWKS into the
+ APL. Returns the stored [`AuthData`], so apps can kick off their own work after registration.
+*/
+pub async fn register_app(
+    apl: &dyn APL,
+    config: &Config,
+    headers: &HeaderMap,
+    auth_token: AuthToken,
+) -> Result<AuthData, RegisterError> {
+    let saleor_api_url = headers
+        .get(SALEOR_API_URL_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(RegisterError::MissingHeader(SALEOR_API_URL_HEADER))?;
+    debug!("/api/register from {:?}", saleor_api_url);
+
+    url::Url::parse(saleor_api_url)?;
+    if !config.is_saleor_api_url_allowed(saleor_api_url) {
+        debug!("{:?} isn't in allowed saleor api urls", saleor_api_url);
+        return Err(RegisterError::NotAllowed(saleor_api_url.to_owned()));
+    }
+    if auth_token.auth_token.is_empty() {
+        return Err(RegisterError::MissingToken);
+    }
+
+    let app_id = fetch_app_id(saleor_api_url, &auth_token.auth_token).await?;
+    let jwks = fetch_jwks_string(saleor_api_url).await?;
+
+    let auth_data = AuthData {
+        jwks: Some(jwks),
+        token: auth_token.auth_token,
+        domain: Some(config.app_api_base_url.clone()),
+        app_id,
+        saleor_api_url: saleor_api_url.to_owned(),
+    };
+    if let Err(e) = apl.set(auth_data.clone()).await {
+        error!("Failed saving auth data into APL, {}", e);
+        return Err(e.into());
+    }
+
+    info!(
+        "registered app {} for {:?}",
+        &auth_data.app_id, &auth_data.saleor_api_url
+    );
+    Ok(auth_data)
+}
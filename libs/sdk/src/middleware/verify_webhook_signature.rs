@@ -1,76 +1,375 @@
-use axum::{body, extract::Request, http::StatusCode, middleware::Next, response::Response};
-use serde_json::Value;
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
-use jsonwebtoken::{crypto, Algorithm, DecodingKey};
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use http_body_util::LengthLimitError;
+use jsonwebtoken::{crypto, decode_header, Algorithm};
+use tower::{Layer, Service};
 use tracing::{debug, error};
 
-use crate::headers::{SALEOR_API_URL_HEADER, SALEOR_SIGNATURE_HEADER};
+use super::jwks::{JwksError, JWKS_CACHE};
+use crate::{
+    apl::{AplError, APL},
+    headers::{get_saleor_header, SALEOR_API_URL_HEADER, SALEOR_SIGNATURE_HEADER},
+};
 
-pub async fn webhook_signature_verifier(request: Request, next: Next) -> Response {
-    let unauthorized = Response::builder()
-        .status(StatusCode::UNAUTHORIZED)
-        .body(body::Body::from("Not authenticated\n"))
-        .unwrap();
+/// Webhook payloads bigger than this get rejected before verification, unless configured otherwise
+pub const DEFAULT_MAX_WEBHOOK_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookVerificationError {
+    #[error("Missing or non-ascii header {0}")]
+    MissingHeader(&'static str),
+    #[error("Malformed JWS in saleor-signature header, {0}")]
+    MalformedJws(String),
+    #[error("Saleor instance isn't registered, {0}")]
+    NotRegistered(AplError),
+    #[error("Failed reaching the APL, {0}")]
+    AplUnavailable(AplError),
+    #[error("Failed getting key to verify the signature with, {0}")]
+    UnknownKey(#[from] JwksError),
+    #[error("Webhook signature doesn't match the payload")]
+    InvalidSignature,
+    #[error("Webhook body is larger than {0} bytes")]
+    BodyTooLarge(usize),
+    #[error("Failed reading webhook body, {0}")]
+    BodyUnreadable(String),
+}
+
+/// Only a failing connection is worth a retry, anything else means the instance isn't registered
+impl From<AplError> for WebhookVerificationError {
+    fn from(e: AplError) -> Self {
+        match e {
+            AplError::Connection(_) => Self::AplUnavailable(e),
+            _ => Self::NotRegistered(e),
+        }
+    }
+}
+
+impl IntoResponse for WebhookVerificationError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::BodyTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::BodyUnreadable(_) => StatusCode::BAD_REQUEST,
+            // saleor retries deliveries that fail with 5xx
+            Self::AplUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::UNAUTHORIZED,
+        };
+        (status, "Not authenticated\n").into_response()
+    }
+}
 
-    let jwks_url = request
-        .headers()
+/**
+ Verifies the detached JWS from `saleor-signature` against the payload, using the key from
+ the JWKS stored in the APL for the saleor instance in `saleor-api-url`.
+ Requests from instances that never registered are rejected.
+*/
+pub async fn verify_webhook_signature(
+    apl: &dyn APL,
+    headers: &HeaderMap,
+    payload: &[u8],
+) -> Result<(), WebhookVerificationError> {
+    let saleor_api_url = headers
         .get(SALEOR_API_URL_HEADER)
-        .and_then(|h| h.to_str().map_or(None, |h| url::Url::parse(h).ok()));
-
-    debug!("request came from {:?}", jwks_url);
-    //get jwk from saleor api
-    let jwks: Value = 'block: {
-        if let Some(mut jwks_url) = jwks_url {
-            jwks_url.set_path("/.well-known/jwks.json");
-            if let Ok(get_res) = reqwest::get(jwks_url).await {
-                if let Ok(val) = get_res.json::<Value>().await {
-                    break 'block val;
-                }
-            }
+        .and_then(|h| h.to_str().ok())
+        .ok_or(WebhookVerificationError::MissingHeader(
+            SALEOR_API_URL_HEADER,
+        ))?;
+    debug!("request came from {:?}", saleor_api_url);
+
+    let jws = get_saleor_header(headers, SALEOR_SIGNATURE_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(WebhookVerificationError::MissingHeader(
+            SALEOR_SIGNATURE_HEADER,
+        ))?;
+
+    let (protected, signature) = match jws.split('.').collect::<Vec<_>>().as_slice() {
+        [protected, _, signature] => (*protected, *signature),
+        _ => {
+            return Err(WebhookVerificationError::MalformedJws(
+                "expected 3 dot separated parts".to_owned(),
+            ))
         }
-        error!("Saleor webhook signature not verified, failed fetching jwks from saleor");
-        return unauthorized;
     };
-    let nstr = jwks["keys"][0]["n"].as_str().unwrap();
-    let estr = jwks["keys"][0]["e"].as_str().unwrap();
 
-    let pubkey = DecodingKey::from_rsa_components(nstr, estr).unwrap();
+    //kid in the JWS header tells which key from saleors jwks signed the payload,
+    //decode_header wants the dot separated form, so it gets the whole detached JWS
+    let kid = decode_header(jws)
+        .map_err(|e| WebhookVerificationError::MalformedJws(e.to_string()))?
+        .kid;
+
+    let auth_data = apl.get(saleor_api_url).await?;
+    let pubkey = JWKS_CACHE
+        .get_key_for(apl, &auth_data, kid.as_deref())
+        .await?;
+
+    let mut msg: Vec<u8> = Vec::with_capacity(protected.len() + 1 + payload.len());
+    msg.extend_from_slice(protected.as_bytes());
+    msg.push(b'.');
+    msg.extend_from_slice(payload);
+
+    match crypto::verify(signature, &msg, &pubkey, Algorithm::RS256) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(WebhookVerificationError::InvalidSignature),
+        Err(e) => Err(WebhookVerificationError::MalformedJws(e.to_string())),
+    }
+}
 
+/**
+ Buffers the body up to `max_body_size` and verifies its signature, returning the request with
+ the buffered body so it can be passed on.
+*/
+async fn verify_request(
+    apl: &dyn APL,
+    request: Request,
+    max_body_size: usize,
+) -> Result<Request, WebhookVerificationError> {
     let (parts, body) = request.into_parts();
-    let payload = body::to_bytes(body, usize::MAX).await.unwrap();
-
-    if let Some(is_verified) = parts
-        .headers
-        .get(SALEOR_SIGNATURE_HEADER)
-        .and_then(|sig| sig.to_str().ok())
-        .and_then(|sig| {
-            let parts: Vec<&str> = sig.split('.').collect();
-            match parts.as_slice() {
-                [protected, _, signature] => Some((*protected, *signature)),
-                _ => None,
+    let payload: Bytes =
+        body::to_bytes(body, max_body_size)
+            .await
+            .map_err(|e| match e.into_inner() {
+                e if e.is::<LengthLimitError>() => {
+                    WebhookVerificationError::BodyTooLarge(max_body_size)
+                }
+                e => WebhookVerificationError::BodyUnreadable(e.to_string()),
+            })?;
+
+    verify_webhook_signature(apl, &parts.headers, &payload).await?;
+    Ok(Request::from_parts(parts, Body::from(payload)))
+}
+
+/**
+ Middleware for `axum::middleware::from_fn_with_state`, with the apps APL as state.
+ Uses [`DEFAULT_MAX_WEBHOOK_BODY_SIZE`], use [`WebhookSignatureLayer`] to configure it.
+*/
+pub async fn webhook_signature_verifier(
+    State(apl): State<Arc<dyn APL>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match verify_request(apl.as_ref(), request, DEFAULT_MAX_WEBHOOK_BODY_SIZE).await {
+        Ok(request) => {
+            debug!("Saleor webhook signature verified");
+            next.run(request).await
+        }
+        Err(e) => {
+            error!("Saleor webhook signature not verified, {}", e);
+            e.into_response()
+        }
+    }
+}
+
+/**
+ Tower layer verifying saleor webhook signatures, rejects unverified requests with 401
+ (or 413 if the body is too large).
+
+ ```ignore
+ Router::new()
+     .route("/api/webhooks", post(webhooks))
+     .layer(WebhookSignatureLayer::new(saleor_app.apl.clone()).max_body_size(1024 * 1024))
+ ```
+*/
+#[derive(Debug, Clone)]
+pub struct WebhookSignatureLayer {
+    apl: Arc<dyn APL>,
+    max_body_size: usize,
+}
+
+impl WebhookSignatureLayer {
+    pub fn new(apl: Arc<dyn APL>) -> Self {
+        Self {
+            apl,
+            max_body_size: DEFAULT_MAX_WEBHOOK_BODY_SIZE,
+        }
+    }
+
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+}
+
+impl<S> Layer<S> for WebhookSignatureLayer {
+    type Service = WebhookSignatureVerifier<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WebhookSignatureVerifier {
+            inner,
+            apl: self.apl.clone(),
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookSignatureVerifier<S> {
+    inner: S,
+    apl: Arc<dyn APL>,
+    max_body_size: usize,
+}
+
+impl<S> Service<Request> for WebhookSignatureVerifier<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // the clone isn't driven to readiness, so swap it with the one that is
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let apl = self.apl.clone();
+        let max_body_size = self.max_body_size;
+
+        Box::pin(async move {
+            match verify_request(apl.as_ref(), request, max_body_size).await {
+                Ok(request) => {
+                    debug!("Saleor webhook signature verified");
+                    inner.call(request).await
+                }
+                Err(e) => {
+                    error!("Saleor webhook signature not verified, {}", e);
+                    Ok(e.into_response())
+                }
             }
         })
-        .and_then(|(protected, signature)| {
-            let mut msg: Vec<u8> = Vec::new();
-            msg.extend_from_slice(format!("{}.", protected).as_bytes());
-            msg.extend_from_slice(&payload);
+    }
+}
+
+#[cfg(all(test, feature = "mock_saleor"))]
+mod tests {
+    use axum::http::HeaderValue;
 
-            crypto::verify(signature, &msg, &pubkey, Algorithm::RS256).ok()
+    use super::*;
+    use crate::{apl::memory_apl::MemoryApl, mock_saleor::MockSaleor, AuthData};
+
+    const PAYLOAD: &[u8] = br#"{"product":{"id":"UHJvZHVjdDox"}}"#;
+    const SALEOR_API_URL: &str = "https://api.example.com/graphql/";
+
+    /// APL with a single instance, registered with [`MockSaleor::jwks`]
+    async fn apl() -> MemoryApl {
+        let apl = MemoryApl::new();
+        apl.set(AuthData {
+            domain: None,
+            token: "token".to_owned(),
+            saleor_api_url: SALEOR_API_URL.to_owned(),
+            app_id: "app".to_owned(),
+            jwks: serde_json::to_string(&MockSaleor::jwks()).ok(),
         })
-    {
-        match is_verified {
-            true => {
-                debug!("Saleor webhook signature verified");
-                next.run(Request::from_parts(parts, payload.into())).await
-            }
-            false => {
-                error!("Saleor webhook signature not correct");
-                unauthorized
-            }
-        }
-    } else {
-        error!("Saleor webhook signature not verified, error parsing headers");
-        unauthorized
+        .await
+        .unwrap();
+        apl
+    }
+
+    fn headers(saleor_api_url: &str, signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SALEOR_API_URL_HEADER,
+            HeaderValue::from_str(saleor_api_url).unwrap(),
+        );
+        headers.insert(
+            SALEOR_SIGNATURE_HEADER,
+            HeaderValue::from_str(signature).unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn accepts_validly_signed_payload() {
+        let apl = apl().await;
+        verify_webhook_signature(
+            &apl,
+            &headers(SALEOR_API_URL, &MockSaleor::sign(PAYLOAD)),
+            PAYLOAD,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_payload() {
+        let apl = apl().await;
+        let result = verify_webhook_signature(
+            &apl,
+            &headers(SALEOR_API_URL, &MockSaleor::sign(PAYLOAD)),
+            br#"{"product":{"id":"UHJvZHVjdDoy"}}"#,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(WebhookVerificationError::InvalidSignature)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_unregistered_instance() {
+        let apl = apl().await;
+        let result = verify_webhook_signature(
+            &apl,
+            &headers(
+                "https://other.example.com/graphql/",
+                &MockSaleor::sign(PAYLOAD),
+            ),
+            PAYLOAD,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(WebhookVerificationError::NotRegistered(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_signature() {
+        let apl = apl().await;
+        let result =
+            verify_webhook_signature(&apl, &headers(SALEOR_API_URL, "not-a-jws"), PAYLOAD).await;
+        assert!(matches!(
+            result,
+            Err(WebhookVerificationError::MalformedJws(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_too_large() {
+        let apl = apl().await;
+        let request = Request::new(Body::from(vec![b'0'; 11]));
+        assert!(matches!(
+            verify_request(&apl, request, 10).await,
+            Err(WebhookVerificationError::BodyTooLarge(10))
+        ));
+    }
+
+    #[test]
+    fn unreachable_apl_gets_retried() {
+        let unavailable =
+            WebhookVerificationError::from(AplError::Connection("refused".to_owned()));
+        assert_eq!(
+            unavailable.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let unknown = WebhookVerificationError::from(AplError::NotFound(SALEOR_API_URL.to_owned()));
+        assert_eq!(unknown.into_response().status(), StatusCode::UNAUTHORIZED);
     }
 }
 
@@ -0,0 +1,373 @@
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{post, MethodRouter},
+};
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+use crate::{
+    apl::{AplError, APL},
+    config::Config,
+    headers::SALEOR_API_URL_HEADER,
+    webhooks::{
+        utils::{get_webhook_event_type, EitherWebhookType, GetWebhookTypeError},
+        AsyncWebhookEventType, SyncWebhookEventType, WebhookManifest, WebhookManifestBuilder,
+    },
+    AuthData,
+};
+
+/// Subscription used for handlers registered before any [`WebhookRouter::subscription`]
+pub const EMPTY_SUBSCRIPTION: &str = "subscription { event { __typename } }";
+
+#[derive(thiserror::Error, Debug)]
+pub enum WebhookError {
+    #[error("Missing or non-ascii header {0}")]
+    MissingHeader(&'static str),
+    #[error("Failed getting webhook event type, {0}")]
+    EventType(#[from] GetWebhookTypeError),
+    #[error("No handler registered for event {0}")]
+    UnhandledEvent(String),
+    #[error("Payload doesn't match event {event}, {source}")]
+    InvalidPayload {
+        event: String,
+        source: serde_json::Error,
+    },
+    #[error("Saleor instance isn't registered, {0}")]
+    NotRegistered(AplError),
+    #[error("APL is unreachable, {0}")]
+    AplUnavailable(AplError),
+}
+
+impl From<AplError> for WebhookError {
+    fn from(e: AplError) -> Self {
+        match e {
+            AplError::Connection(_) => Self::AplUnavailable(e),
+            _ => Self::NotRegistered(e),
+        }
+    }
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::NotRegistered(_) => StatusCode::UNAUTHORIZED,
+            // saleor retries deliveries that fail with 5xx
+            Self::AplUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        (status, self.to_string()).into_response()
+    }
+}
+
+/// Deserialized webhook, passed to handlers registered in [`WebhookRouter`]
+#[derive(Debug, Clone)]
+pub struct Webhook<T> {
+    pub event: EitherWebhookType,
+    /// Auth data of the saleor instance that sent the webhook
+    pub auth_data: AuthData,
+    pub payload: T,
+}
+
+/// Events sharing one subscription query, ends up as one webhook in the app manifest
+#[derive(Debug, Clone, Default)]
+struct Subscription {
+    query: Option<String>,
+    async_events: Vec<AsyncWebhookEventType>,
+    sync_events: Vec<SyncWebhookEventType>,
+}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<Response, WebhookError>> + Send>>;
+type BoxedHandler<S> =
+    Arc<dyn Fn(S, EitherWebhookType, AuthData, Bytes) -> HandlerFuture + Send + Sync>;
+
+/**
+ Dispatches webhooks on `saleor-event` to handlers registered per event type, deserializing
+ the payload into the handlers payload type (usually a cynic subscription fragment).
+ Webhooks with unknown events or payloads that don't match return 400, ones from saleor
+ instances missing in the APL return 401, and 503 when the APL can't be reached so saleor retries.
+
+ Handlers belong to the last [`WebhookRouter::subscription`] set before them, and
+ [`WebhookRouter::webhook_manifests`] turns each subscription and its events into a webhook
+ for the app manifest, so the manifest only ever lists events that have a handler.
+ Every event can only have one handler, registering a second one panics.
+
+ ```ignore
+ let webhooks = WebhookRouter::new(saleor_app.apl.clone())
+     .subscription(PRODUCTS_QUERY)
+     .on_async(AsyncWebhookEventType::ProductUpdated, product_updated)
+     .subscription(TRANSACTIONS_QUERY)
+     .on_sync(SyncWebhookEventType::TransactionInitializeSession, transaction_init);
+
+ let manifest = AppManifestBuilder::new(&config, cargo_info!())
+     .add_webhooks(webhooks.webhook_manifests(&config))
+     .build()?;
+ Router::new().route("/api/webhooks", webhooks.into_method_router())
+
+ async fn product_updated(state: AppState, webhook: Webhook<ProductUpdated>) -> StatusCode { .. }
+ ```
+*/
+pub struct WebhookRouter<S> {
+    apl: Arc<dyn APL>,
+    handlers: HashMap<String, BoxedHandler<S>>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl<S> std::fmt::Debug for WebhookRouter<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookRouter")
+            .field("apl", &self.apl)
+            .field("subscriptions", &self.subscriptions)
+            .finish()
+    }
+}
+
+impl<S> WebhookRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new(apl: Arc<dyn APL>) -> Self {
+        Self {
+            apl,
+            handlers: HashMap::new(),
+            subscriptions: vec![Subscription::default()],
+        }
+    }
+
+    /**
+     Subscription query the payloads of handlers registered after this get built from. Can be
+     a string constant, or the query built by cynics `OperationBuilder::subscription()`.
+    */
+    pub fn subscription(mut self, query: &str) -> Self {
+        self.subscriptions.push(Subscription {
+            query: Some(query.to_owned()),
+            ..Default::default()
+        });
+        self
+    }
+
+    fn current_subscription(&mut self) -> &mut Subscription {
+        self.subscriptions
+            .last_mut()
+            .expect("router always has a subscription")
+    }
+
+    pub fn on_async<T, H, Fut>(mut self, event: AsyncWebhookEventType, handler: H) -> Self
+    where
+        T: DeserializeOwned + Send + 'static,
+        H: Fn(S, Webhook<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: IntoResponse,
+    {
+        self.add_handler(event.as_ref(), handler);
+        self.current_subscription().async_events.push(event);
+        self
+    }
+
+    pub fn on_sync<T, H, Fut>(mut self, event: SyncWebhookEventType, handler: H) -> Self
+    where
+        T: DeserializeOwned + Send + 'static,
+        H: Fn(S, Webhook<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: IntoResponse,
+    {
+        self.add_handler(event.as_ref(), handler);
+        self.current_subscription().sync_events.push(event);
+        self
+    }
+
+    /// Async events that have a handler registered
+    pub fn async_events(&self) -> Vec<AsyncWebhookEventType> {
+        self.subscriptions
+            .iter()
+            .flat_map(|s| s.async_events.clone())
+            .collect()
+    }
+
+    /// Sync events that have a handler registered
+    pub fn sync_events(&self) -> Vec<SyncWebhookEventType> {
+        self.subscriptions
+            .iter()
+            .flat_map(|s| s.sync_events.clone())
+            .collect()
+    }
+
+    /**
+     One webhook per subscription that has handlers, with the events of those handlers and the
+     default target url from [`WebhookManifestBuilder::new`].
+    */
+    pub fn webhook_manifests(&self, config: &Config) -> Vec<WebhookManifest> {
+        self.subscriptions
+            .iter()
+            .filter(|s| !s.async_events.is_empty() || !s.sync_events.is_empty())
+            .enumerate()
+            .map(|(i, s)| {
+                let mut webhook = WebhookManifestBuilder::new(config)
+                    .set_query(s.query.as_deref().unwrap_or(EMPTY_SUBSCRIPTION));
+                if i > 0 {
+                    webhook = webhook.set_name(&format!("webhook {}", i + 1));
+                }
+                if !s.async_events.is_empty() {
+                    webhook = webhook.add_async_events(s.async_events.clone());
+                }
+                if !s.sync_events.is_empty() {
+                    webhook = webhook.add_sync_events(s.sync_events.clone());
+                }
+                webhook.build()
+            })
+            .collect()
+    }
+
+    fn add_handler<T, H, Fut>(&mut self, event: &str, handler: H)
+    where
+        T: DeserializeOwned + Send + 'static,
+        H: Fn(S, Webhook<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future + Send + 'static,
+        Fut::Output: IntoResponse,
+    {
+        let event_name = event.to_owned();
+        let handler = Arc::new(handler);
+        let boxed: BoxedHandler<S> = Arc::new(move |state, event, auth_data, body| {
+            let handler = handler.clone();
+            let event_name = event_name.clone();
+            Box::pin(async move {
+                let payload: T = serde_json::from_slice(&body).map_err(|source| {
+                    WebhookError::InvalidPayload {
+                        event: event_name,
+                        source,
+                    }
+                })?;
+                let webhook = Webhook {
+                    event,
+                    auth_data,
+                    payload,
+                };
+                Ok(handler(state, webhook).await.into_response())
+            })
+        });
+        if self.handlers.insert(event.to_owned(), boxed).is_some() {
+            panic!("WebhookRouter already has a handler for {}", event);
+        }
+    }
+
+    /// Finds the handler for the webhook in `headers` and runs it with the deserialized `body`
+    pub async fn dispatch(
+        &self,
+        state: S,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> Result<Response, WebhookError> {
+        let event = get_webhook_event_type(headers)?;
+        let event_name = match &event {
+            EitherWebhookType::Sync(s) => s.as_ref(),
+            EitherWebhookType::Async(a) => a.as_ref(),
+        };
+        debug!("dispatching webhook {}", event_name);
+        let handler = self
+            .handlers
+            .get(event_name)
+            .ok_or_else(|| WebhookError::UnhandledEvent(event_name.to_owned()))?
+            .clone();
+
+        let saleor_api_url = headers
+            .get(SALEOR_API_URL_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .ok_or(WebhookError::MissingHeader(SALEOR_API_URL_HEADER))?;
+        let auth_data = self.apl.get(saleor_api_url).await?;
+
+        handler(state, event, auth_data, body).await
+    }
+
+    /// Turns the router into a `POST` route, to be mounted on the webhook target url
+    pub fn into_method_router(self) -> MethodRouter<S> {
+        let router = Arc::new(self);
+        post(
+            move |State(state): State<S>, headers: HeaderMap, body: Bytes| {
+                let router = router.clone();
+                async move { router.dispatch(state, &headers, body).await }
+            },
+        )
+    }
+}
+
+#[cfg(all(test, feature = "memory_apl"))]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::{apl::memory_apl::MemoryApl, headers::SALEOR_EVENT_HEADER};
+
+    async fn ok(_state: (), _webhook: Webhook<serde_json::Value>) -> StatusCode {
+        StatusCode::OK
+    }
+
+    async fn accepted(_state: (), _webhook: Webhook<serde_json::Value>) -> StatusCode {
+        StatusCode::ACCEPTED
+    }
+
+    #[tokio::test]
+    async fn dispatches_on_event() {
+        let apl = MemoryApl::new();
+        apl.set(AuthData {
+            domain: None,
+            token: "token".to_owned(),
+            saleor_api_url: "https://api.example.com/graphql/".to_owned(),
+            app_id: "app".to_owned(),
+            jwks: None,
+        })
+        .await
+        .unwrap();
+        let router = WebhookRouter::new(Arc::new(apl))
+            .on_async(AsyncWebhookEventType::ProductUpdated, ok)
+            .on_async(AsyncWebhookEventType::ProductCreated, accepted);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SALEOR_API_URL_HEADER,
+            HeaderValue::from_static("https://api.example.com/graphql/"),
+        );
+        headers.insert(
+            SALEOR_EVENT_HEADER,
+            HeaderValue::from_static("product_created"),
+        );
+        let res = router
+            .dispatch((), &headers, Bytes::from_static(b"{}"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+
+        headers.insert(
+            SALEOR_EVENT_HEADER,
+            HeaderValue::from_static("order_created"),
+        );
+        let err = router
+            .dispatch((), &headers, Bytes::from_static(b"{}"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, WebhookError::UnhandledEvent(_)));
+    }
+
+    #[test]
+    fn unreachable_apl_is_retried() {
+        let unavailable = WebhookError::from(AplError::Connection("refused".to_owned()));
+        assert_eq!(
+            unavailable.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let unknown = WebhookError::from(AplError::NotFound("https://api.example.com".to_owned()));
+        assert_eq!(unknown.into_response().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    #[should_panic(expected = "already has a handler for product_updated")]
+    fn duplicate_events_panic() {
+        let _ = WebhookRouter::new(Arc::new(MemoryApl::new()))
+            .on_async(AsyncWebhookEventType::ProductUpdated, ok)
+            .subscription("subscription { event { __typename } }")
+            .on_async(AsyncWebhookEventType::ProductUpdated, accepted);
+    }
+}
@@ -0,0 +1,607 @@
+pub mod query;
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, Mutex, RwLock},
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{crypto, jwk::JwkSet, Algorithm, EncodingKey, Header};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use query::{parse_root_fields, schema_type_fields, OperationType, RootField};
+
+use crate::{
+    apl::{AplError, APL},
+    headers::{
+        SALEOR_API_URL_HEADER, SALEOR_DOMAIN_HEADER, SALEOR_EVENT_HEADER, SALEOR_SCHEMA_VERSION,
+        SALEOR_SIGNATURE_HEADER,
+    },
+    AuthData,
+};
+
+/// Id `app { id }` resolves to
+pub const MOCK_APP_ID: &str = "QXBwOjE=";
+/// Token the mock accepts, requests with any other token get a 401
+pub const MOCK_APP_TOKEN: &str = "mock-saleor-app-token";
+/// Kid of the only key in the mocks JWKS
+pub const MOCK_JWK_KID: &str = "mock-saleor";
+pub const MOCK_SCHEMA_VERSION: &str = "3.20";
+/// Saleor refuses to return more nodes than this per page
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Test only key, never use it for anything else
+const SIGNING_KEY: &str = include_str!("mock_key.pem");
+const SIGNING_KEY_N: &str = "nc1Bd4u2KcYfnwYQNx_IN8_tRQc4kNKz1ljgQYPR6FAXwp9K_yiflbiqcCp5LH8e0QCD9YEUEzvTIR5RjKZR0E-V_g0GD4g79Zp5thfzTBgmi2y_rD6umNxeek7cC_7VZ_qBb5FxyZmjQjvOrU-rw-VpM7VqSVbTntxz6ZEIQW0T4Sly9tgn2kMqCd2kxgMDO61U77zTYLtzSsGOWSU4HXkZSNsz2Lvuivyu9coh1jzO6m9wBB_dmUJqe046kXjl3UT2jPy6D2MGqIe2xAZdF3EhsCwc2sJ_5cjP2t3Z41Cv3YM3vZE1r0CDjYgAjCY7H6F_8eKf66ORC8X6K3nBow";
+const SCHEMA: &str = include_str!("../../schema.graphql");
+
+static QUERY_FIELDS: LazyLock<Vec<String>> = LazyLock::new(|| schema_type_fields(SCHEMA, "Query"));
+static MUTATION_FIELDS: LazyLock<Vec<String>> =
+    LazyLock::new(|| schema_type_fields(SCHEMA, "Mutation"));
+
+/// Resolves a root field from its arguments, errors end up in the responses `errors`
+pub type Resolver = Arc<dyn Fn(&Map<String, Value>) -> Result<Value, String> + Send + Sync>;
+
+/// A GraphQL request the mock received
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub operation: OperationType,
+    pub fields: Vec<RootField>,
+    pub variables: Map<String, Value>,
+    /// From either `authorization-bearer` or `Authorization: Bearer`
+    pub token: Option<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    resolvers: RwLock<HashMap<(OperationType, String), Resolver>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+/**
+ Local stand-in for a Saleor instance, for testing apps end to end. Serves the GraphQL api at
+ [`MockSaleor::api_url`] with responses from fixtures, the JWKS at `/.well-known/jwks.json` and
+ `schema.graphql` at `/schema.graphql`, and can send webhooks signed with its JWKS key.
+
+ Only the root fields of operations are looked at, fixtures have to contain every field the app
+ selects. Fields that aren't in the schema or have no fixture come back as errors.
+
+ ```ignore
+ let saleor = MockSaleor::start().await?;
+ saleor.set_connection("products", vec![json!({ "id": "UHJvZHVjdDox", "slug": "shoe" })]);
+ saleor.register(apl.as_ref(), "http://localhost:3000").await?;
+ saleor
+     .send_webhook("http://localhost:3000/api/webhooks", AsyncWebhookEventType::ProductCreated, &payload)
+     .await?;
+ ```
+
+ The server stops when this gets dropped.
+*/
+pub struct MockSaleor {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    server: JoinHandle<()>,
+}
+
+impl std::fmt::Debug for MockSaleor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockSaleor")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+impl Drop for MockSaleor {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl MockSaleor {
+    /// Starts the server on a random local port, with `app { id }` already resolving
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(MockState::default());
+
+        let app = Router::new()
+            .route("/graphql/", get(schema).post(graphql))
+            .route("/graphql", get(schema).post(graphql))
+            .route("/schema.graphql", get(schema))
+            .route(
+                "/.well-known/jwks.json",
+                get(|| async { Json(Self::jwks()) }),
+            )
+            .with_state(state.clone());
+        let server = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                #[cfg(feature = "tracing")]
+                tracing::error!("mock saleor stopped, {e}");
+                #[cfg(not(feature = "tracing"))]
+                let _ = e;
+            }
+        });
+
+        let saleor = Self {
+            addr,
+            state,
+            server,
+        };
+        saleor.set_query("app", json!({ "id": MOCK_APP_ID }));
+        Ok(saleor)
+    }
+
+    pub fn api_url(&self) -> String {
+        format!("http://{}/graphql/", self.addr)
+    }
+
+    pub fn domain(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// Auth data an app registered with this instance would store
+    pub fn auth_data(&self, app_api_base_url: &str) -> AuthData {
+        AuthData {
+            domain: Some(app_api_base_url.to_owned()),
+            token: MOCK_APP_TOKEN.to_owned(),
+            saleor_api_url: self.api_url(),
+            app_id: MOCK_APP_ID.to_owned(),
+            jwks: serde_json::to_string(&Self::jwks()).ok(),
+        }
+    }
+
+    /// Stores [`Self::auth_data`] into the apps APL, as if the app got installed
+    pub async fn register(
+        &self,
+        apl: &dyn APL,
+        app_api_base_url: &str,
+    ) -> Result<AuthData, AplError> {
+        let auth_data = self.auth_data(app_api_base_url);
+        apl.set(auth_data.clone()).await?;
+        Ok(auth_data)
+    }
+
+    /**
+     Resolves query field `field` with `resolver`, replacing what it resolved to before.
+
+     # Panics
+     If `field` isn't a field of `Query` in `schema.graphql`.
+    */
+    pub fn on_query<F>(&self, field: &str, resolver: F)
+    where
+        F: Fn(&Map<String, Value>) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.set_resolver(OperationType::Query, field, Arc::new(resolver));
+    }
+
+    /**
+     Resolves mutation `field` with `resolver`, see [`Self::on_query`].
+
+     # Panics
+     If `field` isn't a field of `Mutation` in `schema.graphql`.
+    */
+    pub fn on_mutation<F>(&self, field: &str, resolver: F)
+    where
+        F: Fn(&Map<String, Value>) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.set_resolver(OperationType::Mutation, field, Arc::new(resolver));
+    }
+
+    /// Query field `field` resolves to `value`, whatever the arguments
+    pub fn set_query(&self, field: &str, value: Value) {
+        self.on_query(field, move |_| Ok(value.clone()));
+    }
+
+    /**
+     Query field `field` resolves to a connection over `nodes`, paginated with `first` and
+     `after` like Saleor does. Cursors are opaque to apps, here they're the node indexes.
+    */
+    pub fn set_connection(&self, field: &str, nodes: Vec<Value>) {
+        self.on_query(field, move |args| connection(&nodes, args));
+    }
+
+    /// Every request received so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state
+            .requests
+            .lock()
+            .map(|r| r.clone())
+            .unwrap_or_default()
+    }
+
+    /// How many times root field `field` got requested
+    pub fn request_count(&self, field: &str) -> usize {
+        self.requests()
+            .iter()
+            .flat_map(|r| &r.fields)
+            .filter(|f| f.name == field)
+            .count()
+    }
+
+    /// The JWKS Saleor would serve, containing the key webhooks get signed with
+    pub fn jwks() -> JwkSet {
+        serde_json::from_value(json!({
+            "keys": [{
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": MOCK_JWK_KID,
+                "n": SIGNING_KEY_N,
+                "e": "AQAB",
+            }]
+        }))
+        .expect("mock JWKS is valid")
+    }
+
+    /// Detached JWS over `payload`, the way Saleor fills `saleor-signature`
+    pub fn sign(payload: &[u8]) -> String {
+        let protected = URL_SAFE_NO_PAD.encode(
+            json!({ "alg": "RS256", "kid": MOCK_JWK_KID, "b64": false, "crit": ["b64"] })
+                .to_string(),
+        );
+        let mut msg = Vec::with_capacity(protected.len() + 1 + payload.len());
+        msg.extend_from_slice(protected.as_bytes());
+        msg.push(b'.');
+        msg.extend_from_slice(payload);
+
+        let key = EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).expect("mock key is valid");
+        let signature =
+            crypto::sign(&msg, &key, Algorithm::RS256).expect("RS256 signing doesn't fail");
+        format!("{protected}..{signature}")
+    }
+
+    /// JWT with `claims`, signed like the tokens Saleor gives the dashboard, eg. for
+    /// `saleor-authorization-bearer`
+    pub fn sign_token(claims: &Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(MOCK_JWK_KID.to_owned());
+        let key = EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).expect("mock key is valid");
+        jsonwebtoken::encode(&header, claims, &key).expect("RS256 signing doesn't fail")
+    }
+
+    /// Headers Saleor sends with a webhook of `event` carrying `payload`
+    pub fn webhook_headers(&self, event: impl AsRef<str>, payload: &[u8]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let mut insert = |name: &'static str, value: String| {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        };
+        insert(SALEOR_EVENT_HEADER, event.as_ref().to_owned());
+        insert(SALEOR_DOMAIN_HEADER, self.domain());
+        insert(SALEOR_API_URL_HEADER, self.api_url());
+        insert(SALEOR_SIGNATURE_HEADER, Self::sign(payload));
+        insert(SALEOR_SCHEMA_VERSION, MOCK_SCHEMA_VERSION.to_owned());
+        insert("content-type", "application/json".to_owned());
+        headers
+    }
+
+    /// Posts a signed webhook of `event` to the app at `url`
+    pub async fn send_webhook(
+        &self,
+        url: &str,
+        event: impl AsRef<str>,
+        payload: &Value,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let payload = payload.to_string();
+        reqwest::Client::new()
+            .post(url)
+            .headers(self.webhook_headers(event, payload.as_bytes()))
+            .body(payload)
+            .send()
+            .await
+    }
+
+    fn set_resolver(&self, operation: OperationType, field: &str, resolver: Resolver) {
+        let known = match operation {
+            OperationType::Query => &QUERY_FIELDS,
+            OperationType::Mutation => &MUTATION_FIELDS,
+        };
+        assert!(
+            known.iter().any(|f| f == field),
+            "{field} isn't a {operation:?} field in schema.graphql"
+        );
+        if let Ok(mut resolvers) = self.state.resolvers.write() {
+            resolvers.insert((operation, field.to_owned()), resolver);
+        }
+    }
+}
+
+fn connection(nodes: &[Value], args: &Map<String, Value>) -> Result<Value, String> {
+    if args.get("last").is_some_and(|l| !l.is_null())
+        || args.get("before").is_some_and(|b| !b.is_null())
+    {
+        return Err("the mock only paginates with first and after".to_owned());
+    }
+    let first = match args.get("first").and_then(Value::as_u64) {
+        Some(first) if first as usize > MAX_PAGE_SIZE => {
+            return Err(format!(
+                "Requesting {first} records exceeds the `first` limit of {MAX_PAGE_SIZE} records."
+            ))
+        }
+        Some(first) => first as usize,
+        None => MAX_PAGE_SIZE,
+    };
+    let start = match args.get("after").and_then(Value::as_str) {
+        Some(after) => {
+            after
+                .parse::<usize>()
+                .map_err(|_| format!("invalid cursor {after}"))?
+                + 1
+        }
+        None => 0,
+    };
+    let end = nodes.len().min(start.saturating_add(first));
+    let edges: Vec<Value> = (start..end)
+        .map(|i| json!({ "cursor": i.to_string(), "node": nodes[i] }))
+        .collect();
+
+    Ok(json!({
+        "edges": edges,
+        "pageInfo": {
+            "hasNextPage": end < nodes.len(),
+            "hasPreviousPage": start > 0,
+            "startCursor": (start < end).then(|| start.to_string()),
+            "endCursor": (start < end).then(|| (end - 1).to_string()),
+        },
+        "totalCount": nodes.len(),
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlRequest {
+    query: String,
+    #[serde(default)]
+    variables: Option<Map<String, Value>>,
+    #[serde(default)]
+    operation_name: Option<String>,
+}
+
+fn graphql_error(message: impl Into<String>) -> Value {
+    json!({ "message": message.into() })
+}
+
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("authorization-bearer")
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| {
+            headers
+                .get("authorization")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.split_once(' '))
+                .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+                .map(|(_, token)| token.to_owned())
+        })
+}
+
+async fn schema() -> &'static str {
+    SCHEMA
+}
+
+async fn graphql(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Json(request): Json<GraphqlRequest>,
+) -> Response {
+    let token = request_token(&headers);
+    if token.as_deref().is_some_and(|t| t != MOCK_APP_TOKEN) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "errors": [graphql_error("Invalid token")] })),
+        )
+            .into_response();
+    }
+
+    let variables = request.variables.unwrap_or_default();
+    let (operation, fields) = match parse_root_fields(
+        &request.query,
+        request.operation_name.as_deref(),
+        &variables,
+    ) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "errors": [graphql_error(e)] })),
+            )
+                .into_response()
+        }
+    };
+    if let Ok(mut requests) = state.requests.lock() {
+        requests.push(RecordedRequest {
+            operation,
+            fields: fields.clone(),
+            variables,
+            token: token.clone(),
+        });
+    }
+
+    let known = match operation {
+        OperationType::Query => &QUERY_FIELDS,
+        OperationType::Mutation => &MUTATION_FIELDS,
+    };
+    let unknown: Vec<Value> = fields
+        .iter()
+        .filter(|f| f.name != "__typename" && !known.contains(&f.name))
+        .map(|f| {
+            graphql_error(format!(
+                "Cannot query field \"{}\" on type \"{:?}\".",
+                f.name, operation
+            ))
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "errors": unknown }))).into_response();
+    }
+
+    let mut data = Map::new();
+    let mut errors = vec![];
+    for field in fields {
+        let resolved = if field.name == "__typename" {
+            Ok(json!(format!("{operation:?}")))
+        } else if field.name == "app" && token.is_none() {
+            // saleor only tells apps about themselves
+            Ok(Value::Null)
+        } else {
+            let resolver = state
+                .resolvers
+                .read()
+                .ok()
+                .and_then(|r| r.get(&(operation, field.name.clone())).cloned());
+            match resolver {
+                Some(resolver) => resolver(&field.args),
+                None => Err(format!("mock saleor has no fixture for {}", field.name)),
+            }
+        };
+        match resolved {
+            Ok(value) => {
+                data.insert(field.key, value);
+            }
+            Err(e) => {
+                errors.push(json!({ "message": e, "path": [field.key.clone()] }));
+                data.insert(field.key, Value::Null);
+            }
+        }
+    }
+
+    let mut body = json!({ "data": data });
+    if !errors.is_empty() {
+        body["errors"] = Value::Array(errors);
+    }
+    Json(body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        middleware::{register::fetch_app_id, verify_webhook_signature::verify_webhook_signature},
+        webhooks::AsyncWebhookEventType,
+    };
+
+    async fn query(saleor: &MockSaleor, query: &str, variables: Value) -> Value {
+        reqwest::Client::new()
+            .post(saleor.api_url())
+            .header("authorization-bearer", MOCK_APP_TOKEN)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn paginates_connections() {
+        let saleor = MockSaleor::start().await.unwrap();
+        saleor.set_connection(
+            "products",
+            (0..5).map(|i| json!({ "id": i.to_string() })).collect(),
+        );
+        let products = "query Products($after: String) { products(first: 2, after: $after) { edges { node { id } } pageInfo { hasNextPage endCursor } } }";
+
+        let mut ids = vec![];
+        let mut after = Value::Null;
+        loop {
+            let res = query(&saleor, products, json!({ "after": after })).await;
+            let page = &res["data"]["products"];
+            ids.extend(
+                page["edges"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|e| e["node"]["id"].as_str().unwrap().to_owned()),
+            );
+            if !page["pageInfo"]["hasNextPage"].as_bool().unwrap() {
+                break;
+            }
+            after = page["pageInfo"]["endCursor"].clone();
+        }
+        assert_eq!(ids, ["0", "1", "2", "3", "4"]);
+        assert_eq!(saleor.request_count("products"), 3);
+
+        let res = query(
+            &saleor,
+            "{ products(first: 101) { totalCount } }",
+            json!({}),
+        )
+        .await;
+        assert!(res["data"]["products"].is_null());
+        assert!(res["errors"][0]["message"].is_string());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_fields_and_tokens() {
+        let saleor = MockSaleor::start().await.unwrap();
+        let res = query(&saleor, "{ notAField { id } }", json!({})).await;
+        assert!(res["data"].is_null());
+        let res = query(&saleor, "{ channels { slug } }", json!({})).await;
+        assert!(res["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("no fixture"));
+
+        assert_eq!(
+            fetch_app_id(&saleor.api_url(), MOCK_APP_TOKEN)
+                .await
+                .unwrap(),
+            MOCK_APP_ID
+        );
+        assert!(fetch_app_id(&saleor.api_url(), "wrong").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn resolves_mutations() {
+        let saleor = MockSaleor::start().await.unwrap();
+        saleor.on_mutation("productVariantChannelListingUpdate", |args| {
+            Ok(json!({ "variant": { "id": args["id"] }, "errors": [] }))
+        });
+        let res = query(
+            &saleor,
+            "mutation Update($id: ID!) { productVariantChannelListingUpdate(id: $id, input: []) { variant { id } errors { field } } }",
+            json!({ "id": "UHJvZHVjdFZhcmlhbnQ6MQ==" }),
+        )
+        .await;
+        assert_eq!(
+            res["data"]["productVariantChannelListingUpdate"]["variant"]["id"],
+            "UHJvZHVjdFZhcmlhbnQ6MQ=="
+        );
+        assert_eq!(saleor.requests()[0].operation, OperationType::Mutation);
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_signed() {
+        let saleor = MockSaleor::start().await.unwrap();
+        let apl = crate::apl::memory_apl::MemoryApl::new();
+        saleor
+            .register(&apl, "http://localhost:3000")
+            .await
+            .unwrap();
+
+        let payload = json!({ "product": { "id": "UHJvZHVjdDox" } }).to_string();
+        let headers =
+            saleor.webhook_headers(AsyncWebhookEventType::ProductUpdated, payload.as_bytes());
+        verify_webhook_signature(&apl, &headers, payload.as_bytes())
+            .await
+            .unwrap();
+        assert!(
+            verify_webhook_signature(&apl, &headers, b"{\"tampered\": true}")
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use serde_json::{Map, Number, Value};
+
+/// Root type an operation selects from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperationType {
+    Query,
+    Mutation,
+}
+
+/// Field selected on the root type, with its arguments resolved against the variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootField {
+    /// Alias if there is one, the key the result goes under
+    pub key: String,
+    pub name: String,
+    pub args: Map<String, Value>,
+}
+
+/**
+ Parses just enough of a GraphQL document to know which root fields get selected and with what
+ arguments. Nested selections are skipped, fixtures decide what the fields resolve to.
+ Fragment definitions are ignored, if there are multiple operations `operation_name` picks one.
+*/
+pub fn parse_root_fields(
+    document: &str,
+    operation_name: Option<&str>,
+    variables: &Map<String, Value>,
+) -> Result<(OperationType, Vec<RootField>), String> {
+    let mut parser = Parser {
+        chars: document.chars().collect(),
+        pos: 0,
+        variables,
+    };
+    let mut first = None;
+    loop {
+        parser.skip_ignored();
+        if parser.eof() {
+            break;
+        }
+        let (op, name) = if parser.peek() == Some('{') {
+            (OperationType::Query, None)
+        } else {
+            match parser.name()?.as_str() {
+                "fragment" => {
+                    parser.skip_until('{')?;
+                    parser.skip_balanced('{', '}')?;
+                    continue;
+                }
+                "query" => (OperationType::Query, parser.optional_name()),
+                "mutation" => (OperationType::Mutation, parser.optional_name()),
+                other => return Err(format!("unsupported operation type {other}")),
+            }
+        };
+        parser.skip_until('{')?;
+        let fields = parser.selection_set()?;
+        match operation_name {
+            Some(wanted) if name.as_deref() == Some(wanted) => return Ok((op, fields)),
+            Some(_) => (),
+            None if first.is_none() => first = Some((op, fields)),
+            None => return Err("operationName is needed for multiple operations".to_owned()),
+        }
+    }
+    first.ok_or_else(|| match operation_name {
+        Some(wanted) => format!("unknown operation named {wanted}"),
+        None => "document doesn't contain an operation".to_owned(),
+    })
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    variables: &'a Map<String, Value>,
+}
+
+impl Parser<'_> {
+    fn eof(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Whitespace, commas and comments are insignificant in GraphQL
+    fn skip_ignored(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                c if c.is_whitespace() || c == ',' || c == '\u{feff}' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_ignored();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            other => Err(format!(
+                "expected {expected:?} at {}, found {other:?}",
+                self.pos
+            )),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        self.skip_ignored();
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(format!("expected a name at {start}"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn optional_name(&mut self) -> Option<String> {
+        self.skip_ignored();
+        self.peek()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            .then(|| self.name().ok())
+            .flatten()
+    }
+
+    fn skip_until(&mut self, until: char) -> Result<(), String> {
+        while let Some(c) = self.peek() {
+            match c {
+                c if c == until => return Ok(()),
+                '"' => {
+                    self.string()?;
+                }
+                '(' => self.skip_balanced('(', ')')?,
+                _ => self.pos += 1,
+            }
+        }
+        Err(format!("expected {until:?} before the end of the document"))
+    }
+
+    /// Skips from the opening char to after its closing one, ignoring ones inside strings
+    fn skip_balanced(&mut self, open: char, close: char) -> Result<(), String> {
+        self.expect(open)?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek() {
+                None => return Err(format!("unclosed {open:?}")),
+                Some('"') => {
+                    self.string()?;
+                }
+                Some(c) => {
+                    if c == open {
+                        depth += 1;
+                    } else if c == close {
+                        depth -= 1;
+                    }
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<RootField>, String> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        loop {
+            self.skip_ignored();
+            match self.peek() {
+                None => return Err("unclosed selection set".to_owned()),
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(fields);
+                }
+                Some('.') => return Err("fragments on root types aren't supported".to_owned()),
+                _ => (),
+            }
+            let key = self.name()?;
+            let mut name = key.clone();
+            self.skip_ignored();
+            if self.peek() == Some(':') {
+                self.pos += 1;
+                name = self.name()?;
+            }
+            self.skip_ignored();
+            let args = if self.peek() == Some('(') {
+                self.arguments()?
+            } else {
+                Map::new()
+            };
+            self.skip_ignored();
+            while self.peek() == Some('@') {
+                self.pos += 1;
+                self.name()?;
+                self.skip_ignored();
+                if self.peek() == Some('(') {
+                    self.skip_balanced('(', ')')?;
+                }
+                self.skip_ignored();
+            }
+            if self.peek() == Some('{') {
+                self.skip_balanced('{', '}')?;
+            }
+            fields.push(RootField { key, name, args });
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Map<String, Value>, String> {
+        self.expect('(')?;
+        let mut args = Map::new();
+        loop {
+            self.skip_ignored();
+            if self.peek() == Some(')') {
+                self.pos += 1;
+                return Ok(args);
+            }
+            let name = self.name()?;
+            self.expect(':')?;
+            let value = self.value()?;
+            args.insert(name, value);
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_ignored();
+        match self.peek() {
+            Some('$') => {
+                self.pos += 1;
+                let name = self.name()?;
+                Ok(self.variables.get(&name).cloned().unwrap_or(Value::Null))
+            }
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut list = vec![];
+                loop {
+                    self.skip_ignored();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        return Ok(Value::Array(list));
+                    }
+                    list.push(self.value()?);
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut object = Map::new();
+                loop {
+                    self.skip_ignored();
+                    if self.peek() == Some('}') {
+                        self.pos += 1;
+                        return Ok(Value::Object(object));
+                    }
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    object.insert(name, self.value()?);
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+                {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number
+                    .parse::<i64>()
+                    .map(Number::from)
+                    .ok()
+                    .or_else(|| number.parse::<f64>().ok().and_then(Number::from_f64))
+                    .map(Value::Number)
+                    .ok_or_else(|| format!("invalid number {number}"))
+            }
+            _ => Ok(match self.name()?.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "null" => Value::Null,
+                // enum values
+                other => Value::String(other.to_owned()),
+            }),
+        }
+    }
+
+    /// Block strings aren't supported, nothing sends them as arguments
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let start = self.pos;
+        let mut escaped = false;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '"' if !escaped => {
+                    let raw: String = self.chars[start - 1..self.pos].iter().collect();
+                    return serde_json::from_str(&raw).map_err(|e| e.to_string());
+                }
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        Err("unclosed string".to_owned())
+    }
+}
+
+/// Names of the fields of `type_name` in a GraphQL SDL schema
+pub fn schema_type_fields(schema: &str, type_name: &str) -> Vec<String> {
+    let header = format!("type {type_name} {{");
+    let Some(start) = schema.lines().position(|l| l.trim_end() == header) else {
+        return vec![];
+    };
+    let mut fields = vec![];
+    let mut in_description = false;
+    let mut depth = 0;
+    for line in schema.lines().skip(start + 1) {
+        let trimmed = line.trim();
+        if trimmed.starts_with("\"\"\"") {
+            // single line descriptions open and close on the same line
+            if !(trimmed.len() > 3 && trimmed.ends_with("\"\"\"")) {
+                in_description = !in_description;
+            }
+            continue;
+        }
+        if in_description {
+            continue;
+        }
+        if depth == 0 {
+            if trimmed == "}" {
+                break;
+            }
+            let name: String = trimmed
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            if !name.is_empty() {
+                fields.push(name);
+            }
+        }
+        depth += trimmed.matches('(').count() as i32 - trimmed.matches(')').count() as i32;
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_root_fields() {
+        let variables = json!({ "channel": "default", "after": null })
+            .as_object()
+            .cloned()
+            .unwrap();
+        let document = r#"
+            # comment with a { brace
+            query Products($channel: String, $after: String) {
+              items: products(first: 50, channel: $channel, after: $after, where: { name: { eq: "a } b" } }) {
+                edges { node { id name } }
+              }
+              shop @include(if: true) { name }
+            }
+            fragment Unused on Product { id }
+        "#;
+        let (op, fields) = parse_root_fields(document, None, &variables).unwrap();
+        assert_eq!(op, OperationType::Query);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].key, "items");
+        assert_eq!(fields[0].name, "products");
+        assert_eq!(
+            Value::Object(fields[0].args.clone()),
+            json!({
+                "first": 50,
+                "channel": "default",
+                "after": null,
+                "where": { "name": { "eq": "a } b" } }
+            })
+        );
+        assert_eq!(fields[1].key, "shop");
+    }
+
+    #[test]
+    fn picks_operation() {
+        let document = "query A { shop { name } } mutation B { productUpdate(id: \"1\") { errors { field } } }";
+        let (op, fields) = parse_root_fields(document, Some("B"), &Map::new()).unwrap();
+        assert_eq!(op, OperationType::Mutation);
+        assert_eq!(fields[0].name, "productUpdate");
+        assert!(parse_root_fields(document, None, &Map::new()).is_err());
+        assert!(parse_root_fields("{ app { id } }", None, &Map::new()).is_ok());
+        assert!(parse_root_fields("query { app { id }", None, &Map::new()).is_err());
+    }
+
+    #[test]
+    fn reads_schema_fields() {
+        let schema = include_str!("../../schema.graphql");
+        let query = schema_type_fields(schema, "Query");
+        for field in ["products", "categories", "channels", "app", "collections"] {
+            assert!(query.contains(&field.to_owned()), "{field} missing");
+        }
+        assert!(!query.contains(&"first".to_owned()));
+        assert!(schema_type_fields(schema, "Mutation").contains(&"productUpdate".to_owned()));
+    }
+}
@@ -0,0 +1,405 @@
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    encrypted_metadata::EncryptedMetadataSettingsManagerError,
+    metadata::{MetadataSettingsManager, MetadataSettingsManagerError},
+    SettingsManager,
+};
+use crate::AuthData;
+
+/// Lets [`ChannelConfigManager`] tell a missing key apart from other settings manager errors
+pub trait SettingsError: std::error::Error {
+    fn is_not_found(&self) -> bool;
+}
+
+impl SettingsError for MetadataSettingsManagerError {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::KeyNotFound)
+    }
+}
+
+impl SettingsError for EncryptedMetadataSettingsManagerError {
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::KeyNotFound)
+    }
+}
+
+/**
+ App configuration stored per channel by [`ChannelConfigManager`].
+
+ Bump `VERSION` whenever the struct changes in a way old payloads don't deserialize into, and
+ teach [`ChannelConfig::migrate`] to upgrade payloads from the previous version.
+
+ ```ignore
+ #[derive(Serialize, Deserialize, Clone)]
+ struct GatewayConfig {
+     active_payment_methods: Vec<PaymentMethodType>,
+ }
+
+ impl ChannelConfig for GatewayConfig {
+     const NAME: &'static str = "gateway";
+     const VERSION: u32 = 2;
+     fn migrate(from_version: u32, mut data: Value) -> Result<Value, String> {
+         match from_version {
+             // v1 stored a comma separated string
+             1 => { .. Ok(data) }
+             v => Err(format!("unknown version {v}")),
+         }
+     }
+ }
+ ```
+*/
+pub trait ChannelConfig: Serialize + DeserializeOwned + Send + Sync {
+    /// Prefix of the settings keys, different configs of one app need different names
+    const NAME: &'static str;
+    const VERSION: u32 = 1;
+
+    /// Upgrades a payload stored by `from_version` to `from_version + 1`
+    fn migrate(from_version: u32, data: Value) -> Result<Value, String> {
+        let _ = data;
+        Err(format!("no migration from version {from_version}"))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChannelConfigError<E> {
+    #[error("Settings manager failed, {0}")]
+    Settings(E),
+    #[error("Neither the channel nor the default configuration is set")]
+    NotConfigured,
+    #[error("Failed de/serializing configuration, {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Failed migrating configuration from version {from}, {reason}")]
+    Migration { from: u32, reason: String },
+    #[error("Stored configuration is version {0}, newer than this app understands")]
+    UnknownVersion(u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Versioned {
+    version: u32,
+    data: Value,
+}
+
+/**
+ Typed configuration per channel of one saleor instance, on top of any [`SettingsManager`]
+ storing json values, eg. [`super::metadata::MetadataSettingsManager<String, Value>`] or its
+ encrypted counterpart for secrets. Channels without their own configuration fall back to the
+ default one. Payloads are stored with the version of `C` that wrote them, and migrated on read.
+*/
+#[derive(Debug)]
+pub struct ChannelConfigManager<S, C, E> {
+    pub settings: S,
+    pub saleor_api_url: String,
+    _types: PhantomData<fn() -> (C, E)>,
+}
+
+impl<S, C, E> ChannelConfigManager<S, C, E>
+where
+    S: SettingsManager<String, Value, E>,
+    C: ChannelConfig,
+    E: SettingsError,
+{
+    pub fn new(settings: S, saleor_api_url: &str) -> Self {
+        Self {
+            settings,
+            saleor_api_url: saleor_api_url.to_owned(),
+            _types: PhantomData,
+        }
+    }
+
+    fn default_key() -> String {
+        format!("{}.default", C::NAME)
+    }
+
+    fn channel_key(channel: &str) -> String {
+        format!("{}.channel.{channel}", C::NAME)
+    }
+
+    fn index_key() -> String {
+        format!("{}.channels", C::NAME)
+    }
+
+    /// Configuration of `channel`, or the default one if the channel has none
+    pub async fn get(&mut self, channel: &str) -> Result<C, ChannelConfigError<E>> {
+        match self.load(Self::channel_key(channel)).await? {
+            Some(config) => Ok(config),
+            None => self.get_default().await,
+        }
+    }
+
+    pub async fn get_default(&mut self) -> Result<C, ChannelConfigError<E>> {
+        self.load(Self::default_key())
+            .await?
+            .ok_or(ChannelConfigError::NotConfigured)
+    }
+
+    /// Configuration set for exactly this channel, without falling back to the default
+    pub async fn get_channel(&mut self, channel: &str) -> Result<Option<C>, ChannelConfigError<E>> {
+        self.load(Self::channel_key(channel)).await
+    }
+
+    pub async fn set_default(&mut self, config: &C) -> Result<(), ChannelConfigError<E>> {
+        self.store(Self::default_key(), config).await
+    }
+
+    /// Stores `config` as the default unless one is set already, returning the default in effect
+    pub async fn init_default(&mut self, config: C) -> Result<C, ChannelConfigError<E>> {
+        match self.load(Self::default_key()).await? {
+            Some(stored) => Ok(stored),
+            None => {
+                self.set_default(&config).await?;
+                Ok(config)
+            }
+        }
+    }
+
+    pub async fn set(&mut self, channel: &str, config: &C) -> Result<(), ChannelConfigError<E>> {
+        self.store(Self::channel_key(channel), config).await?;
+        let mut channels = self.channels().await?;
+        if !channels.iter().any(|c| c == channel) {
+            channels.push(channel.to_owned());
+            self.store_index(channels).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the channels own configuration, so it falls back to the default again
+    pub async fn delete(&mut self, channel: &str) -> Result<(), ChannelConfigError<E>> {
+        match self
+            .settings
+            .delete(Self::channel_key(channel), &self.saleor_api_url)
+            .await
+        {
+            Ok(_) => (),
+            Err(e) if e.is_not_found() => (),
+            Err(e) => return Err(ChannelConfigError::Settings(e)),
+        }
+        let mut channels = self.channels().await?;
+        if let Some(i) = channels.iter().position(|c| c == channel) {
+            channels.remove(i);
+            self.store_index(channels).await?;
+        }
+        Ok(())
+    }
+
+    /// Slugs of channels that have their own configuration
+    pub async fn channels(&mut self) -> Result<Vec<String>, ChannelConfigError<E>> {
+        match self.get_raw(Self::index_key()).await? {
+            Some(index) => Ok(serde_json::from_value(index)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn store_index(&mut self, channels: Vec<String>) -> Result<(), ChannelConfigError<E>> {
+        self.settings
+            .set(
+                Self::index_key(),
+                serde_json::to_value(channels)?,
+                &self.saleor_api_url,
+            )
+            .await
+            .map_err(ChannelConfigError::Settings)
+    }
+
+    async fn get_raw(&mut self, key: String) -> Result<Option<Value>, ChannelConfigError<E>> {
+        match self.settings.get(key, &self.saleor_api_url).await {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(ChannelConfigError::Settings(e)),
+        }
+    }
+
+    async fn store(&mut self, key: String, config: &C) -> Result<(), ChannelConfigError<E>> {
+        let versioned = Versioned {
+            version: C::VERSION,
+            data: serde_json::to_value(config)?,
+        };
+        self.settings
+            .set(key, serde_json::to_value(versioned)?, &self.saleor_api_url)
+            .await
+            .map_err(ChannelConfigError::Settings)
+    }
+
+    /// Reads and migrates the payload, storing it back if it was migrated
+    async fn load(&mut self, key: String) -> Result<Option<C>, ChannelConfigError<E>> {
+        let Some(raw) = self.get_raw(key.clone()).await? else {
+            return Ok(None);
+        };
+        let Versioned { version, mut data } = serde_json::from_value(raw)?;
+        if version > C::VERSION {
+            return Err(ChannelConfigError::UnknownVersion(version));
+        }
+        for from in version..C::VERSION {
+            data = C::migrate(from, data)
+                .map_err(|reason| ChannelConfigError::Migration { from, reason })?;
+        }
+        let config: C = serde_json::from_value(data)?;
+        if version < C::VERSION {
+            self.store(key, &config).await?;
+        }
+        Ok(Some(config))
+    }
+}
+
+/// [`ChannelConfigManager`] over the apps metadata
+pub type MetadataChannelConfigManager<C> =
+    ChannelConfigManager<MetadataSettingsManager<String, Value>, C, MetadataSettingsManagerError>;
+
+impl<C: ChannelConfig> MetadataChannelConfigManager<C> {
+    /// Fetches the apps metadata from the saleor instance `auth_data` belongs to
+    pub async fn from_auth_data(
+        auth_data: AuthData,
+    ) -> Result<Self, ChannelConfigError<MetadataSettingsManagerError>> {
+        let saleor_api_url = auth_data.saleor_api_url.clone();
+        let settings = MetadataSettingsManager::new(auth_data)
+            .await
+            .map_err(ChannelConfigError::Settings)?;
+        Ok(Self::new(settings, &saleor_api_url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemorySettings(HashMap<(String, String), Value>);
+
+    #[async_trait]
+    impl SettingsManager<String, Value, MetadataSettingsManagerError> for MemorySettings {
+        async fn get(
+            &mut self,
+            key: String,
+            domain: &str,
+        ) -> Result<Value, MetadataSettingsManagerError> {
+            self.0
+                .get(&(domain.to_owned(), key))
+                .cloned()
+                .ok_or(MetadataSettingsManagerError::KeyNotFound)
+        }
+        async fn set(
+            &mut self,
+            key: String,
+            value: Value,
+            domain: &str,
+        ) -> Result<(), MetadataSettingsManagerError> {
+            self.0.insert((domain.to_owned(), key), value);
+            Ok(())
+        }
+        async fn delete(
+            &mut self,
+            key: String,
+            domain: &str,
+        ) -> Result<Value, MetadataSettingsManagerError> {
+            self.0
+                .remove(&(domain.to_owned(), key))
+                .ok_or(MetadataSettingsManagerError::KeyNotFound)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Config {
+        methods: Vec<String>,
+    }
+
+    impl ChannelConfig for Config {
+        const NAME: &'static str = "test";
+        const VERSION: u32 = 2;
+        fn migrate(from_version: u32, data: Value) -> Result<Value, String> {
+            match from_version {
+                1 => {
+                    let methods = data["methods"].as_str().ok_or("methods isn't a string")?;
+                    Ok(serde_json::json!({ "methods": methods.split(',').collect::<Vec<_>>() }))
+                }
+                v => Err(format!("unknown version {v}")),
+            }
+        }
+    }
+
+    type Manager = ChannelConfigManager<MemorySettings, Config, MetadataSettingsManagerError>;
+
+    fn config(methods: &[&str]) -> Config {
+        Config {
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_default() {
+        let mut mngr = Manager::new(MemorySettings::default(), "https://example.com");
+        assert!(matches!(
+            mngr.get("eu").await,
+            Err(ChannelConfigError::NotConfigured)
+        ));
+
+        mngr.set_default(&config(&["cod"])).await.unwrap();
+        mngr.set("eu", &config(&["transfer"])).await.unwrap();
+        assert_eq!(mngr.get("eu").await.unwrap(), config(&["transfer"]));
+        assert_eq!(mngr.get("us").await.unwrap(), config(&["cod"]));
+        assert_eq!(mngr.get_channel("us").await.unwrap(), None);
+
+        mngr.delete("eu").await.unwrap();
+        assert_eq!(mngr.get("eu").await.unwrap(), config(&["cod"]));
+
+        // an existing default isn't overwritten
+        let default = mngr.init_default(config(&["cash"])).await.unwrap();
+        assert_eq!(default, config(&["cod"]));
+    }
+
+    #[tokio::test]
+    async fn lists_configured_channels() {
+        let mut mngr = Manager::new(MemorySettings::default(), "https://example.com");
+        mngr.set("eu", &config(&[])).await.unwrap();
+        mngr.set("us", &config(&[])).await.unwrap();
+        mngr.set("eu", &config(&["cod"])).await.unwrap();
+        assert_eq!(mngr.channels().await.unwrap(), vec!["eu", "us"]);
+
+        mngr.delete("eu").await.unwrap();
+        mngr.delete("never-configured").await.unwrap();
+        assert_eq!(mngr.channels().await.unwrap(), vec!["us"]);
+    }
+
+    #[tokio::test]
+    async fn migrates_old_payloads() {
+        let mut settings = MemorySettings::default();
+        settings
+            .set(
+                "test.channel.eu".to_owned(),
+                serde_json::json!({ "version": 1, "data": { "methods": "cod,cash" } }),
+                "https://example.com",
+            )
+            .await
+            .unwrap();
+        settings
+            .set(
+                "test.default".to_owned(),
+                serde_json::json!({ "version": 3, "data": {} }),
+                "https://example.com",
+            )
+            .await
+            .unwrap();
+
+        let mut mngr = Manager::new(settings, "https://example.com");
+        assert_eq!(mngr.get("eu").await.unwrap(), config(&["cod", "cash"]));
+        // migrated payload got stored back
+        assert_eq!(
+            mngr.settings.0[&(
+                "https://example.com".to_owned(),
+                "test.channel.eu".to_owned()
+            )]["version"],
+            2
+        );
+        assert!(matches!(
+            mngr.get_default().await,
+            Err(ChannelConfigError::UnknownVersion(3))
+        ));
+    }
+}
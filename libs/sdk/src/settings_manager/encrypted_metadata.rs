@@ -1 +1,405 @@
-//TODO: How does cryptography in rust work TT
+use crate::{cipher::Cipher, config::Config, AuthData};
+
+use super::queries::{
+    DeleteAppPrivateMetadata, DeleteAppPrivateMetadataVariables, GetAppPrivateMetadata,
+    GetAppPrivateMetadataVariables, MetadataInput, SetAppPrivateMetadata,
+    SetAppPrivateMetadataVariables,
+};
+use super::SettingsManager;
+use async_trait::async_trait;
+use cynic::{http::SurfExt, QueryBuilder};
+use cynic::{GraphQlError, MutationBuilder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::str::FromStr;
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+const KDF_INFO: &[u8] = b"saleor-app-sdk encrypted private metadata";
+
+#[derive(thiserror::Error, Debug)]
+pub enum EncryptedMetadataSettingsManagerError {
+    #[error("Error during graphql querys http request, {0}")]
+    HttpRequestError(surf::Error),
+    #[error("Graphql query contains errors, {0}")]
+    GraphQlError(#[from] GraphQlError),
+    #[error("Key was not found in private metadata")]
+    KeyNotFound,
+    #[error("SECRET_KEY is missing or empty")]
+    MissingSecretKey,
+    #[error("Failed de/serializing value, {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Failed encrypting value")]
+    Encryption,
+    #[error(
+        "Failed decrypting value, it's corrupted or was encrypted with a different SECRET_KEY"
+    )]
+    Decryption,
+    #[error("Key got deleted, but its value couldn't be read, {0}")]
+    DeletedUnreadable(Box<EncryptedMetadataSettingsManagerError>),
+}
+
+/**
+ Values get encrypted with XChaCha20-Poly1305, with the key derived from `SECRET_KEY` through
+ HKDF-SHA256. The metadata key and app id are authenticated with every value, so values can't
+ be moved between keys or installations without failing decryption.
+*/
+#[derive(Clone)]
+pub struct MetadataCipher {
+    cipher: Cipher,
+}
+
+impl std::fmt::Debug for MetadataCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MetadataCipher(..)")
+    }
+}
+
+impl MetadataCipher {
+    pub fn new(secret_key: &str) -> Result<Self, EncryptedMetadataSettingsManagerError> {
+        if secret_key.is_empty() {
+            return Err(EncryptedMetadataSettingsManagerError::MissingSecretKey);
+        }
+        Ok(Self {
+            cipher: Cipher::new(secret_key, KDF_INFO),
+        })
+    }
+
+    /// Base64 of the random nonce followed by the ciphertext
+    pub fn encrypt(
+        &self,
+        plaintext: &str,
+        aad: &str,
+    ) -> Result<String, EncryptedMetadataSettingsManagerError> {
+        self.cipher
+            .encrypt(plaintext, aad)
+            .ok_or(EncryptedMetadataSettingsManagerError::Encryption)
+    }
+
+    pub fn decrypt(
+        &self,
+        encrypted: &str,
+        aad: &str,
+    ) -> Result<String, EncryptedMetadataSettingsManagerError> {
+        self.cipher
+            .decrypt(encrypted, aad)
+            .ok_or(EncryptedMetadataSettingsManagerError::Decryption)
+    }
+}
+
+/**
+ Like [`super::metadata::MetadataSettingsManager`], but stores values encrypted in the apps
+ private metadata, so secrets like gateway credentials don't leak through the dashboard or API.
+ Values are only decrypted on [`SettingsManager::get`], a wrong `SECRET_KEY` fails with
+ [`EncryptedMetadataSettingsManagerError::Decryption`] instead of looking like a missing key.
+*/
+#[derive(Debug, Clone)]
+pub struct EncryptedMetadataSettingsManager<K: Hash + Eq + FromStr + ToString, V> {
+    /// Encrypted values as stored in saleor
+    pub private_metadata: HashMap<String, String>,
+    pub auth_data: AuthData,
+    cipher: MetadataCipher,
+    _types: PhantomData<fn() -> (K, V)>,
+}
+
+#[async_trait]
+impl<
+        K: Hash + Eq + Send + Sync + FromStr + ToString,
+        V: Send + Sync + Clone + Serialize + DeserializeOwned,
+    > SettingsManager<K, V, EncryptedMetadataSettingsManagerError>
+    for EncryptedMetadataSettingsManager<K, V>
+{
+    async fn get(
+        &mut self,
+        key: K,
+        domain: &str,
+    ) -> Result<V, EncryptedMetadataSettingsManagerError> {
+        self.remote_get(Some(domain)).await?;
+        self.decrypt_value(&key)
+    }
+
+    async fn set(
+        &mut self,
+        key: K,
+        value: V,
+        domain: &str,
+    ) -> Result<(), EncryptedMetadataSettingsManagerError> {
+        let key = key.to_string();
+        let encrypted = self
+            .cipher
+            .encrypt(&serde_json::to_string(&value)?, &self.aad(&key))?;
+        self.private_metadata.insert(key.clone(), encrypted.clone());
+        self.remote_set(
+            Some(domain),
+            vec![MetadataInput {
+                key,
+                value: encrypted,
+            }],
+        )
+        .await
+    }
+
+    /// Values that fail decrypting still get deleted, the decryption error is returned as
+    /// [`EncryptedMetadataSettingsManagerError::DeletedUnreadable`] afterwards
+    async fn delete(
+        &mut self,
+        key: K,
+        domain: &str,
+    ) -> Result<V, EncryptedMetadataSettingsManagerError> {
+        if !self.private_metadata.contains_key(&key.to_string()) {
+            return Err(EncryptedMetadataSettingsManagerError::KeyNotFound);
+        }
+        let removed = self.decrypt_value(&key);
+        self.private_metadata.remove(&key.to_string());
+        self.remote_delete(Some(domain), vec![key]).await?;
+        removed.map_err(|e| EncryptedMetadataSettingsManagerError::DeletedUnreadable(Box::new(e)))
+    }
+}
+
+impl<
+        K: Hash + Eq + Send + Sync + FromStr + ToString,
+        V: Send + Sync + DeserializeOwned + Serialize,
+    > EncryptedMetadataSettingsManager<K, V>
+{
+    /**
+    creates a new manager, and also prefetches the private metadata from saleor. Values stay
+    encrypted in memory until read.
+    */
+    pub async fn new(
+        auth_data: AuthData,
+        secret_key: &str,
+    ) -> Result<Self, EncryptedMetadataSettingsManagerError> {
+        let mut mngr = Self {
+            private_metadata: HashMap::new(),
+            cipher: MetadataCipher::new(secret_key)?,
+            auth_data,
+            _types: PhantomData,
+        };
+        mngr.remote_get(None).await?;
+        Ok(mngr)
+    }
+
+    /// [`Self::new`] with [`Config::secret_key`]
+    pub async fn from_config(
+        auth_data: AuthData,
+        config: &Config,
+    ) -> Result<Self, EncryptedMetadataSettingsManagerError> {
+        let secret_key = config
+            .secret_key
+            .as_deref()
+            .ok_or(EncryptedMetadataSettingsManagerError::MissingSecretKey)?;
+        Self::new(auth_data, secret_key).await
+    }
+
+    fn aad(&self, key: &str) -> String {
+        format!("{}:{key}", self.auth_data.app_id)
+    }
+
+    fn decrypt_value(&self, key: &K) -> Result<V, EncryptedMetadataSettingsManagerError> {
+        let key = key.to_string();
+        let encrypted = self
+            .private_metadata
+            .get(&key)
+            .ok_or(EncryptedMetadataSettingsManagerError::KeyNotFound)?;
+        let plaintext = self.cipher.decrypt(encrypted, &self.aad(&key))?;
+        Ok(serde_json::from_str(&plaintext)?)
+    }
+
+    pub async fn remote_delete(
+        &mut self,
+        api_url: Option<&str>,
+        keys: Vec<K>,
+    ) -> Result<(), EncryptedMetadataSettingsManagerError> {
+        let app_id = cynic::Id::new(&self.auth_data.app_id);
+        let keys = keys.into_iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        let operation = DeleteAppPrivateMetadata::build(DeleteAppPrivateMetadataVariables {
+            app_id: &app_id,
+            keys: keys.iter().map(|k| k.as_str()).collect(),
+        });
+        match surf::post(api_url.unwrap_or(&self.auth_data.saleor_api_url))
+            .header("authorization-bearer", &self.auth_data.token)
+            .run_graphql(operation)
+            .await
+        {
+            Ok(response) => {
+                if let Some(e) = response.errors.and_then(|e| e.first().cloned()) {
+                    return Err(e.into());
+                }
+                Ok(())
+            }
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                debug!("{:?}", e);
+                Err(EncryptedMetadataSettingsManagerError::HttpRequestError(e))
+            }
+        }
+    }
+
+    /**
+    sets the given, already encrypted entries in the apps private metadata
+    */
+    pub async fn remote_set(
+        &mut self,
+        api_url: Option<&str>,
+        input: Vec<MetadataInput>,
+    ) -> Result<(), EncryptedMetadataSettingsManagerError> {
+        let app_id = cynic::Id::new(&self.auth_data.app_id);
+        let operation = SetAppPrivateMetadata::build(SetAppPrivateMetadataVariables {
+            app_id: &app_id,
+            input,
+        });
+        match surf::post(api_url.unwrap_or(&self.auth_data.saleor_api_url))
+            .header("authorization-bearer", &self.auth_data.token)
+            .run_graphql(operation)
+            .await
+        {
+            Ok(response) => {
+                if let Some(e) = response.errors.and_then(|e| e.first().cloned()) {
+                    return Err(e.into());
+                }
+                Ok(())
+            }
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                debug!("{:?}", e);
+                Err(EncryptedMetadataSettingsManagerError::HttpRequestError(e))
+            }
+        }
+    }
+
+    /**
+    refetches the encrypted private metadata from saleor
+    */
+    pub async fn remote_get(
+        &mut self,
+        api_url: Option<&str>,
+    ) -> Result<&mut Self, EncryptedMetadataSettingsManagerError> {
+        let app_id = cynic::Id::new(&self.auth_data.app_id);
+        let operation =
+            GetAppPrivateMetadata::build(GetAppPrivateMetadataVariables { app_id: &app_id });
+        match surf::post(api_url.unwrap_or(&self.auth_data.saleor_api_url))
+            .header("authorization-bearer", &self.auth_data.token)
+            .run_graphql(operation)
+            .await
+        {
+            Ok(response) => {
+                if let Some(e) = response.errors.and_then(|e| e.first().cloned()) {
+                    return Err(e.into());
+                }
+                self.private_metadata = response
+                    .data
+                    .and_then(|d| d.app)
+                    .map(|app| {
+                        app.private_metadata
+                            .into_iter()
+                            .map(|m| (m.key, m.value))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(self)
+            }
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                debug!("{:?}", e);
+                Err(EncryptedMetadataSettingsManagerError::HttpRequestError(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypts_and_decrypts() {
+        let cipher = MetadataCipher::new("very secret").unwrap();
+        let encrypted = cipher.encrypt("\"sk_live_123\"", "app:key").unwrap();
+        assert!(!encrypted.contains("sk_live_123"));
+        assert_eq!(
+            cipher.decrypt(&encrypted, "app:key").unwrap(),
+            "\"sk_live_123\""
+        );
+        // random nonces, same value never encrypts the same twice
+        assert_ne!(
+            encrypted,
+            cipher.encrypt("\"sk_live_123\"", "app:key").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_key_and_moved_values() {
+        let cipher = MetadataCipher::new("very secret").unwrap();
+        let encrypted = cipher.encrypt("value", "app:key").unwrap();
+
+        let other = MetadataCipher::new("other secret").unwrap();
+        assert!(matches!(
+            other.decrypt(&encrypted, "app:key"),
+            Err(EncryptedMetadataSettingsManagerError::Decryption)
+        ));
+        assert!(matches!(
+            cipher.decrypt(&encrypted, "app:other_key"),
+            Err(EncryptedMetadataSettingsManagerError::Decryption)
+        ));
+        assert!(matches!(
+            cipher.decrypt("not base64!", "app:key"),
+            Err(EncryptedMetadataSettingsManagerError::Decryption)
+        ));
+    }
+
+    #[cfg(feature = "mock_saleor")]
+    #[tokio::test]
+    async fn deletes_values_it_cant_decrypt() {
+        use serde_json::json;
+
+        use crate::mock_saleor::{MockSaleor, MOCK_APP_ID};
+
+        let saleor = MockSaleor::start().await.unwrap();
+        let encrypted = MetadataCipher::new("old secret")
+            .unwrap()
+            .encrypt("\"sk_live_123\"", &format!("{MOCK_APP_ID}:api_key"))
+            .unwrap();
+        saleor.set_query(
+            "app",
+            json!({ "privateMetadata": [{ "key": "api_key", "value": encrypted }] }),
+        );
+        saleor.on_mutation("deletePrivateMetadata", |_| {
+            Ok(json!({ "item": { "privateMetadata": [] } }))
+        });
+
+        let mut manager = EncryptedMetadataSettingsManager::<String, String>::new(
+            saleor.auth_data("http://localhost:3000"),
+            "new secret",
+        )
+        .await
+        .unwrap();
+        let res = manager
+            .delete("api_key".to_owned(), &saleor.api_url())
+            .await;
+        assert!(matches!(
+            res,
+            Err(EncryptedMetadataSettingsManagerError::DeletedUnreadable(e))
+                if matches!(*e, EncryptedMetadataSettingsManagerError::Decryption)
+        ));
+        assert_eq!(saleor.request_count("deletePrivateMetadata"), 1);
+        assert!(manager.private_metadata.is_empty());
+
+        assert!(matches!(
+            manager
+                .delete("api_key".to_owned(), &saleor.api_url())
+                .await,
+            Err(EncryptedMetadataSettingsManagerError::KeyNotFound)
+        ));
+    }
+
+    #[test]
+    fn empty_secret_key_is_rejected() {
+        assert!(matches!(
+            MetadataCipher::new(""),
+            Err(EncryptedMetadataSettingsManagerError::MissingSecretKey)
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use super::{
+    channel_config::{ChannelConfig, ChannelConfigError, MetadataChannelConfigManager},
+    metadata::MetadataSettingsManagerError,
+};
+use crate::AuthData;
+
+pub type InstanceConfigError = ChannelConfigError<MetadataSettingsManagerError>;
+
+/// How long [`InstanceConfigs`] serves a configuration from memory before refetching it
+pub const DEFAULT_INSTANCE_CONFIG_TTL: Duration = Duration::from_secs(300);
+
+/**
+ Default [`ChannelConfig`] of every registered saleor instance, keyed by `saleor_api_url`. Configs
+ are stored in the apps metadata of each instance, and kept in memory for `ttl` after being read,
+ so one running app can serve multiple shops with different settings.
+
+ Call [`Self::init`] from `/api/register` with the configuration from env, so new installations
+ start with it, then [`Self::get_or_init`] wherever the configuration is needed, with the same
+ configuration for instances installed before there was one to store. Saleor sends `APP_UPDATED`
+ webhooks when the apps metadata changes, apps subscribed to it should call [`Self::remove`] so
+ changes made elsewhere apply right away instead of after `ttl`.
+*/
+#[derive(Debug)]
+pub struct InstanceConfigs<C> {
+    configs: Arc<RwLock<HashMap<String, (C, Instant)>>>,
+    pub ttl: Duration,
+}
+
+impl<C> Clone for InstanceConfigs<C> {
+    fn clone(&self) -> Self {
+        Self {
+            configs: self.configs.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<C> Default for InstanceConfigs<C> {
+    fn default() -> Self {
+        Self {
+            configs: Arc::new(RwLock::new(HashMap::new())),
+            ttl: DEFAULT_INSTANCE_CONFIG_TTL,
+        }
+    }
+}
+
+impl<C: ChannelConfig + Clone> InstanceConfigs<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps configurations in memory for `ttl` instead of [`DEFAULT_INSTANCE_CONFIG_TTL`]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Configuration of the instance, fetched from its metadata unless it's in memory and fresh
+    pub async fn get(&self, auth_data: &AuthData) -> Result<C, InstanceConfigError> {
+        if let Some(config) = self.cached(&auth_data.saleor_api_url) {
+            return Ok(config);
+        }
+        debug!("fetching config of {}", &auth_data.saleor_api_url);
+        let config = MetadataChannelConfigManager::<C>::from_auth_data(auth_data.clone())
+            .await?
+            .get_default()
+            .await?;
+        self.insert(&auth_data.saleor_api_url, config.clone());
+        Ok(config)
+    }
+
+    /**
+     Like [`Self::get`], but stores `default` for instances that have no configuration, eg. ones
+     registered before the app kept configurations in metadata, instead of failing with
+     [`ChannelConfigError::NotConfigured`].
+    */
+    pub async fn get_or_init(
+        &self,
+        auth_data: &AuthData,
+        default: &C,
+    ) -> Result<C, InstanceConfigError> {
+        match self.get(auth_data).await {
+            Err(ChannelConfigError::NotConfigured) => {
+                debug!(
+                    "{} has no config, storing the default",
+                    &auth_data.saleor_api_url
+                );
+                self.init(auth_data, default.clone()).await
+            }
+            res => res,
+        }
+    }
+
+    /// Stores `default` unless the instance has a configuration already, eg. from previous install
+    pub async fn init(&self, auth_data: &AuthData, default: C) -> Result<C, InstanceConfigError> {
+        let config = MetadataChannelConfigManager::<C>::from_auth_data(auth_data.clone())
+            .await?
+            .init_default(default)
+            .await?;
+        self.insert(&auth_data.saleor_api_url, config.clone());
+        Ok(config)
+    }
+
+    pub async fn set(&self, auth_data: &AuthData, config: C) -> Result<(), InstanceConfigError> {
+        MetadataChannelConfigManager::<C>::from_auth_data(auth_data.clone())
+            .await?
+            .set_default(&config)
+            .await?;
+        self.insert(&auth_data.saleor_api_url, config);
+        Ok(())
+    }
+
+    /// Configuration in memory, unless it's older than `ttl`
+    pub fn cached(&self, saleor_api_url: &str) -> Option<C> {
+        self.configs
+            .read()
+            .expect("instance configs lock is poisoned")
+            .get(saleor_api_url)
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .map(|(config, _)| config.clone())
+    }
+
+    /// Puts the configuration into memory only, without storing it in saleor
+    pub fn insert(&self, saleor_api_url: &str, config: C) {
+        self.configs
+            .write()
+            .expect("instance configs lock is poisoned")
+            .insert(saleor_api_url.to_owned(), (config, Instant::now()));
+    }
+
+    /// Forgets the configuration, so the next [`Self::get`] refetches it
+    pub fn remove(&self, saleor_api_url: &str) -> Option<C> {
+        self.configs
+            .write()
+            .expect("instance configs lock is poisoned")
+            .remove(saleor_api_url)
+            .map(|(config, _)| config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Config {
+        channel: String,
+    }
+
+    impl ChannelConfig for Config {
+        const NAME: &'static str = "test";
+    }
+
+    #[tokio::test]
+    async fn serves_instances_from_memory() {
+        let configs = InstanceConfigs::<Config>::new();
+        let auth_data = |saleor_api_url: &str| AuthData {
+            domain: None,
+            token: "".into(),
+            saleor_api_url: saleor_api_url.into(),
+            app_id: "".into(),
+            jwks: None,
+        };
+        configs.insert(
+            "https://one.example.com/graphql/",
+            Config {
+                channel: "one".into(),
+            },
+        );
+        configs.clone().insert(
+            "https://two.example.com/graphql/",
+            Config {
+                channel: "two".into(),
+            },
+        );
+
+        let one = configs
+            .get(&auth_data("https://one.example.com/graphql/"))
+            .await
+            .unwrap();
+        assert_eq!(one.channel, "one");
+        let two = configs
+            .get(&auth_data("https://two.example.com/graphql/"))
+            .await
+            .unwrap();
+        assert_eq!(two.channel, "two");
+
+        configs.remove("https://two.example.com/graphql/");
+        assert!(configs.cached("https://two.example.com/graphql/").is_none());
+    }
+
+    #[test]
+    fn expires_after_ttl() {
+        let configs = InstanceConfigs::<Config>::new();
+        let expired = configs.clone().with_ttl(Duration::ZERO);
+        configs.insert(
+            "https://one.example.com/graphql/",
+            Config {
+                channel: "one".into(),
+            },
+        );
+
+        assert!(configs.cached("https://one.example.com/graphql/").is_some());
+        assert!(expired.cached("https://one.example.com/graphql/").is_none());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "mock_saleor")]
+    async fn seeds_instances_without_config() {
+        use serde_json::json;
+
+        use crate::mock_saleor::MockSaleor;
+
+        let saleor = MockSaleor::start().await.unwrap();
+        saleor.set_query("app", json!({ "metadata": [] }));
+        saleor.on_mutation("updateMetadata", |_| Ok(json!({ "item": null })));
+        saleor.on_mutation("deleteMetadata", |_| Ok(json!({ "item": null })));
+        let auth_data = saleor.auth_data("http://localhost:3000");
+        let configs = InstanceConfigs::<Config>::new();
+        let default = Config {
+            channel: "default".into(),
+        };
+
+        assert!(matches!(
+            configs.get(&auth_data).await,
+            Err(ChannelConfigError::NotConfigured)
+        ));
+        let config = configs.get_or_init(&auth_data, &default).await.unwrap();
+        assert_eq!(config, default);
+        assert_eq!(saleor.request_count("updateMetadata"), 1);
+        assert_eq!(configs.cached(&auth_data.saleor_api_url), Some(default));
+    }
+}
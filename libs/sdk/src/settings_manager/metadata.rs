@@ -1,8 +1,9 @@
 use crate::AuthData;
 
 use super::queries::{
-    DeleteAppMetadata, DeleteAppMetadataVariables, GetAppMetadata, GetAppMetadataVariables,
-    MetadataInput, SetAppMetadata, SetAppMetadataVariables,
+    BatchAppMetadata, BatchAppMetadataVariables, DeleteAppMetadata, DeleteAppMetadataVariables,
+    GetAppMetadata, GetAppMetadataVariables, MetadataInput, SetAppMetadata,
+    SetAppMetadataVariables,
 };
 use super::SettingsManager;
 use async_trait::async_trait;
@@ -14,8 +15,12 @@ use std::collections::HashMap;
 use std::hash::{Hash, RandomState};
 use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use tracing::debug;
 
+/// How long fetched metadata is served from memory before [`SettingsManager::get`] refetches it
+pub const DEFAULT_METADATA_TTL: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Clone)]
 pub struct Metadata<K: Hash + Eq + FromStr + ToString, V: Serialize + DeserializeOwned>(
     pub HashMap<K, V>,
@@ -36,6 +41,12 @@ impl<K: Hash + Eq + FromStr + ToString, V: Serialize + DeserializeOwned> DerefMu
     }
 }
 
+/**
+ Read-through cache of the apps metadata. Reads are served from memory until `ttl` passes since
+ the last fetch, as long as they're for the `domain` it was fetched from, writes only send the
+ changed keys. Call [`Self::invalidate`] when the metadata
+ changed elsewhere, saleor sends `APP_UPDATED` webhooks when the apps metadata gets updated.
+*/
 #[derive(Debug, Clone)]
 pub struct MetadataSettingsManager<
     K: Hash + Eq + FromStr + ToString,
@@ -43,6 +54,51 @@ pub struct MetadataSettingsManager<
 > {
     pub metadata: Metadata<K, V>,
     pub auth_data: AuthData,
+    pub ttl: Duration,
+    fetched_at: Option<Instant>,
+    /// Api url the metadata in memory was fetched from
+    fetched_from: Option<String>,
+}
+
+/**
+ Sets and deletes applied together by [`MetadataSettingsManager::apply`], in a single request.
+ When a key is both set and deleted, the last operation wins.
+
+ ```ignore
+ let batch = MetadataBatch::new()
+     .set(AppSettingsKey::Global, global)
+     .delete(AppSettingsKey::User(id));
+ settings.apply(batch, &saleor_api_url).await?;
+ ```
+*/
+#[derive(Debug, Clone)]
+pub struct MetadataBatch<K: Hash + Eq, V> {
+    ops: HashMap<K, Option<V>>,
+}
+
+impl<K: Hash + Eq, V> Default for MetadataBatch<K, V> {
+    fn default() -> Self {
+        Self {
+            ops: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> MetadataBatch<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn set(mut self, key: K, value: V) -> Self {
+        self.ops.insert(key, Some(value));
+        self
+    }
+    pub fn delete(mut self, key: K) -> Self {
+        self.ops.insert(key, None);
+        self
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -53,6 +109,8 @@ pub enum MetadataSettingsManagerError {
     GraphQlError(#[from] GraphQlError),
     #[error("Key was not found in hashmap/metadata")]
     KeyNotFound,
+    #[error("Failed serializing value, {0}")]
+    Serialization(#[from] serde_json::Error),
 }
 
 #[async_trait]
@@ -62,8 +120,9 @@ impl<
     > SettingsManager<K, V, MetadataSettingsManagerError> for MetadataSettingsManager<K, V>
 {
     async fn get(&mut self, key: K, domain: &str) -> Result<V, MetadataSettingsManagerError> {
-        //TODO: Create a cache instead of refetching every time
-        self.remote_get(Some(domain)).await?;
+        if !self.is_cached(domain) {
+            self.remote_get(Some(domain)).await?;
+        }
         let val = self
             .metadata
             .0
@@ -78,18 +137,21 @@ impl<
         value: V,
         domain: &str,
     ) -> Result<(), MetadataSettingsManagerError> {
-        let _ = self.metadata.0.insert(key, value);
-        self.remote_set(Some(domain)).await?;
-        Ok(())
+        self.apply(MetadataBatch::new().set(key, value), domain)
+            .await
     }
 
     async fn delete(&mut self, key: K, domain: &str) -> Result<V, MetadataSettingsManagerError> {
+        if !self.is_cached(domain) {
+            self.remote_get(Some(domain)).await?;
+        }
         let removed = self
             .metadata
             .0
-            .remove(&key)
+            .get(&key)
+            .cloned()
             .ok_or(MetadataSettingsManagerError::KeyNotFound)?;
-        self.remote_delete(Some(domain), vec![key]).await?;
+        self.apply(MetadataBatch::new().delete(key), domain).await?;
         Ok(removed)
     }
 }
@@ -99,6 +161,92 @@ impl<
         V: Send + Sync + DeserializeOwned + Serialize,
     > MetadataSettingsManager<K, V>
 {
+    /// Keeps fetched metadata for `ttl` instead of [`DEFAULT_METADATA_TTL`]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.fetched_at.is_none_or(|at| at.elapsed() >= self.ttl)
+    }
+
+    /// Whether reads for `domain` can be served from memory
+    fn is_cached(&self, domain: &str) -> bool {
+        !self.is_stale() && self.fetched_from.as_deref() == Some(domain)
+    }
+
+    /// Makes the next read refetch the metadata
+    pub fn invalidate(&mut self) {
+        self.fetched_at = None;
+    }
+
+    /// Refetches the metadata right away
+    pub async fn refresh(&mut self, domain: &str) -> Result<(), MetadataSettingsManagerError> {
+        self.remote_get(Some(domain)).await?;
+        Ok(())
+    }
+
+    /**
+    applies all sets and deletes of the batch in one request, and to the cache once saleor accepts
+    them, if the cache holds the metadata of `domain`
+    */
+    pub async fn apply(
+        &mut self,
+        batch: MetadataBatch<K, V>,
+        domain: &str,
+    ) -> Result<(), MetadataSettingsManagerError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let mut input = vec![];
+        let mut deleted = vec![];
+        for (key, value) in &batch.ops {
+            match value {
+                Some(value) => input.push(MetadataInput {
+                    key: key.to_string(),
+                    value: serde_json::to_string(value)?,
+                }),
+                None => deleted.push(key.to_string()),
+            }
+        }
+
+        let app_id = cynic::Id::new(&self.auth_data.app_id);
+        let operation = BatchAppMetadata::build(BatchAppMetadataVariables {
+            app_id: &app_id,
+            input,
+            keys: deleted.iter().map(|k| k.as_str()).collect(),
+        });
+        match surf::post(domain)
+            .header("authorization-bearer", &self.auth_data.token)
+            .run_graphql(operation)
+            .await
+        {
+            Ok(response) => {
+                if let Some(res_errors) = response.errors {
+                    if let Some(e) = res_errors.first().cloned() {
+                        return Err(e.into());
+                    }
+                }
+            }
+            Err(e) => {
+                debug!("{:?}", e);
+                return Err(MetadataSettingsManagerError::HttpRequestError(e));
+            }
+        }
+
+        if self.fetched_from.as_deref() != Some(domain) {
+            return Ok(());
+        }
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => self.metadata.insert(key, value),
+                None => self.metadata.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
     pub fn to_metadata_vec(&self) -> Vec<MetadataInput> {
         self.metadata
             .0
@@ -143,7 +291,8 @@ impl<
     }
 
     /**
-    sets metadata (not the private ones) in saleor app
+    sets all cached metadata (not the private ones) in saleor app, prefer [`Self::apply`] to only
+    send what changed
     */
     pub async fn remote_set(
         &mut self,
@@ -208,7 +357,9 @@ impl<
                         self.metadata = Metadata(hashmap);
                     }
                 }
-                self.metadata = Metadata(HashMap::new());
+                self.fetched_at = Some(Instant::now());
+                self.fetched_from =
+                    Some(api_url.unwrap_or(&self.auth_data.saleor_api_url).to_owned());
                 Ok(self)
             }
             Err(e) => {
@@ -226,39 +377,67 @@ impl<
         let mut mngr = Self {
             auth_data,
             metadata: Metadata(HashMap::new()),
+            ttl: DEFAULT_METADATA_TTL,
+            fetched_at: None,
+            fetched_from: None,
         };
         mngr.remote_get(None).await?;
         Ok(mngr)
     }
 }
 
-// #[cfg(test)]
-// mod metadata_tests {
-//     use crate::AuthData;
-//
-//     use super::MetadataSettingsManager;
-//
-//     #[derive(Eq, PartialEq, Hash)]
-//     pub enum Key {
-//         Cool,
-//         Stuff,
-//     }
-//
-//     #[derive(Eq, PartialEq)]
-//     pub enum Val {
-//         Cool(String),
-//         Stuff(String),
-//     }
-//
-//     async fn can_create() {
-//         let sett: MetadataSettingsManager<Key, Val> = MetadataSettingsManager::new(AuthData {
-//             domain: Some("".into()),
-//             token: "".into(),
-//             saleor_api_url: "".into(),
-//             app_id: "".into(),
-//             jwks: None,
-//         })
-//         .await
-//         .unwrap();
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> MetadataSettingsManager<String, String> {
+        MetadataSettingsManager {
+            metadata: Metadata(HashMap::new()),
+            auth_data: AuthData {
+                domain: None,
+                token: "".into(),
+                saleor_api_url: "".into(),
+                app_id: "".into(),
+                jwks: None,
+            },
+            ttl: DEFAULT_METADATA_TTL,
+            fetched_at: None,
+            fetched_from: None,
+        }
+    }
+
+    #[test]
+    fn last_batch_operation_wins() {
+        let batch = MetadataBatch::new()
+            .set("a", 1)
+            .delete("a")
+            .delete("b")
+            .set("b", 2);
+        assert_eq!(batch.ops.get("a"), Some(&None));
+        assert_eq!(batch.ops.get("b"), Some(&Some(2)));
+    }
+
+    #[test]
+    fn cache_goes_stale() {
+        let mut mngr = manager();
+        assert!(mngr.is_stale());
+
+        mngr.fetched_at = Some(Instant::now());
+        assert!(!mngr.is_stale());
+        mngr.invalidate();
+        assert!(mngr.is_stale());
+
+        let mut mngr = manager().with_ttl(Duration::ZERO);
+        mngr.fetched_at = Some(Instant::now());
+        assert!(mngr.is_stale());
+    }
+
+    #[test]
+    fn cache_is_per_domain() {
+        let mut mngr = manager();
+        mngr.fetched_at = Some(Instant::now());
+        mngr.fetched_from = Some("https://one.example.com/graphql/".into());
+        assert!(mngr.is_cached("https://one.example.com/graphql/"));
+        assert!(!mngr.is_cached("https://two.example.com/graphql/"));
+    }
+}
@@ -1,4 +1,6 @@
+pub mod channel_config;
 pub mod encrypted_metadata;
+pub mod instance_config;
 pub mod metadata;
 pub mod queries;
 
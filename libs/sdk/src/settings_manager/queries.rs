@@ -82,6 +82,22 @@ pub struct ObjectWithMetadata2 {
     pub metadata: Vec<MetadataItem>,
 }
 
+#[derive(cynic::QueryVariables, Debug)]
+pub struct BatchAppMetadataVariables<'a> {
+    pub app_id: &'a cynic::Id,
+    pub input: Vec<MetadataInput>,
+    pub keys: Vec<&'a str>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Mutation", variables = "BatchAppMetadataVariables")]
+pub struct BatchAppMetadata {
+    #[arguments(id: $app_id, input: $input)]
+    pub update_metadata: Option<UpdateMetadata>,
+    #[arguments(id: $app_id, keys: $keys)]
+    pub delete_metadata: Option<DeleteMetadata>,
+}
+
 #[derive(cynic::QueryFragment, Debug)]
 #[cynic(
     graphql_type = "Mutation",
@@ -189,6 +205,25 @@ mutation DeleteAppMetadata($app_id: ID!, $keys: [String!]!) {
   }
 }
 
+mutation BatchAppMetadata($app_id: ID!, $input: [MetadataInput!]!, $keys: [String!]!) {
+  updateMetadata(id: $app_id, input: $input) {
+    item {
+      metadata {
+        key
+        value
+      }
+    }
+  }
+  deleteMetadata(id: $app_id, keys: $keys) {
+    item {
+      privateMetadata {
+        key
+        value
+      }
+    }
+  }
+}
+
 mutation DeleteAppPrivateMetadata($app_id: ID!, $keys: [String!]!) {
   deletePrivateMetadata(id: $app_id, keys: $keys) {
     item {
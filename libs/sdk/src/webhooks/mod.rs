@@ -2,7 +2,9 @@ pub mod sync_response;
 #[cfg(feature = "webhook_utils")]
 pub mod utils;
 
-use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use strum_macros::{AsRefStr, EnumString};
 
 use crate::config::Config;
@@ -187,6 +189,49 @@ pub enum SyncWebhookEventType {
     PaymentMethodInitializeTokenizationSession,
     PaymentMethodProcessTokenizationSession,
 }
+
+/**
+ Event of the `saleor-event` header, which can be either sync or async. De/serializes as the
+ snake_case header value, unlike the event types themselves.
+*/
+#[derive(Debug, Clone)]
+pub enum EitherWebhookType {
+    Sync(SyncWebhookEventType),
+    Async(AsyncWebhookEventType),
+}
+
+impl AsRef<str> for EitherWebhookType {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Sync(s) => s.as_ref(),
+            Self::Async(a) => a.as_ref(),
+        }
+    }
+}
+
+impl FromStr for EitherWebhookType {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match SyncWebhookEventType::from_str(s) {
+            Ok(s) => Ok(Self::Sync(s)),
+            Err(_) => Ok(Self::Async(AsyncWebhookEventType::from_str(s)?)),
+        }
+    }
+}
+
+impl Serialize for EitherWebhookType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_ref())
+    }
+}
+
+impl<'de> Deserialize<'de> for EitherWebhookType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let event = String::deserialize(deserializer)?;
+        event.parse().map_err(serde::de::Error::custom)
+    }
+}
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct WebhookManifest {
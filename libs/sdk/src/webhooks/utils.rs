@@ -1,14 +1,9 @@
-use crate::headers::SALEOR_EVENT_HEADER;
+use crate::headers::{get_saleor_header, SALEOR_EVENT_HEADER};
 use http::{header::ToStrError, HeaderMap};
 
+pub use super::EitherWebhookType;
 use super::{AsyncWebhookEventType, SyncWebhookEventType};
 
-#[derive(Debug, Clone)]
-pub enum EitherWebhookType {
-    Sync(SyncWebhookEventType),
-    Async(AsyncWebhookEventType),
-}
-
 #[derive(thiserror::Error, Debug)]
 pub enum GetWebhookTypeError {
     #[error("Failed parsing webhook type, {0}")]
@@ -22,7 +17,7 @@ pub enum GetWebhookTypeError {
 pub fn get_webhook_event_type(
     header: &HeaderMap,
 ) -> Result<EitherWebhookType, GetWebhookTypeError> {
-    if let Some(event) = header.get(SALEOR_EVENT_HEADER) {
+    if let Some(event) = get_saleor_header(header, SALEOR_EVENT_HEADER) {
         let event = event.to_str()?;
         let s_event: Result<SyncWebhookEventType, _> = SyncWebhookEventType::try_from(event);
         let a_event: Result<AsyncWebhookEventType, _> = AsyncWebhookEventType::try_from(event);